@@ -23,6 +23,57 @@ fn math_sin(bencher: &mut Bencher) {
     });
 }
 
+// Wave, Oscillator::Exact
+#[bench]
+fn wave_exact(bencher: &mut Bencher) {
+    let mut wave_buffer: Vec<SampleCalc> = vec![0.0; BENCH_BUFFER_SIZE];
+    let frequency_buffer: Vec<SampleCalc> = vec![440.0; BENCH_BUFFER_SIZE];
+    let mut wave = Wave::new(BENCH_SAMPLE_RATE, 0).unwrap();
+
+    bencher.iter(|| {
+        wave.get(&frequency_buffer, &mut wave_buffer).unwrap();
+    });
+}
+
+// Wave, Oscillator::Rotation
+#[bench]
+fn wave_rotation(bencher: &mut Bencher) {
+    let mut wave_buffer: Vec<SampleCalc> = vec![0.0; BENCH_BUFFER_SIZE];
+    let frequency_buffer: Vec<SampleCalc> = vec![440.0; BENCH_BUFFER_SIZE];
+    let mut wave = Wave::new(BENCH_SAMPLE_RATE, 0).unwrap();
+
+    bencher.iter(|| {
+        wave.get_rotation(&frequency_buffer, &mut wave_buffer).unwrap();
+    });
+}
+
+// Wave, Oscillator::Table
+#[bench]
+fn wave_table(bencher: &mut Bencher) {
+    let mut wave_buffer: Vec<SampleCalc> = vec![0.0; BENCH_BUFFER_SIZE];
+    let frequency_buffer: Vec<SampleCalc> = vec![440.0; BENCH_BUFFER_SIZE];
+    let mut wave = Wave::new(BENCH_SAMPLE_RATE, 0).unwrap();
+    let sine_table = SineTable::new();
+
+    bencher.iter(|| {
+        wave.get_table(&sine_table, &frequency_buffer, &mut wave_buffer).unwrap();
+    });
+}
+
+// BandLimitedWave, Waveform::Saw
+#[bench]
+fn bandlimited_saw(bencher: &mut Bencher) {
+    let mut wave_buffer: Vec<SampleCalc> = vec![0.0; BENCH_BUFFER_SIZE];
+    let frequency_buffer: Vec<SampleCalc> = vec![440.0; BENCH_BUFFER_SIZE];
+    let amplitude = AmplitudeConst::new(BENCH_SAMPLE_RATE).unwrap();
+    let wave = BandLimitedWave::new(BENCH_SAMPLE_RATE, Waveform::Saw, Rc::new(amplitude))
+        .unwrap();
+
+    bencher.iter(|| {
+        wave.get(&frequency_buffer, &mut wave_buffer).unwrap();
+    });
+}
+
 // FrequencyConst
 #[bench]
 fn freqconst(bencher: &mut Bencher) {
@@ -130,6 +181,68 @@ fn timbre_freqconst_ampdec_overtones16(bencher: &mut Bencher) {
     });
 }
 
+// FrequencyConst, Timbre{ AmplitudeDecayExpOvertones with 16 overtones }, Oscillator::Rotation
+#[bench]
+fn timbre_freqconst_ampdec_overtones16_rotation(bencher: &mut Bencher) {
+    let mut generator_buffer: Vec<SampleCalc> = vec![0.0; BENCH_BUFFER_SIZE];
+    let mut frequency_buffer: Vec<SampleCalc> = vec![440.0; BENCH_BUFFER_SIZE];
+    let mut time: SampleCalc = 0.0;
+    let frequency = FrequencyConst::new(440.0).unwrap();
+    let amplitude = {
+        let overtones_amplitude: Vec<SampleCalc> = vec![10.0, 1.0, 1.0, 0.95, 0.9, 0.9, 0.86,
+                                                        0.83, 0.80, 0.78, 0.76, 0.74, 0.73, 0.72,
+                                                        0.71, 0.70];
+        let overtones_dec_rate: Vec<SampleCalc> = vec![1.0, 0.6, 0.5, 0.4, 0.35, 0.3, 0.28, 0.26,
+                                                       0.25, 0.24, 0.23, 0.22, 0.21, 0.1, 0.2, 0.2];
+        AmplitudeDecayExpOvertones::new(BENCH_SAMPLE_RATE,
+                                        15,
+                                        &overtones_amplitude,
+                                        &overtones_dec_rate)
+            .unwrap()
+    };
+    let mut timbre = Timbre::new(BENCH_SAMPLE_RATE, BENCH_BUFFER_SIZE, Rc::new(amplitude), 16)
+        .unwrap();
+    timbre.set_oscillator(Oscillator::Rotation);
+
+    bencher.iter(|| {
+        frequency.get(time, None, &mut frequency_buffer).unwrap();
+        timbre.get(&frequency_buffer, &mut generator_buffer).unwrap();
+        time += BENCH_BUFFER_TIME;
+        // test::black_box(&mut generator_buffer);
+    });
+}
+
+// FrequencyConst, Timbre{ AmplitudeDecayExpOvertones with 16 overtones }, Oscillator::Table
+#[bench]
+fn timbre_freqconst_ampdec_overtones16_table(bencher: &mut Bencher) {
+    let mut generator_buffer: Vec<SampleCalc> = vec![0.0; BENCH_BUFFER_SIZE];
+    let mut frequency_buffer: Vec<SampleCalc> = vec![440.0; BENCH_BUFFER_SIZE];
+    let mut time: SampleCalc = 0.0;
+    let frequency = FrequencyConst::new(440.0).unwrap();
+    let amplitude = {
+        let overtones_amplitude: Vec<SampleCalc> = vec![10.0, 1.0, 1.0, 0.95, 0.9, 0.9, 0.86,
+                                                        0.83, 0.80, 0.78, 0.76, 0.74, 0.73, 0.72,
+                                                        0.71, 0.70];
+        let overtones_dec_rate: Vec<SampleCalc> = vec![1.0, 0.6, 0.5, 0.4, 0.35, 0.3, 0.28, 0.26,
+                                                       0.25, 0.24, 0.23, 0.22, 0.21, 0.1, 0.2, 0.2];
+        AmplitudeDecayExpOvertones::new(BENCH_SAMPLE_RATE,
+                                        15,
+                                        &overtones_amplitude,
+                                        &overtones_dec_rate)
+            .unwrap()
+    };
+    let mut timbre = Timbre::new(BENCH_SAMPLE_RATE, BENCH_BUFFER_SIZE, Rc::new(amplitude), 16)
+        .unwrap();
+    timbre.set_oscillator(Oscillator::Table);
+
+    bencher.iter(|| {
+        frequency.get(time, None, &mut frequency_buffer).unwrap();
+        timbre.get(&frequency_buffer, &mut generator_buffer).unwrap();
+        time += BENCH_BUFFER_TIME;
+        // test::black_box(&mut generator_buffer);
+    });
+}
+
 // FrequencyConst, Timbre{ AmplitudeDecayExpOvertones with 4 overtones }
 #[bench]
 fn timbre_freqconst_ampdec_overtones4(bencher: &mut Bencher) {
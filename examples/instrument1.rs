@@ -4,6 +4,7 @@
 //!
 //! The keys from <kbd>Q</kbd> to <kbd>O</kbd> changes the frequency to be higher,
 //! the keys from <kbd>A</kbd> to <kbd>L</kbd> changes the frequency to be lower.
+//! <kbd>Space</kbd> switches the glide (portamento) between the frequencies on and off.
 /// Other keys play the previous frequency. To quit press <kbd>Esc</kbd>.
 
 extern crate scaleless_music;
@@ -18,13 +19,23 @@ use scaleless_music::sound::*;
 use std::rc::Rc;
 
 /// Commands of the messages from the UI thread to the playback thread.
+#[derive(Copy, Clone)]
 pub enum GeneratorCommand {
     /// Mute
     Mute,
     /// Keyboard event
     Keypress { key: keyboard::Key },
     /// Multiply frequency by a rational number
-    FrequencyMultiple { numerator: u16, denominator: u16 },
+    FrequencyMultiple {
+        numerator: IntervalTerm,
+        denominator: IntervalTerm,
+    },
+}
+
+impl Default for GeneratorCommand {
+    fn default() -> GeneratorCommand {
+        GeneratorCommand::Mute
+    }
 }
 
 pub struct InstrumentBasic {
@@ -32,6 +43,9 @@ pub struct InstrumentBasic {
     timbre1: Timbre,
     frequency1: Rc<FrequencyConst>,
     frequency1_buffer: Vec<SampleCalc>,
+    /// The glide between the frequencies, it is off by default.
+    portamento: Portamento,
+    glide: bool,
     time: SampleCalc,
 }
 
@@ -52,18 +66,25 @@ impl InstrumentBasic {
                                             &overtones_half_life)?
         };
         let timbre1 = Timbre::new(sample_rate, BUFFER_SIZE_DEFAULT, Rc::new(amplitude), 4)?;
+        let portamento =
+            Portamento::new(sample_rate, TimingOption::TimeConst(0.05), GlideCurve::Exponential)?;
         Ok(InstrumentBasic {
             sample_rate: sample_rate,
             timbre1: timbre1,
             frequency1: frequency1,
             frequency1_buffer: vec![1.0; BUFFER_SIZE_DEFAULT],
+            portamento: portamento,
+            glide: false,
             time: 0.0,
         })
     }
 
     /// Change frequency in harmony with the previous value
     #[allow(dead_code)]
-    pub fn change_frequency(&mut self, numerator: u16, denominator: u16) -> SoundResult<()> {
+    pub fn change_frequency(&mut self,
+                            numerator: IntervalTerm,
+                            denominator: IntervalTerm)
+                            -> SoundResult<()> {
         let interval = Interval::new(numerator, denominator)?;
         self.frequency1.change(interval)?;
         self.time = 0.0;
@@ -71,6 +92,18 @@ impl InstrumentBasic {
         println!("{}  {}", interval, interval.get_name());
         Ok(())
     }
+
+    /// Switches the glide between the frequencies on or off.
+    pub fn toggle_glide(&mut self) -> SoundResult<()> {
+        self.glide = !self.glide;
+        if self.glide {
+            self.frequency1.set_portamento(Some(self.portamento.clone()))?;
+        } else {
+            self.frequency1.set_portamento(None)?;
+        }
+        println!("glide: {}", if self.glide { "on" } else { "off" });
+        Ok(())
+    }
 }
 // TODO: -unwrap()
 impl SoundGenerator for InstrumentBasic {
@@ -105,6 +138,7 @@ impl SoundGenerator for InstrumentBasic {
                     Key::J => self.change_frequency(3, 5),
                     Key::K => self.change_frequency(4, 7),
                     Key::L => self.change_frequency(1, 2),
+                    Key::Space => self.toggle_glide(),
                     _ => self.change_frequency(1, 1),
                 };
             }
@@ -135,12 +169,14 @@ fn main() {
     sound.start().expect("sound.start() shouldn't fail.");
     println!("\n\nThe keys from [Q] to [O] changes the frequency to be higher,");
     println!("the keys from [A] to [L] changes the frequency to be lower.");
+    println!("[Space] switches the glide on and off.");
     println!("Other keys play the previous frequency. To quit press [Esc].");
     while let Some(event) = window.next() {
         if let Some(button) = event.press_args() {
             if let Button::Keyboard(key) = button {
-                sound.send_command(GeneratorCommand::Keypress { key: key })
-                    .expect("send_command failed.");
+                if let Err(err) = sound.send_command(GeneratorCommand::Keypress { key: key }) {
+                    println!("send_command failed: {}", err);
+                }
             } else {
                 println!("Pressed {:?}", button);
             }
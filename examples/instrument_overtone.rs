@@ -1,6 +1,7 @@
 //! This example is an overtone instrument. Keyboard is the user input.
 //! The tone is a very simple function (nothing like a real instrument). It's purpose is
-//! only testing.
+//! only testing. <kbd>Space</kbd> switches the glide (portamento) between the resonances on
+//! and off.
 //! See also: [Overtone flute](https://en.wikipedia.org/wiki/Overtone_flute)
 extern crate scaleless_music;
 
@@ -14,13 +15,23 @@ use scaleless_music::sound::*;
 use std::rc::Rc;
 
 /// Commands of the messages from the UI thread to the playback thread.
+#[derive(Copy, Clone)]
 pub enum GeneratorCommand {
     /// Mute
     Mute,
     /// Keyboard event
     Keypress { key: keyboard::Key },
     /// Multiply frequency by a rational number
-    FrequencyMultiple { numerator: u16, denominator: u16 },
+    FrequencyMultiple {
+        numerator: IntervalTerm,
+        denominator: IntervalTerm,
+    },
+}
+
+impl Default for GeneratorCommand {
+    fn default() -> GeneratorCommand {
+        GeneratorCommand::Mute
+    }
 }
 
 pub struct InstrumentBasic {
@@ -28,6 +39,9 @@ pub struct InstrumentBasic {
     frequency1: Rc<FrequencyConst>,
     frequency1_buffer: Vec<SampleCalc>,
     mixer: Rc<Mixer>,
+    /// The glide between the resonances, it is off by default.
+    portamento: Portamento,
+    glide: bool,
     time: SampleCalc,
 }
 
@@ -60,19 +74,26 @@ impl InstrumentBasic {
         let mixer = Rc::new(Mixer::new(sample_rate, BUFFER_SIZE_DEFAULT)?);
         mixer.add(Interval::new(1, 1)?, timbre1, 4.0)?;
         mixer.add(Interval::new(1, 1)?, timbre2, 1.0)?;
+        let portamento =
+            Portamento::new(sample_rate, TimingOption::TimeConst(0.05), GlideCurve::Exponential)?;
 
         Ok(InstrumentBasic {
             sample_rate: sample_rate,
             frequency1: frequency1,
             frequency1_buffer: vec![1.0; BUFFER_SIZE_DEFAULT],
             mixer: mixer,
+            portamento: portamento,
+            glide: false,
             time: 0.0,
         })
     }
 
     /// Change frequency in harmony with the previous value
     #[allow(dead_code)]
-    pub fn change_frequency(&mut self, numerator: u16, denominator: u16) -> SoundResult<()> {
+    pub fn change_frequency(&mut self,
+                            numerator: IntervalTerm,
+                            denominator: IntervalTerm)
+                            -> SoundResult<()> {
         let interval = Interval::new(numerator, denominator)?;
         self.mixer.set_interval(0, interval)?;
         self.time = 0.0;
@@ -80,6 +101,18 @@ impl InstrumentBasic {
         println!("{}", interval);
         Ok(())
     }
+
+    /// Switches the glide between the resonances on or off.
+    pub fn toggle_glide(&mut self) -> SoundResult<()> {
+        self.glide = !self.glide;
+        if self.glide {
+            self.mixer.set_portamento(0, Some(self.portamento.clone()))?;
+        } else {
+            self.mixer.set_portamento(0, None)?;
+        }
+        println!("glide: {}", if self.glide { "on" } else { "off" });
+        Ok(())
+    }
 }
 // TODO: -unwrap()
 impl SoundGenerator for InstrumentBasic {
@@ -113,6 +146,7 @@ impl SoundGenerator for InstrumentBasic {
                     Key::J => self.change_frequency(7, 1),
                     Key::K => self.change_frequency(8, 1),
                     Key::L => self.change_frequency(9, 1),
+                    Key::Space => self.toggle_glide(),
                     Key::A | _ => self.change_frequency(1, 1),
                     // _ => self.change_frequency(1, 1),
                 };
@@ -145,12 +179,13 @@ fn main() {
     sound.start().expect("sound.start() shouldn't fail.");
     println!("\n\nThe keys from [Q] to [P] produces half wave resonances,");
     println!("the keys from [A] to [L] makes full wave resonances.");
-    println!("To quit press [Esc].");
+    println!("[Space] switches the glide on and off. To quit press [Esc].");
     while let Some(event) = window.next() {
         if let Some(button) = event.press_args() {
             if let Button::Keyboard(key) = button {
-                sound.send_command(GeneratorCommand::Keypress { key: key })
-                    .expect("send_command failed.");
+                if let Err(err) = sound.send_command(GeneratorCommand::Keypress { key: key }) {
+                    println!("send_command failed: {}", err);
+                }
             } else {
                 println!("Pressed {:?}", button);
             }
@@ -0,0 +1,463 @@
+use num::*;
+use sound::*;
+
+/// The default maximal denominator of the rational approximations.
+pub const APPROXIMATION_DENOMINATOR_MAX: u64 = 65535;
+
+/// A candidate of the rational approximation.
+#[derive(Debug, Copy, Clone)]
+pub struct Approximation {
+    interval: Interval,
+    /// The difference from the approximated ratio (in cents).
+    error: SampleCalc,
+}
+
+impl Approximation {
+    /// Provides the approximating interval.
+    pub fn get_interval(&self) -> Interval {
+        self.interval
+    }
+
+    /// Provides the difference of the interval from the approximated ratio (in cents). It is
+    /// positive if the interval is larger.
+    pub fn get_error(&self) -> SampleCalc {
+        self.error
+    }
+}
+
+/// Searches the simple just intervals near a real ratio (e.g. a measured frequency ratio or a
+/// step of a tempered scale).
+///
+/// The candidates are ranked from the simplest to the closest, each one is closer to the ratio
+/// than all the simpler ones (with lower Tenney height). Without prime limit they are found by
+/// walking down the [Stern-Brocot tree](https://en.wikipedia.org/wiki/Stern%E2%80%93Brocot_tree)
+/// along the ratio's continued fraction: each run of semiconvergents is jumped over, only it's
+/// simplest improving fraction, it's simplest fraction within the tolerance and it's last
+/// fraction (the next convergent, or the closest one within the limits) are candidates. So the
+/// simplest interval within the tolerance is always found. With prime limit the fractions of
+/// the prime limited numbers are searched.
+#[derive(Debug, Clone)]
+pub struct RationalApproximation {
+    ratio: f64,
+    denominator_max: u64,
+    prime_limit: Option<u64>,
+    /// The maximal error (in cents).
+    tolerance: Option<SampleCalc>,
+}
+
+impl RationalApproximation {
+    /// custom constructor, without prime limit and tolerance, the maximal denominator is
+    /// `APPROXIMATION_DENOMINATOR_MAX`.
+    pub fn new(ratio: SampleCalc) -> SoundResult<RationalApproximation> {
+        if !(ratio > 0.0) || !ratio.is_finite() {
+            return Err(Error::RateInvalid);
+        }
+        Ok(RationalApproximation {
+            ratio: ratio as f64,
+            denominator_max: APPROXIMATION_DENOMINATOR_MAX,
+            prime_limit: None,
+            tolerance: None,
+        })
+    }
+
+    /// Constructor for the approximation of an interval given by it's size in cents.
+    pub fn from_cents(cents: SampleCalc) -> SoundResult<RationalApproximation> {
+        if !cents.is_finite() {
+            return Err(Error::RateInvalid);
+        }
+        let mut approximation = RationalApproximation::new(1.0)?;
+        approximation.ratio = (cents as f64 / 1200.0).exp2();
+        Ok(approximation)
+    }
+
+    /// Sets the maximal denominator.
+    pub fn set_denominator_max(&mut self, denominator_max: u64) -> SoundResult<&mut Self> {
+        if denominator_max == 0 {
+            return Err(Error::DenominatorInvalid);
+        }
+        self.denominator_max = denominator_max;
+        Ok(self)
+    }
+
+    /// Sets the prime limit, only the intervals without larger prime factors are accepted.
+    pub fn set_prime_limit(&mut self, prime_limit: u64) -> SoundResult<&mut Self> {
+        if prime_limit < 2 {
+            return Err(Error::PrimeLimitInvalid);
+        }
+        self.prime_limit = Some(prime_limit);
+        Ok(self)
+    }
+
+    /// Sets the maximal error (in cents), only the candidates within it are accepted.
+    pub fn set_tolerance(&mut self, tolerance: SampleCalc) -> SoundResult<&mut Self> {
+        if !(tolerance > 0.0) {
+            return Err(Error::ToleranceInvalid);
+        }
+        self.tolerance = Some(tolerance);
+        Ok(self)
+    }
+
+    /// Provides the candidates, from the simplest to the closest.
+    pub fn get_candidates(&self) -> SoundResult<Vec<Approximation>> {
+        let mut candidates = Vec::new();
+        let mut error_min = ::std::f64::INFINITY;
+        {
+            let mut add = |numerator: u64, denominator: u64| -> SoundResult<()> {
+                let error = self.get_error(numerator, denominator);
+                if error.abs() >= error_min {
+                    return Ok(());
+                }
+                error_min = error.abs();
+                if let Some(tolerance) = self.tolerance {
+                    if error.abs() > tolerance as f64 {
+                        return Ok(());
+                    }
+                }
+                let numerator: IntervalTerm = NumCast::from(numerator).ok_or(Error::Overflow)?;
+                let denominator: IntervalTerm = NumCast::from(denominator)
+                    .ok_or(Error::Overflow)?;
+                candidates.push(Approximation {
+                    interval: Interval::new(numerator, denominator)?,
+                    error: error as SampleCalc,
+                });
+                Ok(())
+            };
+            match self.prime_limit {
+                None => self.walk_stern_brocot(&mut add)?,
+                Some(prime_limit) => {
+                    for (numerator, denominator) in self.get_prime_limited(prime_limit) {
+                        add(numerator, denominator)?;
+                    }
+                }
+            }
+        }
+        Ok(candidates)
+    }
+
+    /// Provides the best approximation: the simplest one within the tolerance, or the closest
+    /// one if there is no tolerance. Returns `Error::ApproximationNotFound` if no interval
+    /// satisfies the limits.
+    pub fn get_best(&self) -> SoundResult<Approximation> {
+        let candidates = self.get_candidates()?;
+        let best = match self.tolerance {
+            Some(_) => candidates.first(),
+            None => candidates.last(),
+        };
+        best.cloned().ok_or(Error::ApproximationNotFound)
+    }
+
+    /// The error of the fraction in cents.
+    fn get_error(&self, numerator: u64, denominator: u64) -> f64 {
+        1200.0 * ((numerator as f64 / denominator as f64) / self.ratio).log2()
+    }
+
+    /// The largest numerator, which can be accepted.
+    fn get_numerator_max(&self) -> u64 {
+        let term_max: u64 = IntervalTerm::max_value().into();
+        let numerator_max = (self.ratio * self.denominator_max as f64).ceil() + 1.0;
+        if numerator_max < term_max as f64 {
+            numerator_max as u64
+        } else {
+            term_max
+        }
+    }
+
+    /// Walks down the Stern-Brocot tree towards the ratio, until the terms reach their limits.
+    /// The candidates are given to `add` in the order of their complexity.
+    fn walk_stern_brocot<F>(&self, add: &mut F) -> SoundResult<()>
+        where F: FnMut(u64, u64) -> SoundResult<()>
+    {
+        let term_max: u64 = IntervalTerm::max_value().into();
+        let mut walk = SternBrocotWalk::new(self.get_numerator_max(),
+                                            self.denominator_max.min(term_max));
+        let mut error_min = ::std::f64::INFINITY;
+        while let Some((numerator, denominator)) = walk.get_mediant() {
+            let mediant = numerator as f64 / denominator as f64;
+            if mediant == self.ratio {
+                return add(numerator, denominator);
+            }
+            let raising = mediant < self.ratio;
+            // the fractions of the run stay on the same side of the ratio
+            let run_end = (walk.get_steps_to(raising, self.ratio) - 1)
+                .min(walk.get_step_max(raising));
+            let get_target = |error: f64| if raising {
+                self.ratio / (error / 1200.0).exp2()
+            } else {
+                self.ratio * (error / 1200.0).exp2()
+            };
+            let mut steps = vec![walk.get_steps_to(raising, get_target(error_min)), run_end];
+            if let Some(tolerance) = self.tolerance {
+                steps.push(walk.get_steps_to(raising, get_target(tolerance as f64)));
+            }
+            steps.sort();
+            steps.dedup();
+            for &step in steps.iter().filter(|&&step| step <= run_end) {
+                if let Some((numerator, denominator)) = walk.get_fraction(raising, step) {
+                    error_min = error_min.min(self.get_error(numerator, denominator).abs());
+                    add(numerator, denominator)?;
+                }
+            }
+            walk.advance(raising, run_end);
+        }
+        Ok(())
+    }
+
+    /// Provides the fractions of prime limited numbers in the order of their complexity
+    /// (Tenney height). For each denominator only the nearest numerators are given (from both
+    /// sides of the ratio).
+    fn get_prime_limited(&self, prime_limit: u64) -> Vec<(u64, u64)> {
+        let term_max: u64 = IntervalTerm::max_value().into();
+        let numerator_max = self.get_numerator_max();
+        let numbers = get_smooth_numbers(prime_limit,
+                                         numerator_max.max(self.denominator_max.min(term_max)));
+        let mut fractions = Vec::new();
+        for &denominator in numbers.iter().take_while(|&&d| d <= self.denominator_max) {
+            let target = self.ratio * denominator as f64;
+            let index = match numbers.binary_search_by(|&n| {
+                (n as f64).partial_cmp(&target).unwrap_or(::std::cmp::Ordering::Less)
+            }) {
+                Ok(index) | Err(index) => index,
+            };
+            for &index in &[index.wrapping_sub(1), index] {
+                if let Some(&numerator) = numbers.get(index) {
+                    if numerator <= numerator_max && numerator.gcd(&denominator) == 1 {
+                        fractions.push((numerator, denominator));
+                    }
+                }
+            }
+        }
+        fractions.sort_by(|a, b| {
+            let height_a = a.0 as f64 * a.1 as f64;
+            let height_b = b.0 as f64 * b.1 as f64;
+            height_a.partial_cmp(&height_b).unwrap_or(::std::cmp::Ordering::Equal)
+        });
+        fractions
+    }
+}
+
+/// Walks down the Stern-Brocot tree between a lower and an upper bound. The runs of steps in
+/// the same direction can be jumped over, so the number of iterations is proportional to the
+/// length of the continued fraction of the target, not to the sum of it's terms. A step moves
+/// one bound towards the other: `lower + steps · upper` when raising, `upper + steps · lower`
+/// when lowering (adding the numerators and the denominators).
+#[derive(Debug, Clone)]
+pub(crate) struct SternBrocotWalk {
+    /// (numerator, denominator), starting from 0/1.
+    lower: (u64, u64),
+    /// (numerator, denominator), starting from 1/0.
+    upper: (u64, u64),
+    numerator_max: u64,
+    denominator_max: u64,
+}
+
+impl SternBrocotWalk {
+    /// custom constructor, the fractions are limited by `numerator_max` and `denominator_max`.
+    pub(crate) fn new(numerator_max: u64, denominator_max: u64) -> SternBrocotWalk {
+        SternBrocotWalk {
+            lower: (0, 1),
+            upper: (1, 0),
+            numerator_max: numerator_max,
+            denominator_max: denominator_max,
+        }
+    }
+
+    /// Provides the (moving, fixed) bounds of a step.
+    fn get_bounds(&self, raising: bool) -> ((u64, u64), (u64, u64)) {
+        if raising {
+            (self.lower, self.upper)
+        } else {
+            (self.upper, self.lower)
+        }
+    }
+
+    /// Provides the simplest fraction between the bounds, `None` if it exceeds the limits.
+    pub(crate) fn get_mediant(&self) -> Option<(u64, u64)> {
+        if self.get_step_max(true) == 0 {
+            return None;
+        }
+        self.get_fraction(true, 1)
+    }
+
+    /// Provides the fraction reached by `steps` steps, `None` on overflow.
+    pub(crate) fn get_fraction(&self, raising: bool, steps: u64) -> Option<(u64, u64)> {
+        let ((moving_n, moving_d), (fixed_n, fixed_d)) = self.get_bounds(raising);
+        let numerator = try_opt!(fixed_n.checked_mul(steps).and_then(|n| n.checked_add(moving_n)));
+        let denominator =
+            try_opt!(fixed_d.checked_mul(steps).and_then(|d| d.checked_add(moving_d)));
+        Some((numerator, denominator))
+    }
+
+    /// Provides the largest step count within the limits.
+    pub(crate) fn get_step_max(&self, raising: bool) -> u64 {
+        let ((moving_n, moving_d), (fixed_n, fixed_d)) = self.get_bounds(raising);
+        let mut step_max = u64::max_value();
+        if fixed_n > 0 {
+            step_max = step_max.min(self.numerator_max.saturating_sub(moving_n) / fixed_n);
+        }
+        if fixed_d > 0 {
+            step_max = step_max.min(self.denominator_max.saturating_sub(moving_d) / fixed_d);
+        }
+        step_max
+    }
+
+    /// Provides the smallest step count (at least one), with which the fraction reaches the
+    /// `target`: it is at least `target` when raising, at most `target` when lowering. The
+    /// limits are not considered, `u64::max_value()` means that it is never reached.
+    pub(crate) fn get_steps_to(&self, raising: bool, target: f64) -> u64 {
+        let ((moving_n, moving_d), (fixed_n, fixed_d)) = self.get_bounds(raising);
+        // raising: moving_n + steps · fixed_n >= target · (moving_d + steps · fixed_d)
+        let (distance, approach) = if raising {
+            (target * moving_d as f64 - moving_n as f64, fixed_n as f64 - target * fixed_d as f64)
+        } else {
+            (moving_n as f64 - target * moving_d as f64, target * fixed_d as f64 - fixed_n as f64)
+        };
+        if !(approach > 0.0) {
+            return u64::max_value();
+        }
+        let estimate = (distance / approach).ceil().max(1.0);
+        if !(estimate < u64::max_value() as f64) {
+            return u64::max_value();
+        }
+        // the estimate is corrected against the rounding errors
+        let mut steps = estimate as u64;
+        while (steps > 1) && self.reaches(raising, steps - 1, target) {
+            steps -= 1;
+        }
+        while !self.reaches(raising, steps, target) {
+            steps += 1;
+        }
+        steps
+    }
+
+    /// True, if the fraction reached by `steps` steps reaches the `target` (or overflows).
+    fn reaches(&self, raising: bool, steps: u64, target: f64) -> bool {
+        match self.get_fraction(raising, steps) {
+            Some((numerator, denominator)) => {
+                let ratio = numerator as f64 / denominator as f64;
+                if raising {
+                    ratio >= target
+                } else {
+                    ratio <= target
+                }
+            }
+            None => true,
+        }
+    }
+
+    /// Moves the bound by `steps` steps. The fraction must be within the limits.
+    pub(crate) fn advance(&mut self, raising: bool, steps: u64) {
+        if let Some(fraction) = self.get_fraction(raising, steps) {
+            if raising {
+                self.lower = fraction;
+            } else {
+                self.upper = fraction;
+            }
+        }
+    }
+}
+
+/// Provides the numbers up to `limit` without prime factors above `prime_limit`, in ascending
+/// order.
+fn get_smooth_numbers(prime_limit: u64, limit: u64) -> Vec<u64> {
+    let mut numbers = vec![1u64];
+    let mut prime = 2u64;
+    while prime <= prime_limit {
+        let mut index = 0;
+        while index < numbers.len() {
+            if let Some(number) = numbers[index].checked_mul(prime) {
+                if number <= limit {
+                    numbers.push(number);
+                }
+            }
+            index += 1;
+        }
+        prime += 1;
+        while !is_prime(prime) {
+            prime += 1;
+        }
+    }
+    numbers.sort();
+    numbers
+}
+
+#[cfg(test)]
+mod tests {
+    use sound::*;
+
+    fn get_terms(approximation: Approximation) -> (IntervalTerm, IntervalTerm) {
+        let interval = approximation.get_interval();
+        (interval.get_numerator(), interval.get_denominator())
+    }
+
+    fn get_all_terms(approximation: &RationalApproximation) -> Vec<(IntervalTerm, IntervalTerm)> {
+        approximation.get_candidates().unwrap().into_iter().map(get_terms).collect()
+    }
+
+    #[test]
+    fn continued_fraction() {
+        let approximation = RationalApproximation::new(::std::f32::consts::PI).unwrap();
+        // the semiconvergents 16:5 and 19:6 of the run from 13:4 to 22:7 are jumped over
+        let candidates = get_all_terms(&approximation);
+        assert_eq!(&candidates[..4], &[(1, 1), (3, 1), (13, 4), (22, 7)]);
+        assert!(candidates.contains(&(355, 113)));
+        let errors: Vec<_> = approximation.get_candidates()
+            .unwrap()
+            .iter()
+            .map(|candidate| candidate.get_error().abs())
+            .collect();
+        assert!(errors.windows(2).all(|pair| pair[1] < pair[0]));
+        let mut approximation = RationalApproximation::new(1.4983).unwrap();
+        let _ = approximation.set_tolerance(2.5).unwrap();
+        assert_eq!(get_terms(approximation.get_best().unwrap()), (3, 2));
+        let _ = approximation.set_denominator_max(1).unwrap();
+        assert!(approximation.get_best().is_err());
+    }
+
+    #[test]
+    fn simplest_within_tolerance() {
+        // the simplest interval within the tolerance is the same as the one of `from_cents`
+        for &(cents, tolerance) in &[(400.0, 14.0), (400.0, 1.0), (1.0, 0.5), (-702.0, 3.0),
+                                     (2400.0, 0.01), (3.0, 0.001)] {
+            let mut approximation = RationalApproximation::from_cents(cents).unwrap();
+            let _ = approximation.set_tolerance(tolerance).unwrap();
+            let best = approximation.get_best().unwrap().get_interval();
+            let interval = Interval::from_cents(cents, tolerance).unwrap();
+            assert_eq!((best.get_numerator(), best.get_denominator()),
+                       (interval.get_numerator(), interval.get_denominator()));
+            assert!((best.get_cents() - cents).abs() <= tolerance);
+        }
+        assert_eq!(get_terms(RationalApproximation::from_cents(400.0)
+                       .unwrap()
+                       .set_tolerance(14.0)
+                       .unwrap()
+                       .get_best()
+                       .unwrap()),
+                   (5, 4));
+    }
+
+    #[test]
+    fn prime_limit() {
+        let mut approximation = RationalApproximation::from_cents(400.0).unwrap();
+        let _ = approximation.set_prime_limit(5).unwrap().set_denominator_max(1000).unwrap();
+        let candidates = get_all_terms(&approximation);
+        assert!(candidates.contains(&(5, 4)));
+        assert!(!candidates.contains(&(14, 11)));
+        for (numerator, denominator) in candidates {
+            let interval = Interval::new(numerator, denominator).unwrap();
+            assert!(interval.get_prime_limit() <= 5);
+        }
+    }
+
+    #[test]
+    fn long_runs_are_jumped_over() {
+        // the continued fraction is [1; 8388608], walking it mediant by mediant would be slow
+        let approximation = RationalApproximation::new(1.0 + 1e-7).unwrap();
+        let candidates = get_all_terms(&approximation);
+        assert!(candidates.len() <= 3);
+        assert_eq!(candidates[0], (1, 1));
+        // the closest interval within the tolerance would need terms larger than `u16`
+        assert!(Interval::from_cents(0.0001, 0.00001).is_err());
+        let interval = Interval::from_cents(1.0, 0.1).unwrap();
+        assert!((interval.get_cents() - 1.0).abs() <= 0.1);
+    }
+}
@@ -0,0 +1,302 @@
+use sound::*;
+
+/// Headless backend for testing `SoundGenerator`s. It simulates the callbacks of a real-time
+/// backend on a virtual clock, and captures the output into memory.
+///
+/// Callbacks are driven exactly like in the PortAudio backend: all pending commands are
+/// processed, the timestamped ones at their exact frame positions (see `CommandScheduler`).
+pub struct SoundInterface<T> {
+    sample_rate: u32,
+    buffer_size: usize,
+    channel_count: u16,
+    generator: Box<SoundGenerator<Command = T>>,
+    channel_buffers: Vec<Vec<SampleCalc>>,
+    /// The frame counts of the callbacks, used cyclically.
+    frame_counts: Vec<usize>,
+    frame_count_index: usize,
+    /// Commands waiting for being processed by the generator. Its frame position is the
+    /// virtual clock.
+    scheduler: CommandScheduler<T>,
+    callback_count: usize,
+    /// The captured (interleaved) output.
+    output: Vec<SampleOutput>,
+}
+
+impl<T> SoundInterface<T> {
+    /// Creates a new virtual backend. By default every callback requests `buffer_size`
+    /// frames.
+    pub fn new(sample_rate: u32,
+               buffer_size: usize,
+               channel_count: u16,
+               generator: Box<SoundGenerator<Command = T>>)
+               -> SoundResult<SoundInterface<T>> {
+        if sample_rate == 0 {
+            return Err(Error::SampleRateInvalid);
+        }
+        if buffer_size == 0 {
+            return Err(Error::BufferSize);
+        }
+        if channel_count == 0 {
+            return Err(Error::ChannelInvalid);
+        }
+        Ok(SoundInterface {
+            sample_rate: sample_rate,
+            buffer_size: buffer_size,
+            channel_count: channel_count,
+            generator: generator,
+            channel_buffers: vec![vec![0.0; buffer_size]; channel_count as usize],
+            frame_counts: vec![buffer_size],
+            frame_count_index: 0,
+            scheduler: CommandScheduler::new(buffer_size,
+                                             channel_count as usize,
+                                             SCHEDULED_COMMAND_CAPACITY),
+            callback_count: 0,
+            output: Vec::new(),
+        })
+    }
+
+    /// Sets the frame counts of the successive callbacks. They are repeated cyclically, so
+    /// irregular callback sizes can be simulated. Each of them must be in the
+    /// `1...buffer_size` range.
+    pub fn set_frame_counts(&mut self, frame_counts: &[usize]) -> SoundResult<()> {
+        if frame_counts.is_empty() {
+            return Err(Error::SequenceEmpty);
+        }
+        for frame_count in frame_counts {
+            if (*frame_count == 0) || (*frame_count > self.buffer_size) {
+                return Err(Error::BufferSize);
+            }
+        }
+        self.frame_counts = frame_counts.to_vec();
+        self.frame_count_index = 0;
+        Ok(())
+    }
+
+    /// Does nothing, the stream is only advanced by `run_callback()`, `run_frames()` and
+    /// `run_time()`.
+    pub fn start(&mut self) -> BackendResult<()> {
+        Ok(())
+    }
+
+    /// Sends a command to the sound generator. It is processed at the beginning of the next
+    /// callback. At most `SCHEDULED_COMMAND_CAPACITY` commands can wait, above it
+    /// `BackendError::QueueFull` is returned.
+    pub fn send_command(&mut self, command: T) -> BackendResult<()> {
+        self.scheduler.push(TimedCommand::immediate(command))
+    }
+
+    /// Schedules a command on the virtual timeline. It is processed exactly at the given frame
+    /// position, the callback which contains it splits the rendering there.
+    pub fn send_command_at(&mut self, command: T, frame: u64) -> BackendResult<()> {
+        self.scheduler.push(TimedCommand::at(command, frame))
+    }
+
+    /// Simulates one callback. Returns the number of frames produced.
+    pub fn run_callback(&mut self) -> SoundResult<usize> {
+        let frames = self.frame_counts[self.frame_count_index];
+        self.frame_count_index = (self.frame_count_index + 1) % self.frame_counts.len();
+        self.scheduler.render(&mut *self.generator, frames, &mut self.channel_buffers)?;
+        for frame in 0..frames {
+            for channel in &self.channel_buffers {
+                self.output.push(channel[frame]);
+            }
+        }
+        self.callback_count += 1;
+        Ok(frames)
+    }
+
+    /// Simulates callbacks until at least `frame_count` more frames are produced.
+    pub fn run_frames(&mut self, frame_count: u64) -> SoundResult<()> {
+        let frame_end = self.scheduler.get_frame_position() + frame_count;
+        while self.scheduler.get_frame_position() < frame_end {
+            let _ = self.run_callback()?;
+        }
+        Ok(())
+    }
+
+    /// Simulates callbacks for (at least) the given duration, measured in seconds.
+    pub fn run_time(&mut self, duration: SampleCalc) -> SoundResult<()> {
+        if duration <= 0.0 {
+            return Err(Error::DurationInvalid);
+        }
+        self.run_frames((duration * self.sample_rate as SampleCalc).ceil() as u64)
+    }
+
+    /// Returns the captured output. Channels are interleaved.
+    pub fn get_output(&self) -> &[SampleOutput] {
+        &self.output
+    }
+
+    /// Returns the captured output of one channel.
+    pub fn get_channel_output(&self, channel: u16) -> SoundResult<Vec<SampleOutput>> {
+        if channel >= self.channel_count {
+            return Err(Error::ChannelInvalid);
+        }
+        Ok(self.output
+            .iter()
+            .skip(channel as usize)
+            .step_by(self.channel_count as usize)
+            .cloned()
+            .collect())
+    }
+
+    /// Clears the captured output. The virtual clock is not affected.
+    pub fn clear_output(&mut self) {
+        self.output.clear();
+    }
+
+    /// Returns the position of the virtual clock, measured in frames.
+    pub fn get_frame_position(&self) -> u64 {
+        self.scheduler.get_frame_position()
+    }
+
+    /// Returns the position of the virtual clock, measured in seconds.
+    pub fn get_time(&self) -> SampleCalc {
+        self.get_frame_position() as SampleCalc / self.sample_rate as SampleCalc
+    }
+
+    /// Returns the number of simulated callbacks.
+    pub fn get_callback_count(&self) -> usize {
+        self.callback_count
+    }
+
+    /// Returns the number of commands not yet processed by the generator.
+    pub fn get_pending_count(&self) -> usize {
+        self.scheduler.get_pending_count()
+    }
+
+    /// Returns the number of commands dropped because of a full schedule.
+    pub fn get_dropped_count(&self) -> usize {
+        self.scheduler.get_dropped_count()
+    }
+
+    /// Returns the sample rate of the sond output
+    pub fn get_sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Returns the channel count of the sond output
+    pub fn get_channel_count(&self) -> u16 {
+        self.channel_count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sound::*;
+    use super::*;
+
+    /// Outputs a constant level, which is set by the commands.
+    struct Level {
+        value: SampleCalc,
+    }
+
+    impl SoundGenerator for Level {
+        type Command = SampleCalc;
+
+        fn get_samples(&mut self, sample_count: usize, result: &mut Vec<SampleCalc>) {
+            for item in result.iter_mut().take(sample_count) {
+                *item = self.value;
+            }
+        }
+
+        fn process_command(&mut self, command: SampleCalc) {
+            self.value = command;
+        }
+    }
+
+    fn new_interface(buffer_size: usize, channel_count: u16) -> SoundInterface<SampleCalc> {
+        SoundInterface::new(48000, buffer_size, channel_count, Box::new(Level { value: 0.0 }))
+            .unwrap()
+    }
+
+    /// Returns the frame position of the first sample with the given value.
+    fn find(output: &[SampleOutput], value: SampleOutput) -> Option<usize> {
+        output.iter().position(|sample| *sample == value)
+    }
+
+    #[test]
+    fn immediate_command_at_next_callback() {
+        let mut sound_interface = new_interface(64, 1);
+        let _ = sound_interface.run_callback().unwrap();
+        sound_interface.send_command(1.0).unwrap();
+        assert_eq!(sound_interface.get_pending_count(), 1);
+        let _ = sound_interface.run_callback().unwrap();
+        assert_eq!(sound_interface.get_pending_count(), 0);
+        assert_eq!(find(sound_interface.get_output(), 1.0), Some(64));
+        assert_eq!(sound_interface.get_output().len(), 128);
+    }
+
+    #[test]
+    fn timestamped_command_is_sample_accurate() {
+        let mut sound_interface = new_interface(64, 1);
+        sound_interface.send_command_at(1.0, 100).unwrap();
+        sound_interface.send_command_at(0.5, 150).unwrap();
+        sound_interface.run_frames(256).unwrap();
+        let output = sound_interface.get_output();
+        assert_eq!(find(output, 1.0), Some(100));
+        assert_eq!(find(output, 0.5), Some(150));
+        assert!(output[150..].iter().all(|sample| *sample == 0.5));
+        assert_eq!(sound_interface.get_callback_count(), 4);
+    }
+
+    #[test]
+    fn irregular_frame_counts() {
+        let mut sound_interface = new_interface(64, 2);
+        sound_interface.set_frame_counts(&[10, 37, 64]).unwrap();
+        sound_interface.send_command_at(1.0, 50).unwrap();
+        sound_interface.run_frames(200).unwrap();
+        // 10 + 37 + 64 + 10 + 37 + 64 = 222
+        assert_eq!(sound_interface.get_frame_position(), 222);
+        assert_eq!(sound_interface.get_callback_count(), 6);
+        assert_eq!(sound_interface.get_output().len(), 222 * 2);
+        let left = sound_interface.get_channel_output(0).unwrap();
+        let right = sound_interface.get_channel_output(1).unwrap();
+        assert_eq!(find(&left, 1.0), Some(50));
+        assert_eq!(left, right);
+        assert!(sound_interface.get_channel_output(2).is_err());
+        assert!(sound_interface.set_frame_counts(&[65]).is_err());
+        assert!(sound_interface.set_frame_counts(&[]).is_err());
+    }
+
+    #[test]
+    fn commands_in_the_past_and_order() {
+        let mut sound_interface = new_interface(32, 1);
+        sound_interface.run_frames(64).unwrap();
+        sound_interface.clear_output();
+        // already in the past: applied at the beginning of the next callback
+        sound_interface.send_command_at(1.0, 10).unwrap();
+        // same frame: processed in the order of arrival
+        sound_interface.send_command_at(0.25, 80).unwrap();
+        sound_interface.send_command_at(0.75, 80).unwrap();
+        sound_interface.run_frames(32).unwrap();
+        let output = sound_interface.get_output();
+        assert_eq!(output[0], 1.0);
+        assert_eq!(output[15], 1.0);
+        assert_eq!(output[16], 0.75);
+        assert_eq!(find(output, 0.25), None);
+    }
+
+    #[test]
+    fn full_schedule_drops_commands() {
+        let mut sound_interface = new_interface(32, 1);
+        for frame in 0..SCHEDULED_COMMAND_CAPACITY {
+            sound_interface.send_command_at(1.0, 1000 - frame as u64).unwrap();
+        }
+        assert!(sound_interface.send_command(0.5).is_err());
+        assert_eq!(sound_interface.get_dropped_count(), 1);
+        assert_eq!(sound_interface.get_pending_count(), SCHEDULED_COMMAND_CAPACITY);
+        sound_interface.run_frames(2000).unwrap();
+        assert_eq!(sound_interface.get_pending_count(), 0);
+        assert_eq!(find(sound_interface.get_output(), 1.0), Some(1001 - 256));
+    }
+
+    #[test]
+    fn run_time() {
+        let mut sound_interface = new_interface(480, 1);
+        sound_interface.run_time(0.1).unwrap();
+        assert_eq!(sound_interface.get_frame_position(), 4800);
+        assert!((sound_interface.get_time() - 0.1).abs() < 1e-6);
+        assert!(sound_interface.run_time(0.0).is_err());
+    }
+}
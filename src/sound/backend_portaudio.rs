@@ -1,87 +1,192 @@
 use portaudio as pa;
 use sound::*;
-// use std::thread;
-// use std::sync::mpsc::channel;
-use std::sync::mpsc::Sender;
+use sound::command_queue::*;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
-/// This is a wrapper around the sound output backend
+/// The output stream, with the sample type of the selected `SampleFormat`.
+enum OutputStream {
+    Int16(pa::Stream<pa::NonBlocking, pa::stream::Output<i16>>),
+    Int32(pa::Stream<pa::NonBlocking, pa::stream::Output<i32>>),
+    Float32(pa::Stream<pa::NonBlocking, pa::stream::Output<f32>>),
+}
+
+impl OutputStream {
+    fn start(&mut self) -> Result<(), pa::Error> {
+        match *self {
+            OutputStream::Int16(ref mut stream) => stream.start(),
+            OutputStream::Int32(ref mut stream) => stream.start(),
+            OutputStream::Float32(ref mut stream) => stream.start(),
+        }
+    }
+    fn stop(&mut self) -> Result<(), pa::Error> {
+        match *self {
+            OutputStream::Int16(ref mut stream) => stream.stop(),
+            OutputStream::Int32(ref mut stream) => stream.stop(),
+            OutputStream::Float32(ref mut stream) => stream.stop(),
+        }
+    }
+    fn close(&mut self) -> Result<(), pa::Error> {
+        match *self {
+            OutputStream::Int16(ref mut stream) => stream.close(),
+            OutputStream::Int32(ref mut stream) => stream.close(),
+            OutputStream::Float32(ref mut stream) => stream.close(),
+        }
+    }
+    fn is_active(&self) -> Result<bool, pa::Error> {
+        match *self {
+            OutputStream::Int16(ref stream) => stream.is_active(),
+            OutputStream::Int32(ref stream) => stream.is_active(),
+            OutputStream::Float32(ref stream) => stream.is_active(),
+        }
+    }
+}
+
+/// Everything the callback owns. The buffers are allocated in advance.
+struct CallbackState<T> {
+    generator: Box<SoundGenerator<Command = T>>,
+    receiver: CommandReceiver<T>,
+    scheduler: CommandScheduler<T>,
+    channel_buffers: Vec<Vec<SampleCalc>>,
+    interleaved_buffer: Vec<SampleCalc>,
+    converter: SampleConverter,
+    frame_position: Arc<AtomicUsize>,
+}
+
+impl<T> CallbackState<T> {
+    fn render<S: OutputSample + Default>(&mut self, frames: usize, buffer: &mut [S]) {
+        self.receiver.receive_all(&mut self.scheduler);
+        let samples = frames * self.channel_buffers.len();
+        if self.scheduler
+            .render(&mut *self.generator, frames, &mut self.channel_buffers)
+            .and_then(|_| interleave(&self.channel_buffers, frames, &mut self.interleaved_buffer))
+            .and_then(|_| {
+                self.converter.convert(&self.interleaved_buffer[..samples], &mut buffer[..samples])
+            })
+            .is_err() {
+            for item in buffer.iter_mut() {
+                *item = S::default();
+            }
+        }
+        self.frame_position.store(self.scheduler.get_frame_position() as usize,
+                                  Ordering::Relaxed);
+    }
+}
+
+/// This is a wrapper around the sound output backend. Commands are passed to the callback
+/// through a bounded, preallocated queue (see `command_queue()`).
 pub struct SoundInterface<T: 'static> {
     sample_rate: u32,
     channel_count: u16,
-    stream: pa::Stream<pa::NonBlocking, pa::stream::Output<SampleOutput>>,
-    sender: Option<Sender<T>>, // receiver: Option<Receiver<T>,
+    stream: OutputStream,
+    sender: CommandSender<T>,
+    frame_position: Arc<AtomicUsize>,
+    clipped_count: Arc<AtomicUsize>,
 }
 
-impl<T> SoundInterface<T> {
-    /// Creates a new backend for sound playback.
-    /// At the moment all channels output the same sound.
+impl<T: Send> SoundInterface<T> {
+    /// Creates a new backend for sound playback. Each channel outputs the corresponding
+    /// channel of the generator. The output format is 32-bit float with hard clipping, the
+    /// command queue can hold `COMMAND_QUEUE_SIZE_DEFAULT` commands.
     pub fn new(sample_rate: u32,
                buffer_size: usize,
                channel_count: u16,
-               mut generator: Box<SoundGenerator<Command = T>>)
+               generator: Box<SoundGenerator<Command = T>>)
                -> BackendResult<SoundInterface<T>> {
+        let converter = SampleConverter::new(SampleFormat::Float32, channel_count as usize)
+            .map_err(|_| BackendError::FormatInvalid)?;
+        Self::new_with_converter(sample_rate,
+                                 buffer_size,
+                                 generator,
+                                 converter,
+                                 COMMAND_QUEUE_SIZE_DEFAULT)
+    }
+
+    /// Creates a new backend for sound playback. The sample format, the channel count, the
+    /// dither and the clipping are defined by the `converter`. `SampleFormat::Int24` samples
+    /// are sent in 32-bit integers.
+    pub fn new_with_converter(sample_rate: u32,
+                              buffer_size: usize,
+                              generator: Box<SoundGenerator<Command = T>>,
+                              converter: SampleConverter,
+                              queue_size: usize)
+                              -> BackendResult<SoundInterface<T>> {
         println!("PortAudio version : {}", pa::version());
         println!("PortAudio version text : {:?}", pa::version_text());
         let pa = pa::PortAudio::new()?;
         println!("host count: {}", pa.host_api_count()?);
-        let mut settings = pa.default_output_stream_settings(channel_count as i32,
-                                            sample_rate as f64,
-                                            buffer_size as u32)?;
-        // we won't output out of range samples so don't bother clipping them.
-        settings.flags = pa::stream_flags::CLIP_OFF;
-
-        let mut generator_buffer: Vec<SampleCalc> = vec![0.0; buffer_size];
-
-        let (sender, receiver) = ::std::sync::mpsc::channel();
-        // This routine will be called by the PortAudio engine when audio is needed. It may
-        // called at interrupt level on some machines so don't do anything that could mess
-        // up the system like dynamic resource allocation or IO.
-        let callback_fn = move |pa::OutputStreamCallbackArgs { buffer, frames, .. }| {
-            if let Ok(command) = receiver.try_recv() {
-                generator.process_command(command);
+        let channel_count = converter.get_channel_count();
+        let sample_format = converter.get_sample_format();
+        let clipped_count = converter.get_clipped_counter();
+        let frame_position = Arc::new(AtomicUsize::new(0));
+        let (sender, receiver) = command_queue(queue_size);
+        let mut scheduler = CommandScheduler::new(buffer_size, channel_count, queue_size);
+        scheduler.set_dropped_counter(sender.get_dropped_counter());
+        let state = CallbackState {
+            generator: generator,
+            receiver: receiver,
+            scheduler: scheduler,
+            channel_buffers: vec![vec![0.0; buffer_size]; channel_count],
+            interleaved_buffer: vec![0.0; buffer_size * channel_count],
+            converter: converter,
+            frame_position: frame_position.clone(),
+        };
+        let stream = match sample_format {
+            SampleFormat::Int16 => {
+                OutputStream::Int16(open_stream(&pa,
+                                                sample_rate,
+                                                buffer_size,
+                                                channel_count,
+                                                state)?)
+            }
+            SampleFormat::Int24 |
+            SampleFormat::Int32 => {
+                OutputStream::Int32(open_stream(&pa,
+                                                sample_rate,
+                                                buffer_size,
+                                                channel_count,
+                                                state)?)
             }
-            generator.get_samples(frames, &mut generator_buffer);
-            let mut idx = 0;
-            for item in generator_buffer.iter().take(frames) {
-                for _ in 0..(channel_count as usize) {
-                    buffer[idx] = *item;// as SampleOutput;
-                    idx += 1;
-                }
+            SampleFormat::Float32 => {
+                OutputStream::Float32(open_stream(&pa,
+                                                  sample_rate,
+                                                  buffer_size,
+                                                  channel_count,
+                                                  state)?)
             }
-            //            for output_frame in buffer.chunks_mut(channel_count) {
-            //                for channel_sample in output_frame {
-            //                    *channel_sample = synthesizer.sample_next();
-            // sender.send(time_start).ok();
-            pa::Continue
         };
-
-        // Open a non-blocking stream.
-        let stream = pa.open_non_blocking_stream(settings, callback_fn)?;
         println!("Stream is created.");
 
         Ok(SoundInterface {
             sample_rate: sample_rate,
-            channel_count: channel_count,
+            channel_count: channel_count as u16,
             stream: stream,
-            sender: Some(sender),
+            sender: sender,
+            frame_position: frame_position,
+            clipped_count: clipped_count,
         })
     }
+}
+
+impl<T> SoundInterface<T> {
     /// Starts the sound output stream.
     pub fn start(&mut self) -> BackendResult<()> {
         self.stream.start()?;
         println!("Successfully started the stream.");
         Ok(())
     }
-    /// Sends a command to the sound generator.
+    /// Sends a command to the sound generator. It is processed at the beginning of the next
+    /// buffer.
     pub fn send_command(&mut self, command: T) -> BackendResult<()> {
-        if let Some(ref sender) = self.sender {
-            match sender.send(command) {
-                Ok(_) => Ok(()),
-                Err(_) => Err(BackendError::Disconnected),
-            }
-        } else {
-            return Err(BackendError::Disconnected);
-        }
+        self.send_timed_command(TimedCommand::immediate(command))
+    }
+    /// Sends a command to the sound generator, which is processed exactly at the given frame
+    /// position of the output stream (see `get_frame_position()`).
+    pub fn send_command_at(&mut self, command: T, frame: u64) -> BackendResult<()> {
+        self.send_timed_command(TimedCommand::at(command, frame))
+    }
+    fn send_timed_command(&mut self, timed_command: TimedCommand<T>) -> BackendResult<()> {
+        self.sender.send(timed_command)
     }
 
     /// Returns the sample rate of the sond output
@@ -92,6 +197,23 @@ impl<T> SoundInterface<T> {
     pub fn get_channel_count(&self) -> u16 {
         self.channel_count
     }
+    /// Returns the frame position of the output stream, which is the position of the next
+    /// buffer to be rendered.
+    pub fn get_frame_position(&self) -> u64 {
+        self.frame_position.load(Ordering::Relaxed) as u64
+    }
+    /// Returns the number of commands waiting in the queue.
+    pub fn get_queue_depth(&self) -> usize {
+        self.sender.get_depth()
+    }
+    /// Returns the number of commands dropped because of a full queue (or a full schedule).
+    pub fn get_dropped_count(&self) -> usize {
+        self.sender.get_dropped_count()
+    }
+    /// Returns the number of output samples which exceeded the [-1.0, 1.0] range.
+    pub fn get_clipped_count(&self) -> usize {
+        self.clipped_count.load(Ordering::Relaxed)
+    }
 }
 
 impl<T> Drop for SoundInterface<T> {
@@ -108,47 +230,27 @@ impl<T> Drop for SoundInterface<T> {
     }
 }
 
-/// Wrapper for the propagation of backend specific errors.
-#[derive(Debug, Copy, Clone)]
-pub enum BackendError {
-    /// Errors of the PortAudio backend.
-    PortAudio(pa::Error),
-    /// The SoundGenerator is disconnected, could not recieve the command
-    Disconnected,
-}
-
-use std::{error, fmt};
-
-impl fmt::Display for BackendError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        use std::error::Error;
-        f.write_str(self.description())
-    }
-}
-
-impl error::Error for BackendError {
-    fn description(&self) -> &str {
-        use self::BackendError::*;
-        match *self {
-            PortAudio(ref err) => err.description(),
-            Disconnected => "SoundGenerator is disconnected",
-        }
-    }
-
-    fn cause(&self) -> Option<&error::Error> {
-        use self::BackendError::*;
-        match *self {
-            PortAudio(ref err) => Some(err),
-            _ => None,
-        }
-    }
-}
-
-impl From<pa::Error> for BackendError {
-    fn from(e: pa::Error) -> Self {
-        BackendError::PortAudio(e)
-    }
+/// Opens a non-blocking stream with the sample type `S`.
+fn open_stream<S, T>(pa: &pa::PortAudio,
+                     sample_rate: u32,
+                     buffer_size: usize,
+                     channel_count: usize,
+                     mut state: CallbackState<T>)
+                     -> BackendResult<pa::Stream<pa::NonBlocking, pa::stream::Output<S>>>
+    where S: pa::Sample + OutputSample + Default + 'static,
+          T: 'static
+{
+    let mut settings = pa.default_output_stream_settings(channel_count as i32,
+                                                         sample_rate as f64,
+                                                         buffer_size as u32)?;
+    // The converter clips (or saturates) the out of range samples.
+    settings.flags = pa::stream_flags::CLIP_OFF;
+    // This routine will be called by the PortAudio engine when audio is needed. It may
+    // called at interrupt level on some machines so don't do anything that could mess
+    // up the system like dynamic resource allocation or IO.
+    let callback_fn = move |pa::OutputStreamCallbackArgs { buffer, frames, .. }| {
+        state.render(frames, buffer);
+        pa::Continue
+    };
+    Ok(pa.open_non_blocking_stream(settings, callback_fn)?)
 }
-
-/// Return type for the backend functions.
-pub type BackendResult<T> = Result<T, BackendError>;
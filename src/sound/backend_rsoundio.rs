@@ -1,5 +1,353 @@
 use rb::{RB, RbConsumer, RbProducer, SpscRb};
+use rsoundio;
 use sound::*;
-use std::sync::mpsc::Sender;
+use sound::command_queue::*;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
 
-// TODO create this backend
+/// The size of the ring buffer, measured in generator buffers.
+const RING_BUFFER_COUNT: usize = 4;
+
+/// This is a wrapper around the sound output backend. The generator is running on a separate
+/// producer thread, which renders into a ring buffer. The output stream reads that buffer.
+/// Commands are passed to the producer thread through a bounded, preallocated queue (see
+/// `command_queue()`).
+///
+/// Unlike the PortAudio backend, the generator has to be `Send`, because it is moved to the
+/// producer thread.
+pub struct SoundInterface<T: 'static> {
+    sample_rate: u32,
+    channel_count: u16,
+    stream: rsoundio::OutStream,
+    sender: CommandSender<T>,
+    running: Arc<AtomicBool>,
+    frame_position: Arc<AtomicUsize>,
+    clipped_count: Arc<AtomicUsize>,
+    /// The error which has stopped the producer thread.
+    producer_error: Arc<Mutex<Option<BackendError>>>,
+    producer_thread: Option<thread::JoinHandle<()>>,
+    // It must be dropped after the stream.
+    sound_io: rsoundio::SoundIo,
+}
+
+impl<T: Send> SoundInterface<T> {
+    /// Creates a new backend for sound playback, using the default libsoundio backend.
+    /// Each channel outputs the corresponding channel of the generator. The samples are hard
+    /// clipped. The generator must be `Send`, it runs on the producer thread.
+    ///
+    /// If the rendering fails, the producer thread stops and the output becomes silent, the
+    /// error (`BackendError::RenderFailed`) is returned by the next `start()` or
+    /// `send_command()` call.
+    pub fn new(sample_rate: u32,
+               buffer_size: usize,
+               channel_count: u16,
+               generator: Box<SoundGenerator<Command = T> + Send>)
+               -> BackendResult<SoundInterface<T>> {
+        let converter = SampleConverter::new(SampleFormat::Float32, channel_count as usize)
+            .map_err(|_| BackendError::FormatInvalid)?;
+        Self::new_with_backend(sample_rate, buffer_size, generator, converter, None)
+    }
+
+    /// Creates a new backend for sound playback, using the given libsoundio backend.
+    /// With `rsoundio::SioBackend::Dummy` it can run without sound hardware.
+    /// The channel count, the dither and the clipping are defined by the `converter`. The
+    /// stream format is always 32-bit float, integer sample formats are quantized (and
+    /// dithered) to their resolution before.
+    pub fn new_with_backend(sample_rate: u32,
+                            buffer_size: usize,
+                            mut generator: Box<SoundGenerator<Command = T> + Send>,
+                            mut converter: SampleConverter,
+                            backend: Option<rsoundio::SioBackend>)
+                            -> BackendResult<SoundInterface<T>> {
+        let channel_count = converter.get_channel_count() as u16;
+        let clipped_count = converter.get_clipped_counter();
+        let sound_io = rsoundio::SoundIo::new("scaleless_music");
+        match backend {
+            Some(backend) => sound_io.connect_backend(backend)?,
+            None => sound_io.connect()?,
+        }
+        sound_io.flush_events();
+        let device = sound_io.default_output_device().ok_or(BackendError::DeviceUnavailable)?;
+        let mut stream = device.create_outstream()?;
+        stream.set_name("scaleless_music")?;
+        stream.set_format(rsoundio::SioFormat::Float32LE)?;
+        stream.set_sample_rate(sample_rate);
+        stream.set_layout(rsoundio::ChannelLayout::get_default(channel_count as i32));
+
+        let channels = channel_count as usize;
+        let ring_buffer = SpscRb::new(buffer_size * channels * RING_BUFFER_COUNT);
+        let (producer, consumer) = (ring_buffer.producer(), ring_buffer.consumer());
+        let (sender, mut receiver) = command_queue(COMMAND_QUEUE_SIZE_DEFAULT);
+        let running = Arc::new(AtomicBool::new(true));
+        let frame_position = Arc::new(AtomicUsize::new(0));
+        let producer_error = Arc::new(Mutex::new(None));
+
+        // The producer thread renders the sound in advance, so the generator is not called
+        // from the callback of the output stream.
+        let producer_running = running.clone();
+        let producer_frame_position = frame_position.clone();
+        let producer_thread_error = producer_error.clone();
+        let scheduler_dropped_count = sender.get_dropped_counter();
+        let buffer_time = buffer_size as u64 * 1_000_000 / sample_rate.max(1) as u64;
+        let producer_thread = thread::spawn(move || {
+            let mut channel_buffers: Vec<Vec<SampleCalc>> =
+                vec![vec![0.0; buffer_size]; channels];
+            let mut interleaved_buffer: Vec<SampleCalc> = vec![0.0; buffer_size * channels];
+            let mut frame_buffer: Vec<SampleOutput> = vec![0.0; buffer_size * channels];
+            let mut scheduler =
+                CommandScheduler::new(buffer_size, channels, COMMAND_QUEUE_SIZE_DEFAULT);
+            scheduler.set_dropped_counter(scheduler_dropped_count);
+            while producer_running.load(Ordering::Relaxed) {
+                receiver.receive_all(&mut scheduler);
+                if scheduler.render(&mut *generator, buffer_size, &mut channel_buffers)
+                    .and_then(|_| {
+                        interleave(&channel_buffers, buffer_size, &mut interleaved_buffer)
+                    })
+                    .and_then(|_| converter.convert(&interleaved_buffer, &mut frame_buffer))
+                    .is_err() {
+                    if let Ok(mut error) = producer_thread_error.lock() {
+                        *error = Some(BackendError::RenderFailed);
+                    }
+                    return;
+                }
+                producer_frame_position.store(scheduler.get_frame_position() as usize,
+                                              Ordering::Relaxed);
+                let mut written = 0;
+                while written < frame_buffer.len() {
+                    match producer.write(&frame_buffer[written..]) {
+                        Ok(count) => written += count,
+                        Err(_) => {
+                            if !producer_running.load(Ordering::Relaxed) {
+                                return;
+                            }
+                            thread::sleep(Duration::from_micros(buffer_time / 4 + 1));
+                        }
+                    }
+                }
+            }
+        });
+
+        // This routine will be called by libsoundio when audio is needed. Its buffers are
+        // allocated in advance, so it only copies samples. At least `min_frame_count` frames
+        // must be written, so it is done in chunks of `buffer_size` frames if necessary.
+        let mut interleaved_buffer: Vec<SampleOutput> = vec![0.0; buffer_size * channels];
+        let mut output_buffers: Vec<Vec<SampleOutput>> = vec![vec![0.0; buffer_size]; channels];
+        stream.register_write_callback(Box::new(move |out: rsoundio::OutStream,
+                                                      min_frame_count: u32,
+                                                      max_frame_count: u32| {
+            let frame_count = (max_frame_count as usize)
+                .min(buffer_size)
+                .max(min_frame_count as usize);
+            let mut written = 0;
+            while written < frame_count {
+                let frames = (frame_count - written).min(buffer_size);
+                let samples = frames * channels;
+                let read = consumer.read(&mut interleaved_buffer[..samples]).unwrap_or(0);
+                // buffer underrun: silence
+                for item in interleaved_buffer[read..samples].iter_mut() {
+                    *item = 0.0;
+                }
+                for (channel, output_buffer) in output_buffers.iter_mut().enumerate() {
+                    output_buffer.truncate(0);
+                    for frame in interleaved_buffer[..samples].chunks(channels) {
+                        output_buffer.push(frame[channel]);
+                    }
+                }
+                if let Err(err) = out.write_stream_f32(frames as u32, &output_buffers) {
+                    println!("libsoundio.write_stream_f32: {}", err);
+                    return;
+                }
+                written += frames;
+            }
+        }));
+        stream.open()?;
+        println!("Stream is created.");
+
+        Ok(SoundInterface {
+            sample_rate: sample_rate,
+            channel_count: channel_count,
+            stream: stream,
+            sender: sender,
+            running: running,
+            frame_position: frame_position,
+            clipped_count: clipped_count,
+            producer_error: producer_error,
+            producer_thread: Some(producer_thread),
+            sound_io: sound_io,
+        })
+    }
+}
+
+impl<T> SoundInterface<T> {
+    /// Starts the sound output stream.
+    pub fn start(&mut self) -> BackendResult<()> {
+        self.check_producer()?;
+        self.stream.start()?;
+        println!("Successfully started the stream.");
+        Ok(())
+    }
+
+    /// Sends a command to the sound generator. It is processed at the beginning of the next
+    /// rendered buffer.
+    pub fn send_command(&mut self, command: T) -> BackendResult<()> {
+        self.send_timed_command(TimedCommand::immediate(command))
+    }
+
+    /// Sends a command to the sound generator, which is processed exactly at the given frame
+    /// position of the output stream (see `get_frame_position()`).
+    pub fn send_command_at(&mut self, command: T, frame: u64) -> BackendResult<()> {
+        self.send_timed_command(TimedCommand::at(command, frame))
+    }
+
+    fn send_timed_command(&mut self, timed_command: TimedCommand<T>) -> BackendResult<()> {
+        self.check_producer()?;
+        self.sender.send(timed_command)
+    }
+
+    /// Returns the error which has stopped the producer thread (the output is silent since
+    /// then).
+    fn check_producer(&self) -> BackendResult<()> {
+        match self.producer_error.lock() {
+            Ok(error) => {
+                match *error {
+                    Some(error) => Err(error),
+                    None => Ok(()),
+                }
+            }
+            Err(_) => Err(BackendError::RenderFailed),
+        }
+    }
+
+    /// Returns the sample rate of the sond output
+    pub fn get_sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Returns the channel count of the sond output
+    pub fn get_channel_count(&self) -> u16 {
+        self.channel_count
+    }
+
+    /// Returns the frame position of the next buffer to be rendered. The producer thread
+    /// renders ahead of the playback by the length of the ring buffer.
+    pub fn get_frame_position(&self) -> u64 {
+        self.frame_position.load(Ordering::Relaxed) as u64
+    }
+
+    /// Returns the number of commands waiting in the queue.
+    pub fn get_queue_depth(&self) -> usize {
+        self.sender.get_depth()
+    }
+
+    /// Returns the number of commands dropped because of a full queue (or a full schedule).
+    pub fn get_dropped_count(&self) -> usize {
+        self.sender.get_dropped_count()
+    }
+
+    /// Returns the number of output samples which exceeded the [-1.0, 1.0] range.
+    pub fn get_clipped_count(&self) -> usize {
+        self.clipped_count.load(Ordering::Relaxed)
+    }
+}
+
+impl<T> Drop for SoundInterface<T> {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        if let Err(err) = self.stream.pause(true) {
+            println!("libsoundio.stream.pause: {}", err);
+        }
+        if let Some(producer_thread) = self.producer_thread.take() {
+            if producer_thread.join().is_err() {
+                println!("libsoundio: the producer thread has panicked");
+            }
+        }
+        self.sound_io.flush_events();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rsoundio;
+    use sound::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::Duration;
+    use super::*;
+
+    /// Outputs silence, counts the rendered samples and the processed commands.
+    struct CountingGenerator {
+        sample_count: Arc<AtomicUsize>,
+        command_count: Arc<AtomicUsize>,
+    }
+
+    impl SoundGenerator for CountingGenerator {
+        type Command = u8;
+
+        fn get_samples(&mut self, sample_count: usize, result: &mut Vec<SampleCalc>) {
+            for item in result.iter_mut().take(sample_count) {
+                *item = 0.0;
+            }
+            let _ = self.sample_count.fetch_add(sample_count, Ordering::Relaxed);
+        }
+
+        fn process_command(&mut self, _command: u8) {
+            let _ = self.command_count.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn new_dummy(buffer_size: usize)
+                 -> (SoundInterface<u8>, Arc<AtomicUsize>, Arc<AtomicUsize>) {
+        let sample_count = Arc::new(AtomicUsize::new(0));
+        let command_count = Arc::new(AtomicUsize::new(0));
+        let generator = CountingGenerator {
+            sample_count: sample_count.clone(),
+            command_count: command_count.clone(),
+        };
+        let converter = SampleConverter::new(SampleFormat::Float32, 2).unwrap();
+        let sound_interface = SoundInterface::new_with_backend(48000,
+                                                               buffer_size,
+                                                               Box::new(generator),
+                                                               converter,
+                                                               Some(rsoundio::SioBackend::Dummy))
+            .unwrap();
+        (sound_interface, sample_count, command_count)
+    }
+
+    #[test]
+    fn dummy_backend_renders() {
+        let (mut sound_interface, sample_count, _) = new_dummy(256);
+        assert_eq!(sound_interface.get_channel_count(), 2);
+        assert_eq!(sound_interface.get_sample_rate(), 48000);
+        sound_interface.start().unwrap();
+        thread::sleep(Duration::from_millis(200));
+        // the producer thread counts the samples before it stores the frame position
+        let frame_position = sound_interface.get_frame_position();
+        assert!(frame_position > 0);
+        assert!(sample_count.load(Ordering::Relaxed) as u64 >= frame_position);
+    }
+
+    #[test]
+    fn dummy_backend_small_buffer() {
+        // The device requests more frames than the buffer size, they are written in chunks.
+        let (mut sound_interface, _, _) = new_dummy(16);
+        sound_interface.start().unwrap();
+        thread::sleep(Duration::from_millis(200));
+        assert!(sound_interface.get_frame_position() > 16);
+    }
+
+    #[test]
+    fn dummy_backend_commands() {
+        let (mut sound_interface, _, command_count) = new_dummy(256);
+        sound_interface.start().unwrap();
+        for command in 0..10 {
+            sound_interface.send_command(command).unwrap();
+        }
+        thread::sleep(Duration::from_millis(200));
+        assert_eq!(command_count.load(Ordering::Relaxed), 10);
+        assert_eq!(sound_interface.get_queue_depth(), 0);
+        assert_eq!(sound_interface.get_dropped_count(), 0);
+    }
+}
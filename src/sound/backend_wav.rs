@@ -0,0 +1,447 @@
+use sound::*;
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// `WAVE_FORMAT_PCM`
+const FORMAT_PCM: u16 = 0x0001;
+/// `WAVE_FORMAT_IEEE_FLOAT`
+const FORMAT_IEEE_FLOAT: u16 = 0x0003;
+/// `WAVE_FORMAT_EXTENSIBLE`, required for more than 2 channels or more than 16 bits.
+const FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+/// The tail of the `KSDATAFORMAT_SUBTYPE_*` GUIDs, after the two bytes of the format tag.
+const SUBFORMAT_GUID_TAIL: [u8; 14] = [0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00,
+                                       0xAA, 0x00, 0x38, 0x9B, 0x71];
+
+fn get_format_tag(sample_format: SampleFormat) -> u16 {
+    if sample_format.is_integer() {
+        FORMAT_PCM
+    } else {
+        FORMAT_IEEE_FLOAT
+    }
+}
+
+/// Writes sound into a [RIFF/WAVE](https://en.wikipedia.org/wiki/WAV) file, for offline
+/// rendering. Multichannel samples are interleaved. The header is updated by `finalize()`,
+/// which is also called on drop. An odd sized data chunk is followed by a pad byte, as RIFF
+/// requires it. The samples are converted by a `SampleConverter`, without
+/// dither and with hard clipping by default.
+pub struct WavWriter<W: Write + Seek> {
+    writer: W,
+    sample_rate: u32,
+    channel_count: u16,
+    sample_format: SampleFormat,
+    converter: SampleConverter,
+    /// The number of written frames (a frame contains one sample for each channel).
+    frame_count: u32,
+    /// Position of the frame count in the `fact` chunk (only used for floating point samples).
+    fact_position: Option<u64>,
+    /// Position of the size field of the `data` chunk.
+    data_size_position: u64,
+    byte_buffer: Vec<u8>,
+}
+
+impl WavWriter<BufWriter<File>> {
+    /// Creates a new WAVE file at the given path.
+    pub fn create<P: AsRef<Path>>(path: P,
+                                  sample_rate: u32,
+                                  channel_count: u16,
+                                  sample_format: SampleFormat)
+                                  -> SoundResult<WavWriter<BufWriter<File>>> {
+        let file = File::create(path)?;
+        WavWriter::new(BufWriter::new(file), sample_rate, channel_count, sample_format)
+    }
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    /// custom constructor, it writes the header immediately.
+    pub fn new(writer: W,
+               sample_rate: u32,
+               channel_count: u16,
+               sample_format: SampleFormat)
+               -> SoundResult<WavWriter<W>> {
+        if sample_rate == 0 {
+            return Err(Error::SampleRateInvalid);
+        }
+        if channel_count == 0 {
+            return Err(Error::ChannelInvalid);
+        }
+        let block_align = (sample_format.get_bytes_per_sample() as u32) * (channel_count as u32);
+        if (block_align > (::std::u16::MAX as u32)) ||
+           sample_rate.checked_mul(block_align).is_none() {
+            return Err(Error::Overflow);
+        }
+        let mut wav_writer = WavWriter {
+            writer: writer,
+            sample_rate: sample_rate,
+            channel_count: channel_count,
+            sample_format: sample_format,
+            converter: SampleConverter::new(sample_format, channel_count as usize)?,
+            frame_count: 0,
+            fact_position: None,
+            data_size_position: 0,
+            byte_buffer: Vec::new(),
+        };
+        wav_writer.write_header()?;
+        Ok(wav_writer)
+    }
+
+    fn write_header(&mut self) -> SoundResult<()> {
+        let bytes_per_sample = self.sample_format.get_bytes_per_sample();
+        // checked by the constructor
+        let block_align = (bytes_per_sample as u32) * (self.channel_count as u32);
+        let extensible = (self.channel_count > 2) || (bytes_per_sample > 2);
+        let mut header: Vec<u8> = Vec::with_capacity(80);
+        header.extend_from_slice(b"RIFF");
+        push_u32(&mut header, 0); // updated by finalize()
+        header.extend_from_slice(b"WAVE");
+        header.extend_from_slice(b"fmt ");
+        push_u32(&mut header, if extensible { 40 } else { 16 });
+        push_u16(&mut header,
+                 if extensible {
+                     FORMAT_EXTENSIBLE
+                 } else {
+                     get_format_tag(self.sample_format)
+                 });
+        push_u16(&mut header, self.channel_count);
+        push_u32(&mut header, self.sample_rate);
+        push_u32(&mut header, self.sample_rate * block_align);
+        push_u16(&mut header, block_align as u16);
+        push_u16(&mut header, bytes_per_sample * 8);
+        if extensible {
+            push_u16(&mut header, 22); // size of the extension
+            push_u16(&mut header, bytes_per_sample * 8); // valid bits per sample
+            push_u32(&mut header, 0); // channel mask: no speaker assignment
+            push_u16(&mut header, get_format_tag(self.sample_format));
+            header.extend_from_slice(&SUBFORMAT_GUID_TAIL);
+        }
+        if self.sample_format == SampleFormat::Float32 {
+            header.extend_from_slice(b"fact");
+            push_u32(&mut header, 4);
+            self.fact_position = Some(header.len() as u64);
+            push_u32(&mut header, 0); // updated by finalize()
+        }
+        header.extend_from_slice(b"data");
+        self.data_size_position = header.len() as u64;
+        push_u32(&mut header, 0); // updated by finalize()
+        self.writer.write_all(&header)?;
+        Ok(())
+    }
+
+    /// Writes interleaved samples, the sample count must be divisible by the channel count.
+    /// Samples are expected to be in the [-1.0, 1.0] range, they are clipped (or saturated)
+    /// according to the clip mode.
+    pub fn write_samples(&mut self, samples: &[SampleCalc]) -> SoundResult<()> {
+        if samples.len() % (self.channel_count as usize) != 0 {
+            return Err(Error::BufferSize);
+        }
+        let frames = samples.len() / (self.channel_count as usize);
+        let bytes_per_frame = (self.sample_format.get_bytes_per_sample() as u64) *
+                              (self.channel_count as u64);
+        let frame_count = (self.frame_count as u64) + (frames as u64);
+        // The RIFF chunk size is limited to 32 bits (including the pad byte).
+        if (frame_count * bytes_per_frame) + self.data_size_position + 5 >
+           (::std::u32::MAX as u64) {
+            return Err(Error::Overflow);
+        }
+        self.byte_buffer.clear();
+        self.converter.convert_to_bytes(samples, &mut self.byte_buffer)?;
+        self.writer.write_all(&self.byte_buffer)?;
+        self.frame_count = frame_count as u32;
+        Ok(())
+    }
+
+    /// Writes mono samples, all channels get the same sound.
+    pub fn write_mono(&mut self, samples: &[SampleCalc]) -> SoundResult<()> {
+        let mut interleaved = Vec::with_capacity(samples.len() * (self.channel_count as usize));
+        for sample in samples {
+            for _ in 0..self.channel_count {
+                interleaved.push(*sample);
+            }
+        }
+        self.write_samples(&interleaved)
+    }
+
+    /// Writes the first `frame_count` samples of each channel buffer. The number of buffers
+    /// must be equal to the channel count.
+    pub fn write_channels(&mut self,
+                          channels: &[Vec<SampleCalc>],
+                          frame_count: usize)
+                          -> SoundResult<()> {
+        if channels.len() != self.channel_count as usize {
+            return Err(Error::ChannelInvalid);
+        }
+        let mut interleaved = vec![0.0; frame_count * channels.len()];
+        interleave(channels, frame_count, &mut interleaved)?;
+        self.write_samples(&interleaved)
+    }
+
+    /// Drives the `generator` for the given `duration` (in seconds), and writes it's output.
+    /// The generator is called with `buffer_size` samples each time, the last buffer is
+    /// truncated.
+    pub fn render_generator<T>(&mut self,
+                               generator: &mut SoundGenerator<Command = T>,
+                               buffer_size: usize,
+                               duration: SampleCalc)
+                               -> SoundResult<()> {
+        if buffer_size == 0 {
+            return Err(Error::BufferSize);
+        }
+        let mut frames_remaining = self.get_frames_for_duration(duration)?;
+        let mut channel_buffers: Vec<Vec<SampleCalc>> =
+            vec![vec![0.0; buffer_size]; self.channel_count as usize];
+        while frames_remaining > 0 {
+            generator.get_channel_samples(buffer_size, &mut channel_buffers);
+            let frames = frames_remaining.min(buffer_size);
+            self.write_channels(&channel_buffers, frames)?;
+            frames_remaining -= frames;
+        }
+        Ok(())
+    }
+
+    /// Renders the `sound` structure for the given `duration` (in seconds), with the base
+    /// frequency provided by `frequency`. The last buffer is truncated.
+    pub fn render_structure(&mut self,
+                            sound: &SoundStructure,
+                            frequency: &FrequencyFunction,
+                            buffer_size: usize,
+                            duration: SampleCalc)
+                            -> SoundResult<()> {
+        if buffer_size == 0 {
+            return Err(Error::BufferSize);
+        }
+        let mut frames_remaining = self.get_frames_for_duration(duration)?;
+        let mut frequency_buffer: Vec<SampleCalc> = vec![1.0; buffer_size];
+        let mut wave_buffer: Vec<SampleCalc> = vec![0.0; buffer_size];
+        let mut frame_index: u64 = 0;
+        while frames_remaining > 0 {
+            // the time is calculated from the frame index, so the rounding errors don't add up
+            let time = (frame_index as f64 / self.sample_rate as f64) as SampleCalc;
+            frequency.get(time, None, &mut frequency_buffer)?;
+            sound.get(&frequency_buffer, &mut wave_buffer)?;
+            let frames = frames_remaining.min(buffer_size);
+            self.write_mono(&wave_buffer[..frames])?;
+            frames_remaining -= frames;
+            frame_index += buffer_size as u64;
+        }
+        Ok(())
+    }
+
+    fn get_frames_for_duration(&self, duration: SampleCalc) -> SoundResult<usize> {
+        if duration <= 0.0 {
+            return Err(Error::DurationInvalid);
+        }
+        Ok((duration * self.sample_rate as SampleCalc).round() as usize)
+    }
+
+    /// Updates the chunk sizes in the header, writes the pad byte after an odd sized data
+    /// chunk, and flushes the writer. Writing can be continued afterwards (the pad byte is
+    /// overwritten by the next samples).
+    pub fn finalize(&mut self) -> SoundResult<()> {
+        let data_size = (self.frame_count as u64) *
+                        (self.sample_format.get_bytes_per_sample() as u64) *
+                        (self.channel_count as u64);
+        let pad_size = data_size % 2;
+        let riff_size = self.data_size_position + data_size + pad_size - 4;
+        let end_position = self.writer.seek(SeekFrom::Current(0))?;
+        if pad_size != 0 {
+            self.writer.write_all(&[0])?;
+        }
+        let _ = self.writer.seek(SeekFrom::Start(4))?;
+        write_u32(&mut self.writer, riff_size as u32)?;
+        if let Some(position) = self.fact_position {
+            let _ = self.writer.seek(SeekFrom::Start(position))?;
+            write_u32(&mut self.writer, self.frame_count)?;
+        }
+        let _ = self.writer.seek(SeekFrom::Start(self.data_size_position))?;
+        write_u32(&mut self.writer, data_size as u32)?;
+        let _ = self.writer.seek(SeekFrom::Start(end_position))?;
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    /// Sets the dither option of the integer formats.
+    pub fn set_dither(&mut self, dither: Dither) {
+        self.converter.set_dither(dither);
+    }
+
+    /// Sets the handling of out of range samples.
+    pub fn set_clip_mode(&mut self, clip_mode: ClipMode) {
+        self.converter.set_clip_mode(clip_mode);
+    }
+
+    /// Returns the number of written samples which exceeded the [-1.0, 1.0] range.
+    pub fn get_clipped_count(&self) -> usize {
+        self.converter.get_clipped_count()
+    }
+
+    /// Returns the number of frames written so far.
+    pub fn get_frame_count(&self) -> u32 {
+        self.frame_count
+    }
+
+    /// Returns the sample rate of the file.
+    pub fn get_sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Returns the channel count of the file.
+    pub fn get_channel_count(&self) -> u16 {
+        self.channel_count
+    }
+}
+
+impl<W: Write + Seek> Drop for WavWriter<W> {
+    fn drop(&mut self) {
+        if let Err(err) = self.finalize() {
+            println!("WavWriter.finalize: {}", err);
+        }
+    }
+}
+
+fn push_u16(buffer: &mut Vec<u8>, value: u16) {
+    buffer.push(value as u8);
+    buffer.push((value >> 8) as u8);
+}
+
+fn push_u32(buffer: &mut Vec<u8>, value: u32) {
+    buffer.push(value as u8);
+    buffer.push((value >> 8) as u8);
+    buffer.push((value >> 16) as u8);
+    buffer.push((value >> 24) as u8);
+}
+
+fn write_u32<W: Write>(writer: &mut W, value: u32) -> SoundResult<()> {
+    let mut bytes: Vec<u8> = Vec::with_capacity(4);
+    push_u32(&mut bytes, value);
+    writer.write_all(&bytes)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use sound::*;
+    use std::io::Cursor;
+    use super::*;
+
+    fn get_u16(bytes: &[u8], position: usize) -> u16 {
+        bytes[position] as u16 | (bytes[position + 1] as u16) << 8
+    }
+
+    fn get_u32(bytes: &[u8], position: usize) -> u32 {
+        get_u16(bytes, position) as u32 | (get_u16(bytes, position + 2) as u32) << 16
+    }
+
+    fn render(sample_rate: u32,
+              channel_count: u16,
+              sample_format: SampleFormat,
+              samples: &[SampleCalc])
+              -> Vec<u8> {
+        let mut wav_writer = WavWriter::new(Cursor::new(Vec::new()),
+                                            sample_rate,
+                                            channel_count,
+                                            sample_format)
+            .unwrap();
+        wav_writer.write_samples(samples).unwrap();
+        wav_writer.finalize().unwrap();
+        wav_writer.writer.get_ref().clone()
+    }
+
+    #[test]
+    fn header_int16_stereo() {
+        let bytes = render(44100, 2, SampleFormat::Int16, &[0.0, 0.5, -1.0, 1.0]);
+        assert_eq!(bytes.len(), 44 + 8);
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(get_u32(&bytes, 4), bytes.len() as u32 - 8);
+        assert_eq!(&bytes[8..16], b"WAVEfmt ");
+        assert_eq!(get_u32(&bytes, 16), 16);
+        assert_eq!(get_u16(&bytes, 20), 1); // PCM
+        assert_eq!(get_u16(&bytes, 22), 2);
+        assert_eq!(get_u32(&bytes, 24), 44100);
+        assert_eq!(get_u32(&bytes, 28), 44100 * 4);
+        assert_eq!(get_u16(&bytes, 32), 4);
+        assert_eq!(get_u16(&bytes, 34), 16);
+        assert_eq!(&bytes[36..40], b"data");
+        assert_eq!(get_u32(&bytes, 40), 8);
+        let samples: Vec<i16> = (0..4)
+            .map(|index| get_u16(&bytes, 44 + index * 2) as i16)
+            .collect();
+        assert_eq!(samples, vec![0, 16384, -32767, 32767]);
+    }
+
+    #[test]
+    fn header_int24_extensible() {
+        let bytes = render(SAMPLE_RATE_DEFAULT as u32, 1, SampleFormat::Int24, &[0.0, 0.25]);
+        assert_eq!(get_u32(&bytes, 16), 40);
+        assert_eq!(get_u16(&bytes, 20), 0xFFFE);
+        assert_eq!(get_u16(&bytes, 32), 3);
+        assert_eq!(get_u16(&bytes, 34), 24);
+        assert_eq!(get_u16(&bytes, 38), 24); // valid bits
+        assert_eq!(get_u16(&bytes, 44), 1); // subformat: PCM
+        assert_eq!(&bytes[60..64], b"data");
+        assert_eq!(get_u32(&bytes, 64), 6);
+        assert_eq!(bytes.len(), 68 + 6);
+        assert_eq!(get_u32(&bytes, 4), bytes.len() as u32 - 8);
+    }
+
+    #[test]
+    fn header_float32_fact() {
+        let bytes = render(48000, 3, SampleFormat::Float32, &[0.0, 0.5, -0.5, 1.0, 0.25, 2.0]);
+        assert_eq!(get_u16(&bytes, 20), 0xFFFE);
+        assert_eq!(get_u16(&bytes, 22), 3);
+        assert_eq!(get_u32(&bytes, 28), 48000 * 12);
+        assert_eq!(get_u16(&bytes, 44), 3); // subformat: IEEE float
+        assert_eq!(&bytes[60..64], b"fact");
+        assert_eq!(get_u32(&bytes, 68), 2); // frame count
+        assert_eq!(&bytes[72..76], b"data");
+        assert_eq!(get_u32(&bytes, 76), 24);
+        assert_eq!(f32::from_bits(get_u32(&bytes, 80 + 4)), 0.5);
+        // the out of range sample is clipped
+        assert_eq!(f32::from_bits(get_u32(&bytes, 80 + 20)), 1.0);
+    }
+
+    #[test]
+    fn pad_byte_of_odd_data_size() {
+        let mut wav_writer = WavWriter::new(Cursor::new(Vec::new()),
+                                            44100,
+                                            1,
+                                            SampleFormat::Int24)
+            .unwrap();
+        wav_writer.write_samples(&[0.5]).unwrap();
+        wav_writer.finalize().unwrap();
+        {
+            let bytes = wav_writer.writer.get_ref();
+            assert_eq!(get_u32(bytes, 64), 3);
+            assert_eq!(bytes.len(), 68 + 3 + 1);
+            assert_eq!(bytes[71], 0);
+            assert_eq!(get_u32(bytes, 4), bytes.len() as u32 - 8);
+        }
+        // the next samples overwrite the pad byte
+        wav_writer.write_samples(&[-0.5]).unwrap();
+        wav_writer.finalize().unwrap();
+        assert_eq!(*wav_writer.writer.get_ref(),
+                   render(44100, 1, SampleFormat::Int24, &[0.5, -0.5]));
+    }
+
+    #[test]
+    fn block_align_overflow() {
+        for &(sample_rate, channel_count) in &[(44100, ::std::u16::MAX), (::std::u32::MAX, 2)] {
+            match WavWriter::new(Cursor::new(Vec::new()),
+                                 sample_rate,
+                                 channel_count,
+                                 SampleFormat::Float32) {
+                Err(Error::Overflow) => {}
+                _ => panic!("Overflow expected"),
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_parameters() {
+        let format = SampleFormat::Int16;
+        assert!(WavWriter::new(Cursor::new(Vec::new()), 0, 1, format).is_err());
+        assert!(WavWriter::new(Cursor::new(Vec::new()), 44100, 0, format).is_err());
+        let mut wav_writer = WavWriter::new(Cursor::new(Vec::new()), 44100, 2, format).unwrap();
+        assert!(wav_writer.write_samples(&[0.0; 3]).is_err());
+    }
+}
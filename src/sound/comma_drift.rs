@@ -0,0 +1,387 @@
+use sound::*;
+use std::cell::{Cell, RefCell};
+
+/// The commas identified by default, from the
+/// [list of commas](https://en.wikipedia.org/wiki/Comma_(music)): the Pythagorean comma
+/// (531441:524288), the syntonic comma (81:80), the septimal comma (64:63) and the undecimal
+/// comma (33:32).
+pub const DRIFT_COMMAS_DEFAULT: &'static [&'static [i32]] = &[&[-19, 12],
+                                                               &[-4, 4, -1],
+                                                               &[6, -2, 0, -1],
+                                                               &[-5, 1, 0, 0, 1]];
+
+/// Corrective policy of the `DriftTracker`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum DriftCorrection {
+    /// No correction, the drift accumulates.
+    None,
+    /// The octaves are removed, the pitch stays within half an octave of the reference.
+    Octaves,
+    /// The identified commas are removed after each change (adaptive tuning), only the
+    /// residual remains.
+    Commas,
+}
+
+/// Tracks the pitch drift of successive interval changes. In just intonation a chord
+/// progression returning to the tonic usually does not return to the starting pitch, e.g.
+/// C-F-Dm-G-C moves down by a syntonic comma. After many repetitions the pitch can leave the
+/// hearing range.
+///
+/// The exact rational product of all the changes is accumulated as a `Monzo` (so it never
+/// overflows), the drift is reported in cents and as a ratio, and its commas are identified.
+/// An optional correction can be applied to keep the pitch near the reference.
+///
+/// The monzos are vectors, so each change allocates memory (and the comma identification is
+/// iterative): the changes are not real-time safe, they should be done outside of the audio
+/// callback.
+#[derive(Debug, Clone)]
+pub struct DriftTracker {
+    reference: Cell<SampleCalc>,
+    /// The product of all the changes.
+    product: RefCell<Monzo>,
+    /// The product of all the applied corrections.
+    correction: RefCell<Monzo>,
+    /// The commas used for the identification.
+    commas: Vec<Monzo>,
+    policy: DriftCorrection,
+}
+
+impl DriftTracker {
+    /// custom constructor, with the default commas and without correction.
+    pub fn new(reference: SampleCalc) -> SoundResult<DriftTracker> {
+        check_frequency(reference as f64)?;
+        Ok(DriftTracker {
+            reference: Cell::new(reference),
+            product: RefCell::new(Monzo::default()),
+            correction: RefCell::new(Monzo::default()),
+            commas: DRIFT_COMMAS_DEFAULT.iter()
+                .map(|exponents| Monzo::from_exponents(exponents))
+                .collect(),
+            policy: DriftCorrection::None,
+        })
+    }
+
+    /// Sets the commas used for the identification. Unisons and octaves are not accepted
+    /// (`Error::CommaInvalid`).
+    pub fn set_commas(&mut self, commas: Vec<Monzo>) -> SoundResult<&mut Self> {
+        if commas.iter().any(|comma| comma.get_prime_limit() < 3) {
+            return Err(Error::CommaInvalid);
+        }
+        self.commas = commas;
+        Ok(self)
+    }
+
+    /// Provides the commas used for the identification.
+    pub fn get_commas(&self) -> &[Monzo] {
+        &self.commas
+    }
+
+    /// Sets the corrective policy, it is applied from the next change.
+    pub fn set_correction(&mut self, policy: DriftCorrection) -> &mut Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Provides the corrective policy.
+    pub fn get_correction_policy(&self) -> DriftCorrection {
+        self.policy
+    }
+
+    /// Restarts the tracking from a new reference frequency.
+    pub fn reset(&self, reference: SampleCalc) -> SoundResult<()> {
+        check_frequency(reference as f64)?;
+        self.reference.set(reference);
+        *self.product.borrow_mut() = Monzo::default();
+        *self.correction.borrow_mut() = Monzo::default();
+        Ok(())
+    }
+
+    /// Provides the reference (starting) frequency.
+    pub fn get_reference(&self) -> SampleCalc {
+        self.reference.get()
+    }
+
+    /// Accumulates an interval change, applies the correction and provides the new frequency.
+    /// If the frequency leaves the hearing range the change is not accumulated.
+    pub fn change(&self, interval: Interval) -> SoundResult<SampleCalc> {
+        let change = self.get_change(interval)?;
+        let frequency = change.get_frequency();
+        self.commit(change);
+        Ok(frequency)
+    }
+
+    /// Calculates an interval change with the correction, without accumulating it. Returns an
+    /// error if the frequency leaves the hearing range. The change is accumulated by
+    /// `commit()`, so it can be dropped if a later step of the caller fails.
+    pub fn get_change(&self, interval: Interval) -> SoundResult<DriftChange> {
+        let product = self.product.borrow().add_multiple(&Monzo::from(interval), 1);
+        let mut correction = self.correction.borrow().clone();
+        let drift = product.add_multiple(&correction, 1);
+        match self.policy {
+            DriftCorrection::None => {}
+            DriftCorrection::Octaves => {
+                let octaves = (get_ratio(&drift).log2()).round() as i32;
+                correction = correction.add_multiple(&Monzo::from_exponents(&[octaves]), -1);
+            }
+            DriftCorrection::Commas => {
+                let (commas, _) = self.identify_commas(&drift);
+                for (comma, count) in commas {
+                    correction = correction.add_multiple(&comma, -count);
+                }
+            }
+        }
+        let frequency = self.reference.get() as f64 *
+                        get_ratio(&product.add_multiple(&correction, 1));
+        check_frequency(frequency)?;
+        Ok(DriftChange {
+            product: product,
+            correction: correction,
+            frequency: frequency as SampleCalc,
+        })
+    }
+
+    /// Accumulates a change calculated by `get_change()`. It replaces the accumulated state,
+    /// so the change has to be calculated after the previous commit.
+    pub fn commit(&self, change: DriftChange) {
+        *self.product.borrow_mut() = change.product;
+        *self.correction.borrow_mut() = change.correction;
+    }
+
+    /// Provides the actual frequency: the reference changed by the drift.
+    pub fn get_frequency(&self) -> SampleCalc {
+        (self.reference.get() as f64 * get_ratio(&self.get_drift())) as SampleCalc
+    }
+
+    /// Provides the exact product of all the changes (without the corrections).
+    pub fn get_product(&self) -> Monzo {
+        self.product.borrow().clone()
+    }
+
+    /// Provides the product of all the applied corrections.
+    pub fn get_correction(&self) -> Monzo {
+        self.correction.borrow().clone()
+    }
+
+    /// Provides the drift from the reference: the product of the changes and the corrections.
+    pub fn get_drift(&self) -> Monzo {
+        self.get_product() + self.get_correction()
+    }
+
+    /// Provides the drift in cents.
+    pub fn get_drift_cents(&self) -> SampleCalc {
+        self.get_drift().get_cents()
+    }
+
+    /// Provides the drift as a ratio. Returns `Error::Overflow` if it's terms can not be
+    /// represented by `IntervalTerm`.
+    pub fn get_drift_ratio(&self) -> SoundResult<Interval> {
+        self.get_drift().get_interval()
+    }
+
+    /// Identifies the commas of the drift. Provides the (comma, count) pairs with non-zero
+    /// counts, and the residual, which is the simplest interval reachable by removing commas
+    /// (e.g. octaves or the interval between the reference and the actual chord root).
+    pub fn get_drift_commas(&self) -> (Vec<(Monzo, i32)>, Monzo) {
+        self.identify_commas(&self.get_drift())
+    }
+
+    /// Decomposes the monzo. First, starting from the highest prime, each comma eliminates the
+    /// exponent of it's highest prime (as much as possible). Then the commas are removed or
+    /// added back one by one while the residual gets simpler (it's octave equivalent Tenney
+    /// height decreases), so the intended intervals (e.g. a major third) are not mistaken for
+    /// commas.
+    fn identify_commas(&self, monzo: &Monzo) -> (Vec<(Monzo, i32)>, Monzo) {
+        let mut residual = monzo.clone();
+        let mut counts = vec![0; self.commas.len()];
+        let mut order: Vec<usize> = (0..self.commas.len()).collect();
+        order.sort_by(|&a, &b| {
+            self.commas[b].get_prime_limit().cmp(&self.commas[a].get_prime_limit())
+        });
+        let mut prime_previous = 0;
+        for index in order {
+            let comma = &self.commas[index];
+            let prime = comma.get_prime_limit();
+            if prime == prime_previous {
+                continue;
+            }
+            prime_previous = prime;
+            let ratio = residual.get_exponent(prime) as f64 / comma.get_exponent(prime) as f64;
+            let count = ratio.round() as i32;
+            if count != 0 {
+                residual = residual - comma.clone() * count;
+                counts[index] += count;
+            }
+        }
+        let mut height = get_octave_free_height(&residual);
+        let mut improved = true;
+        while improved {
+            improved = false;
+            for (index, comma) in self.commas.iter().enumerate() {
+                for &sign in &[1, -1] {
+                    let candidate = residual.clone() - comma.clone() * sign;
+                    let candidate_height = get_octave_free_height(&candidate);
+                    if candidate_height < height - 1e-9 {
+                        residual = candidate;
+                        height = candidate_height;
+                        counts[index] += sign;
+                        improved = true;
+                    }
+                }
+            }
+        }
+        let commas = self.commas
+            .iter()
+            .cloned()
+            .zip(counts)
+            .filter(|&(_, count)| count != 0)
+            .collect();
+        (commas, residual)
+    }
+}
+
+/// An interval change calculated by `DriftTracker::get_change()`, which is not accumulated yet.
+#[derive(Debug, Clone)]
+pub struct DriftChange {
+    /// The product of all the changes, including this one.
+    product: Monzo,
+    /// The product of all the corrections, including the one of this change.
+    correction: Monzo,
+    frequency: SampleCalc,
+}
+
+impl DriftChange {
+    /// Provides the new frequency.
+    pub fn get_frequency(&self) -> SampleCalc {
+        self.frequency
+    }
+}
+
+/// Provides the ratio of the monzo with double precision.
+fn get_ratio(monzo: &Monzo) -> f64 {
+    monzo.get_factors()
+        .iter()
+        .map(|&(prime, exponent)| (prime as f64).powi(exponent))
+        .product()
+}
+
+/// Provides the Tenney height without the factors of 2.
+fn get_octave_free_height(monzo: &Monzo) -> f64 {
+    monzo.get_factors()
+        .iter()
+        .filter(|&&(prime, _)| prime > 2)
+        .map(|&(prime, exponent)| exponent.abs() as f64 * (prime as f64).log2())
+        .sum()
+}
+
+/// Checks whether the frequency is in the hearing range.
+fn check_frequency(frequency: f64) -> SoundResult<()> {
+    if !(frequency > 0.0) {
+        return Err(Error::FrequencyInvalid);
+    }
+    if frequency < TONE_FREQUENCY_MIN as f64 {
+        return Err(Error::FrequencyTooLow);
+    }
+    if frequency > TONE_FREQUENCY_MAX as f64 {
+        return Err(Error::FrequencyTooHigh);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use sound::*;
+
+    /// I–vi–ii–V–I in just intonation, the roots move by 5:3, 2:3, 4:3 and 2:3.
+    fn play_progression(drift_tracker: &DriftTracker) -> SampleCalc {
+        let mut frequency = 0.0;
+        for &(numerator, denominator) in &[(5, 3), (2, 3), (4, 3), (2, 3)] {
+            frequency = drift_tracker.change(Interval::new(numerator, denominator).unwrap())
+                .unwrap();
+        }
+        frequency
+    }
+
+    fn get_syntonic_comma() -> Monzo {
+        Monzo::from_exponents(&[-4, 4, -1])
+    }
+
+    #[test]
+    fn syntonic_comma_pump() {
+        let drift_tracker = DriftTracker::new(264.0).unwrap();
+        let frequency = play_progression(&drift_tracker);
+        assert!((frequency - 264.0 * 80.0 / 81.0).abs() < 1e-3);
+        assert_eq!(drift_tracker.get_drift(), -get_syntonic_comma());
+        assert!((drift_tracker.get_drift_cents() + 21.506).abs() < 0.001);
+        let ratio = drift_tracker.get_drift_ratio().unwrap();
+        assert_eq!((ratio.get_numerator(), ratio.get_denominator()), (80, 81));
+        let (commas, residual) = drift_tracker.get_drift_commas();
+        assert_eq!(commas, vec![(get_syntonic_comma(), -1)]);
+        assert!(residual.is_unison());
+    }
+
+    #[test]
+    fn septimal_comma() {
+        // a harmonic seventh up, then two fourths down: 7:4 · 3:4 · 3:4 = 63:64
+        let drift_tracker = DriftTracker::new(440.0).unwrap();
+        for &(numerator, denominator) in &[(7, 4), (3, 4), (3, 4)] {
+            let _ = drift_tracker.change(Interval::new(numerator, denominator).unwrap())
+                .unwrap();
+        }
+        let septimal_comma = Monzo::from_exponents(&[6, -2, 0, -1]);
+        let (commas, residual) = drift_tracker.get_drift_commas();
+        assert_eq!(commas, vec![(septimal_comma, -1)]);
+        assert!(residual.is_unison());
+        // an intended interval is not mistaken for a comma
+        let _ = drift_tracker.change(Interval::new(5, 4).unwrap()).unwrap();
+        let (commas, residual) = drift_tracker.get_drift_commas();
+        assert_eq!(commas.len(), 1);
+        assert_eq!(residual, Monzo::from_exponents(&[-2, 0, 1]));
+    }
+
+    #[test]
+    fn correction_policies() {
+        let mut drift_tracker = DriftTracker::new(264.0).unwrap();
+        assert_eq!(drift_tracker.get_correction_policy(), DriftCorrection::None);
+        let _ = drift_tracker.set_correction(DriftCorrection::Commas);
+        let frequency = play_progression(&drift_tracker);
+        assert!((frequency - 264.0).abs() < 1e-3);
+        assert_eq!(drift_tracker.get_product(), -get_syntonic_comma());
+        assert_eq!(drift_tracker.get_correction(), get_syntonic_comma());
+        assert!(drift_tracker.get_drift().is_unison());
+
+        let _ = drift_tracker.set_correction(DriftCorrection::Octaves);
+        drift_tracker.reset(264.0).unwrap();
+        let frequency = drift_tracker.change(Interval::new(3, 1).unwrap()).unwrap();
+        assert!((frequency - 264.0 * 0.75).abs() < 1e-3);
+        assert_eq!(drift_tracker.get_correction(), Monzo::from_exponents(&[-2]));
+        for _ in 0..4 {
+            let _ = drift_tracker.change(Interval::new(2, 1).unwrap()).unwrap();
+        }
+        assert!((drift_tracker.get_frequency() - 264.0 * 0.75).abs() < 1e-3);
+        let frequency = play_progression(&drift_tracker);
+        assert!((frequency - 264.0 * 0.75 * 80.0 / 81.0).abs() < 1e-3);
+
+        let _ = drift_tracker.set_correction(DriftCorrection::None);
+        drift_tracker.reset(264.0).unwrap();
+        for _ in 0..6 {
+            let _ = drift_tracker.change(Interval::new(2, 1).unwrap()).unwrap();
+        }
+        // 264 Hz · 2^7 is out of the hearing range, the change is not accumulated
+        assert_eq!(drift_tracker.get_product(), Monzo::from_exponents(&[6]));
+        assert!(drift_tracker.change(Interval::new(2, 1).unwrap()).is_err());
+        assert_eq!(drift_tracker.get_product(), Monzo::from_exponents(&[6]));
+    }
+
+    #[test]
+    fn invalid_commas() {
+        let mut drift_tracker = DriftTracker::new(440.0).unwrap();
+        match drift_tracker.set_commas(vec![Monzo::from_exponents(&[1])]) {
+            Err(Error::CommaInvalid) => {}
+            _ => panic!("CommaInvalid expected"),
+        }
+        assert!(drift_tracker.set_commas(vec![Monzo::default()]).is_err());
+        assert_eq!(drift_tracker.get_commas().len(), DRIFT_COMMAS_DEFAULT.len());
+        let _ = drift_tracker.set_commas(vec![get_syntonic_comma()]).unwrap();
+        assert_eq!(drift_tracker.get_commas(), &[get_syntonic_comma()]);
+    }
+}
@@ -0,0 +1,197 @@
+use sound::*;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::sync::Arc;
+use std::sync::atomic::{self, AtomicUsize};
+
+/// The default number of scheduled commands, which can be stored without memory allocation.
+pub const SCHEDULED_COMMAND_CAPACITY: usize = 256;
+
+/// A command for a `SoundGenerator`, with an optional timestamp.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct TimedCommand<T> {
+    /// The message for the generator.
+    pub command: T,
+    /// The frame position of the output stream, where the command has to be applied. `None`
+    /// means as soon as possible (at the beginning of the next buffer). Positions which are
+    /// already in the past are also applied at the beginning of the next buffer.
+    pub frame: Option<u64>,
+}
+
+impl<T> TimedCommand<T> {
+    /// A command to be applied as soon as possible.
+    pub fn immediate(command: T) -> TimedCommand<T> {
+        TimedCommand {
+            command: command,
+            frame: None,
+        }
+    }
+
+    /// A command to be applied at the given frame position of the output stream.
+    pub fn at(command: T, frame: u64) -> TimedCommand<T> {
+        TimedCommand {
+            command: command,
+            frame: Some(frame),
+        }
+    }
+}
+
+/// A command waiting in the `CommandScheduler`.
+struct ScheduledCommand<T> {
+    frame: u64,
+    /// The order of arrival.
+    sequence: u64,
+    command: T,
+}
+
+impl<T> PartialEq for ScheduledCommand<T> {
+    fn eq(&self, other: &Self) -> bool {
+        (self.frame == other.frame) && (self.sequence == other.sequence)
+    }
+}
+
+impl<T> Eq for ScheduledCommand<T> {}
+
+impl<T> PartialOrd for ScheduledCommand<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> Ord for ScheduledCommand<T> {
+    /// Reversed, so the `BinaryHeap` provides the earliest command first.
+    fn cmp(&self, other: &Self) -> Ordering {
+        (other.frame, other.sequence).cmp(&(self.frame, self.sequence))
+    }
+}
+
+/// Renders a `SoundGenerator` for the backends, applying the commands sample-accurately.
+///
+/// When a command is due inside a buffer, the rendering is split at its offset: the generator
+/// renders the samples before the command, processes it, then renders the rest. Thus
+/// generators which receive timestamped commands must produce exactly `sample_count` samples
+/// in `get_channel_samples()`, at the beginning of the result buffers.
+///
+/// The commands are kept in a preallocated binary heap, so adding and processing them takes
+/// O(log n) time without memory allocation. When it is full, the new commands are dropped and
+/// counted.
+pub struct CommandScheduler<T> {
+    /// The frame position of the next buffer.
+    frame_position: u64,
+    /// Commands waiting for being processed, the earliest one is on the top.
+    scheduled: BinaryHeap<ScheduledCommand<T>>,
+    /// The maximal number of commands waiting.
+    capacity: usize,
+    /// The arrival counter of the commands.
+    sequence: u64,
+    /// The number of commands rejected because of a full schedule.
+    dropped_count: Arc<AtomicUsize>,
+    /// Temporary buffers for the split rendering.
+    segment_buffers: Vec<Vec<SampleCalc>>,
+}
+
+impl<T> CommandScheduler<T> {
+    /// custom constructor, at most `capacity` commands can wait for being processed. The
+    /// backends use the size of their command queue.
+    pub fn new(buffer_size: usize,
+               channel_count: usize,
+               capacity: usize)
+               -> CommandScheduler<T> {
+        CommandScheduler {
+            frame_position: 0,
+            scheduled: BinaryHeap::with_capacity(capacity),
+            capacity: capacity,
+            sequence: 0,
+            dropped_count: Arc::new(AtomicUsize::new(0)),
+            segment_buffers: vec![vec![0.0; buffer_size]; channel_count],
+        }
+    }
+
+    /// Adds a command to the schedule. Commands of the same frame position are processed in
+    /// the order of their arrival. If the schedule is full, the command is dropped and
+    /// `BackendError::QueueFull` is returned.
+    pub fn push(&mut self, timed_command: TimedCommand<T>) -> BackendResult<()> {
+        if self.scheduled.len() >= self.capacity {
+            let _ = self.dropped_count.fetch_add(1, atomic::Ordering::Relaxed);
+            return Err(BackendError::QueueFull);
+        }
+        let frame = timed_command.frame.unwrap_or(0).max(self.frame_position);
+        self.scheduled.push(ScheduledCommand {
+            frame: frame,
+            sequence: self.sequence,
+            command: timed_command.command,
+        });
+        self.sequence += 1;
+        Ok(())
+    }
+
+    /// Sets the counter of the dropped commands, so it can be shared with the command queue.
+    pub fn set_dropped_counter(&mut self, dropped_count: Arc<AtomicUsize>) {
+        self.dropped_count = dropped_count;
+    }
+
+    /// Returns the number of commands dropped because of a full schedule.
+    pub fn get_dropped_count(&self) -> usize {
+        self.dropped_count.load(atomic::Ordering::Relaxed)
+    }
+
+    /// Renders the next `frame_count` frames into the `result` buffers (one buffer per
+    /// channel), processing all commands which are due in the meantime.
+    pub fn render(&mut self,
+                  generator: &mut SoundGenerator<Command = T>,
+                  frame_count: usize,
+                  result: &mut [Vec<SampleCalc>])
+                  -> SoundResult<()> {
+        if result.len() != self.segment_buffers.len() {
+            return Err(Error::ChannelInvalid);
+        }
+        if self.segment_buffers.first().map_or(false, |segment| segment.len() < frame_count) {
+            return Err(Error::BufferSize);
+        }
+        for channel in result.iter() {
+            if channel.len() < frame_count {
+                return Err(Error::BufferSize);
+            }
+        }
+        let mut offset = 0;
+        loop {
+            let frame_position = self.frame_position + offset as u64;
+            while self.scheduled.peek().map_or(false, |item| item.frame <= frame_position) {
+                if let Some(item) = self.scheduled.pop() {
+                    generator.process_command(item.command);
+                }
+            }
+            let segment_end = match self.scheduled.peek() {
+                Some(item) if item.frame < self.frame_position + frame_count as u64 => {
+                    (item.frame - self.frame_position) as usize
+                }
+                _ => frame_count,
+            };
+            if (offset == 0) && (segment_end == frame_count) {
+                generator.get_channel_samples(frame_count, result);
+            } else {
+                let segment_length = segment_end - offset;
+                generator.get_channel_samples(segment_length, &mut self.segment_buffers);
+                for (channel, segment) in result.iter_mut().zip(self.segment_buffers.iter()) {
+                    channel[offset..segment_end].copy_from_slice(&segment[..segment_length]);
+                }
+            }
+            offset = segment_end;
+            if offset >= frame_count {
+                break;
+            }
+        }
+        self.frame_position += frame_count as u64;
+        Ok(())
+    }
+
+    /// Returns the frame position of the next buffer.
+    pub fn get_frame_position(&self) -> u64 {
+        self.frame_position
+    }
+
+    /// Returns the number of commands not yet processed.
+    pub fn get_pending_count(&self) -> usize {
+        self.scheduled.len()
+    }
+}
@@ -0,0 +1,253 @@
+use sound::*;
+use std::cell::UnsafeCell;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The default capacity of the command queue of the backends.
+pub const COMMAND_QUEUE_SIZE_DEFAULT: usize = 256;
+
+/// Preallocated single-producer single-consumer ring buffer. One slot is always kept empty, so
+/// the full and the empty states can be distinguished. It is lock-free: the producer and the
+/// consumer synchronize only through the atomic `head` and `tail` indices. The slots are
+/// `Option`s, so the items are moved in and out without any bounds on their type.
+struct RingBuffer<T> {
+    slots: Box<[UnsafeCell<Option<T>>]>,
+    /// The index of the next slot to be read, it is only modified by the consumer.
+    head: AtomicUsize,
+    /// The index of the next slot to be written, it is only modified by the producer.
+    tail: AtomicUsize,
+}
+
+// A slot is written by the producer only while it is outside the `head..tail` range, and read
+// by the consumer only while it is inside, so they never access the same slot at the same
+// time. There is only one producer (`CommandSender`) and one consumer (`CommandReceiver`),
+// both of them need `&mut self` for their operations.
+#[allow(unsafe_code)]
+unsafe impl<T: Send> Sync for RingBuffer<T> {}
+
+impl<T> RingBuffer<T> {
+    fn new(capacity: usize) -> RingBuffer<T> {
+        RingBuffer {
+            slots: (0..capacity + 1).map(|_| UnsafeCell::new(None)).collect(),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+        }
+    }
+
+    fn get_next(&self, index: usize) -> usize {
+        (index + 1) % self.slots.len()
+    }
+
+    /// Writes an item, returns `false` if the buffer is full. It must be called only by the
+    /// producer.
+    #[allow(unsafe_code)]
+    fn push(&self, item: T) -> bool {
+        let tail = self.tail.load(Ordering::Relaxed);
+        let next = self.get_next(tail);
+        if next == self.head.load(Ordering::Acquire) {
+            return false;
+        }
+        unsafe {
+            *self.slots[tail].get() = Some(item);
+        }
+        self.tail.store(next, Ordering::Release);
+        true
+    }
+
+    /// Reads an item, returns `None` if the buffer is empty. It must be called only by the
+    /// consumer.
+    #[allow(unsafe_code)]
+    fn pop(&self) -> Option<T> {
+        let head = self.head.load(Ordering::Relaxed);
+        if head == self.tail.load(Ordering::Acquire) {
+            return None;
+        }
+        let item = unsafe { (*self.slots[head].get()).take() };
+        self.head.store(self.get_next(head), Ordering::Release);
+        item
+    }
+
+    /// The number of items in the buffer.
+    fn count(&self) -> usize {
+        let head = self.head.load(Ordering::Acquire);
+        let tail = self.tail.load(Ordering::Acquire);
+        (tail + self.slots.len() - head) % self.slots.len()
+    }
+
+    fn capacity(&self) -> usize {
+        self.slots.len() - 1
+    }
+}
+
+/// Creates a bounded single-producer single-consumer command queue, with the given capacity.
+/// It is a lock-free ring buffer, which is allocated in advance: neither sending nor
+/// receiving blocks or allocates memory, so the receiver can be used in the real-time audio
+/// callback.
+pub fn command_queue<T>(size: usize) -> (CommandSender<T>, CommandReceiver<T>) {
+    let ring_buffer = Arc::new(RingBuffer::new(size));
+    let sender = CommandSender {
+        ring_buffer: ring_buffer.clone(),
+        dropped_count: Arc::new(AtomicUsize::new(0)),
+    };
+    let receiver = CommandReceiver { ring_buffer: ring_buffer };
+    (sender, receiver)
+}
+
+/// The sending half of a command queue.
+pub struct CommandSender<T> {
+    ring_buffer: Arc<RingBuffer<TimedCommand<T>>>,
+    /// The number of commands rejected because of a full queue.
+    dropped_count: Arc<AtomicUsize>,
+}
+
+impl<T> CommandSender<T> {
+    /// Puts a command into the queue. If the queue is full, the command is dropped and
+    /// `BackendError::QueueFull` is returned.
+    pub fn send(&mut self, timed_command: TimedCommand<T>) -> BackendResult<()> {
+        if self.ring_buffer.push(timed_command) {
+            Ok(())
+        } else {
+            let _ = self.dropped_count.fetch_add(1, Ordering::Relaxed);
+            Err(BackendError::QueueFull)
+        }
+    }
+
+    /// Returns the number of commands waiting in the queue.
+    pub fn get_depth(&self) -> usize {
+        self.ring_buffer.count()
+    }
+
+    /// Returns the capacity of the queue.
+    pub fn get_capacity(&self) -> usize {
+        self.ring_buffer.capacity()
+    }
+
+    /// Returns the number of commands dropped because of a full queue.
+    pub fn get_dropped_count(&self) -> usize {
+        self.dropped_count.load(Ordering::Relaxed)
+    }
+
+    /// Returns the shared counter of the dropped commands. The `CommandScheduler` of the
+    /// receiver side can count it's dropped commands into it.
+    pub fn get_dropped_counter(&self) -> Arc<AtomicUsize> {
+        self.dropped_count.clone()
+    }
+}
+
+/// The receiving half of a command queue.
+pub struct CommandReceiver<T> {
+    ring_buffer: Arc<RingBuffer<TimedCommand<T>>>,
+}
+
+impl<T> CommandReceiver<T> {
+    /// Moves all the queued commands into the scheduler. The commands which do not fit into
+    /// the scheduler are dropped (and counted by the scheduler). At most the capacity of the
+    /// queue is moved, so a fast sender can not keep it busy forever.
+    pub fn receive_all(&mut self, scheduler: &mut CommandScheduler<T>) {
+        for _ in 0..self.ring_buffer.capacity() {
+            match self.ring_buffer.pop() {
+                Some(timed_command) => {
+                    let _ = scheduler.push(timed_command);
+                }
+                None => break,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sound::*;
+    use std::thread;
+    use super::*;
+
+    /// Records the received commands.
+    struct Recorder {
+        commands: Vec<u32>,
+    }
+
+    impl SoundGenerator for Recorder {
+        type Command = u32;
+
+        fn get_samples(&mut self, _sample_count: usize, _result: &mut Vec<SampleCalc>) {}
+
+        fn process_command(&mut self, command: u32) {
+            self.commands.push(command);
+        }
+    }
+
+    #[test]
+    fn full_queue() {
+        let (mut sender, mut receiver) = command_queue(4);
+        assert_eq!(sender.get_capacity(), 4);
+        for command in 0..4 {
+            sender.send(TimedCommand::immediate(command)).unwrap();
+        }
+        assert_eq!(sender.get_depth(), 4);
+        assert!(sender.send(TimedCommand::immediate(4)).is_err());
+        assert_eq!(sender.get_dropped_count(), 1);
+        let mut scheduler = CommandScheduler::new(1, 1, 16);
+        receiver.receive_all(&mut scheduler);
+        assert_eq!(sender.get_depth(), 0);
+        assert_eq!(scheduler.get_pending_count(), 4);
+        sender.send(TimedCommand::immediate(5)).unwrap();
+        assert_eq!(sender.get_depth(), 1);
+    }
+
+    #[test]
+    fn threads_keep_the_order() {
+        const COUNT: u32 = 10000;
+        let (mut sender, mut receiver) = command_queue(16);
+        let producer = thread::spawn(move || {
+            let mut command = 0;
+            while command < COUNT {
+                if sender.send(TimedCommand::immediate(command)).is_ok() {
+                    command += 1;
+                } else {
+                    thread::yield_now();
+                }
+            }
+        });
+        let mut generator = Recorder { commands: Vec::new() };
+        let mut scheduler = CommandScheduler::new(1, 1, 16);
+        let mut buffers = vec![vec![0.0; 1]];
+        while generator.commands.len() < COUNT as usize {
+            receiver.receive_all(&mut scheduler);
+            scheduler.render(&mut generator, 1, &mut buffers).unwrap();
+        }
+        producer.join().unwrap();
+        assert_eq!(generator.commands, (0..COUNT).collect::<Vec<_>>());
+        assert_eq!(scheduler.get_dropped_count(), 0);
+    }
+
+    #[test]
+    fn stress_two_threads() {
+        // boxed items: a torn or duplicated slot would be detected as a wrong value or as a
+        // double free
+        const COUNT: usize = 200_000;
+        for &capacity in &[1, 7, 64] {
+            let ring_buffer = Arc::new(RingBuffer::new(capacity));
+            let producer_buffer = ring_buffer.clone();
+            let producer = thread::spawn(move || {
+                for item in 0..COUNT {
+                    while !producer_buffer.push(Box::new(item)) {
+                        thread::yield_now();
+                    }
+                }
+            });
+            let mut expected = 0;
+            while expected < COUNT {
+                match ring_buffer.pop() {
+                    Some(item) => {
+                        assert_eq!(*item, expected);
+                        assert!(ring_buffer.count() <= capacity);
+                        expected += 1;
+                    }
+                    None => thread::yield_now(),
+                }
+            }
+            producer.join().unwrap();
+            assert!(ring_buffer.pop().is_none());
+        }
+    }
+}
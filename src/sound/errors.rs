@@ -1,9 +1,9 @@
 
 #[cfg(feature = "be-portaudio")]
-use sound::backend_portaudio::*;
+use portaudio as pa;
 #[cfg(feature = "be-rsoundio")]
-use sound::backend_rsoundio::*;
-use std::{error, fmt};
+use rsoundio;
+use std::{error, fmt, io};
 
 /// Return type for the sound module functions.
 pub type SoundResult<T> = Result<T, Error>;
@@ -34,6 +34,8 @@ pub enum Error {
     FrequencyInvalid,
     /// This frequency function is a source, it can not use an input frequency buffer.
     FrequencySource,
+    /// This frequency function needs an input frequency buffer.
+    FrequencyInputMissing,
     /// A rate must be positive.
     RateInvalid,
     /// Amplitude cannot be negative.
@@ -48,6 +50,18 @@ pub enum Error {
     DurationInvalid,
     /// Channel of the given number does not exist.
     ChannelInvalid,
+    /// Pan position is out of the [-1.0, 1.0] range.
+    PanInvalid,
+    /// The angle is out of the allowed range.
+    AngleInvalid,
+    /// The inharmonicity coefficient can not be negative.
+    InharmonicityInvalid,
+    /// Pulse width is out of the (0.0, 1.0) range.
+    PulseWidthInvalid,
+    /// The position is out of the allowed range.
+    PositionInvalid,
+    /// Bandwidth must be wider than unison.
+    BandwidthInvalid,
     /// Beats per minute must be positive.
     TempoInvalid,
     /// Timing option does not match the method.
@@ -64,6 +78,16 @@ pub enum Error {
     ItemInvalid,
     /// Overflow occured during calculations.
     Overflow,
+    /// The text can not be parsed.
+    ParseInvalid,
+    /// The tolerance must be positive.
+    ToleranceInvalid,
+    /// The prime limit must be at least 2.
+    PrimeLimitInvalid,
+    /// No approximation satisfies the given limits.
+    ApproximationNotFound,
+    /// The comma must contain other primes than 2.
+    CommaInvalid,
 }
 
 impl fmt::Display for Error {
@@ -87,6 +111,7 @@ impl error::Error for Error {
             FrequencyTooHigh => "frequency exceeds the hearing range",
             FrequencyInvalid => "frequency can not be zero or negative",
             FrequencySource => "input frequency buffer can not be used",
+            FrequencyInputMissing => "input frequency buffer is missing",
             RateInvalid => "invalid rate",
             AmplitudeInvalid => "invalid amplitude",
             AmplitudeTimeInvalid => "invalid amplitude change time",
@@ -94,6 +119,12 @@ impl error::Error for Error {
             PeriodInvalid => "invalid period",
             DurationInvalid => "invalid duration",
             ChannelInvalid => "invalid channel",
+            PanInvalid => "invalid pan position",
+            AngleInvalid => "invalid angle",
+            InharmonicityInvalid => "invalid inharmonicity",
+            PulseWidthInvalid => "invalid pulse width",
+            PositionInvalid => "invalid position",
+            BandwidthInvalid => "invalid bandwidth",
             TempoInvalid => "beats per minute must be positive",
             TimingInvalid => "invalid timing option",
             ProgressInvalid => "invalid progress option",
@@ -102,6 +133,11 @@ impl error::Error for Error {
             SequenceEmpty => "sequence has no items",
             ItemInvalid => "the item does not exist",
             Overflow => "overflow",
+            ParseInvalid => "invalid text",
+            ToleranceInvalid => "invalid tolerance",
+            PrimeLimitInvalid => "invalid prime limit",
+            ApproximationNotFound => "no approximation found",
+            CommaInvalid => "invalid comma",
         }
     }
 
@@ -119,3 +155,90 @@ impl From<BackendError> for Error {
         Error::Backend(e)
     }
 }
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Backend(BackendError::from(e))
+    }
+}
+
+/// Return type for the backend functions.
+pub type BackendResult<T> = Result<T, BackendError>;
+
+/// Wrapper for the propagation of backend specific errors.
+#[derive(Debug, Copy, Clone)]
+pub enum BackendError {
+    /// Errors of the PortAudio backend.
+    #[cfg(feature = "be-portaudio")]
+    PortAudio(pa::Error),
+    /// Errors of the libsoundio backend.
+    #[cfg(feature = "be-rsoundio")]
+    SoundIo(rsoundio::SioError),
+    /// There is no output device available.
+    DeviceUnavailable,
+    /// The output format (sample format or channel count) is invalid.
+    FormatInvalid,
+    /// Input/output error of a file backend.
+    Io(io::ErrorKind),
+    /// The SoundGenerator is disconnected, could not recieve the command
+    Disconnected,
+    /// The command queue is full, the command has been dropped.
+    QueueFull,
+    /// The rendering of the sound has failed, the output is silent.
+    RenderFailed,
+}
+
+impl fmt::Display for BackendError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use std::error::Error;
+        f.write_str(self.description())
+    }
+}
+
+impl error::Error for BackendError {
+    fn description(&self) -> &str {
+        use self::BackendError::*;
+        match *self {
+            #[cfg(feature = "be-portaudio")]
+            PortAudio(ref err) => err.description(),
+            #[cfg(feature = "be-rsoundio")]
+            SoundIo(ref err) => err.description(),
+            DeviceUnavailable => "no output device available",
+            FormatInvalid => "invalid output format",
+            Io(_) => "input/output error",
+            Disconnected => "SoundGenerator is disconnected",
+            QueueFull => "command queue is full",
+            RenderFailed => "rendering of the sound has failed",
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            #[cfg(feature = "be-portaudio")]
+            BackendError::PortAudio(ref err) => Some(err),
+            #[cfg(feature = "be-rsoundio")]
+            BackendError::SoundIo(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "be-portaudio")]
+impl From<pa::Error> for BackendError {
+    fn from(e: pa::Error) -> Self {
+        BackendError::PortAudio(e)
+    }
+}
+
+#[cfg(feature = "be-rsoundio")]
+impl From<rsoundio::SioError> for BackendError {
+    fn from(e: rsoundio::SioError) -> Self {
+        BackendError::SoundIo(e)
+    }
+}
+
+impl From<io::Error> for BackendError {
+    fn from(e: io::Error) -> Self {
+        BackendError::Io(e.kind())
+    }
+}
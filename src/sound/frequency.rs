@@ -1,5 +1,6 @@
 use sound::*;
-use std::cell::Cell;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
 // use std::fmt;
 // use rayon::prelude::*;
 
@@ -13,23 +14,72 @@ pub trait FrequencyFunction {
            -> SoundResult<()>;
 }
 
-/// Frequency is not changing by time.
+/// Frequency is not changing by time, except for the glides of the optional portamento.
 #[derive(Debug, Clone)]
 pub struct FrequencyConst {
     frequency: Cell<SampleCalc>,
+    portamento: RefCell<Option<Portamento>>,
+    drift_tracker: RefCell<Option<DriftTracker>>,
 }
 
 impl FrequencyConst {
     /// custom constructor
     pub fn new(frequency: SampleCalc) -> SoundResult<FrequencyConst> {
-        Ok(FrequencyConst { frequency: Cell::new(frequency) })
+        Ok(FrequencyConst {
+            frequency: Cell::new(frequency),
+            portamento: RefCell::new(None),
+            drift_tracker: RefCell::new(None),
+        })
     }
 
-    /// Change frequency in harmony with it's previous value.
+    /// Change frequency in harmony with it's previous value. With portamento the frequency
+    /// glides from it's actual value to the new one. With drift tracker the change is
+    /// accumulated, and the tracker's correction is applied (it allocates memory, see
+    /// `DriftTracker`). If any step fails, neither the frequency nor the drift is changed.
     pub fn change(&self, interval: Interval) -> SoundResult<&FrequencyConst> {
-        self.frequency.set(interval.change_frequency(self.frequency.get())?);
+        let drift_tracker = self.drift_tracker.borrow();
+        let drift_change = match *drift_tracker {
+            Some(ref drift_tracker) => Some(drift_tracker.get_change(interval)?),
+            None => None,
+        };
+        let frequency = match drift_change {
+            Some(ref drift_change) => drift_change.get_frequency(),
+            None => interval.change_frequency(self.frequency.get())?,
+        };
+        if let Some(ref portamento) = *self.portamento.borrow() {
+            portamento.set_target(frequency)?;
+        }
+        if let (Some(ref drift_tracker), Some(drift_change)) = (drift_tracker.as_ref(),
+                                                                drift_change) {
+            drift_tracker.commit(drift_change);
+        }
+        self.frequency.set(frequency);
         Ok(self)
     }
+
+    /// Sets the portamento used by `change()`, `None` switches it off (the frequency jumps).
+    pub fn set_portamento(&self, portamento: Option<Portamento>) -> SoundResult<()> {
+        if let Some(ref portamento) = portamento {
+            portamento.jump(self.frequency.get())?;
+        }
+        *self.portamento.borrow_mut() = portamento;
+        Ok(())
+    }
+
+    /// Sets the drift tracker used by `change()`, the actual frequency becomes it's reference.
+    /// `None` switches the tracking off.
+    pub fn set_drift_tracker(&self, drift_tracker: Option<DriftTracker>) -> SoundResult<()> {
+        if let Some(ref drift_tracker) = drift_tracker {
+            drift_tracker.reset(self.frequency.get())?;
+        }
+        *self.drift_tracker.borrow_mut() = drift_tracker;
+        Ok(())
+    }
+
+    /// Provides a copy of the drift tracker (with the accumulated drift).
+    pub fn get_drift_tracker(&self) -> Option<DriftTracker> {
+        self.drift_tracker.borrow().clone()
+    }
 }
 
 impl FrequencyFunction for FrequencyConst {
@@ -41,6 +91,10 @@ impl FrequencyFunction for FrequencyConst {
         if base_frequency.is_some() {
             return Err(Error::FrequencySource);
         }
+        if let Some(ref portamento) = *self.portamento.borrow() {
+            portamento.get(result);
+            return Ok(());
+        }
         for item in result.iter_mut() {
             *item = self.frequency.get();
         }
@@ -48,14 +102,558 @@ impl FrequencyFunction for FrequencyConst {
     }
 }
 
-/// Changing frequency linearly. Linearity means constant multiplication over time slices.
-#[allow(dead_code)]
-pub struct FrequencyChangeLinear {
+/// Progress of the pitch changes, from 0.0 to 1.0 during the timing's duration. With
+/// `TimingOption::None` the duration is zero, the change is immediate.
+#[derive(Debug, Clone)]
+struct PitchChangeProgress {
     sample_time: SampleCalc,
+    timing: Cell<TimingOption>,
+    /// It is used for tempo based timings.
+    tempo: Cell<Tempo>,
+    /// Elapsed time (in seconds or in beats, depending on the timing).
+    elapsed: Cell<SampleCalc>,
+}
+
+impl PitchChangeProgress {
+    fn new(sample_rate: SampleCalc, timing: TimingOption) -> SoundResult<PitchChangeProgress> {
+        let progress = PitchChangeProgress {
+            sample_time: get_sample_time(sample_rate)?,
+            timing: Cell::new(TimingOption::None),
+            tempo: Cell::new(Tempo::default()),
+            elapsed: Cell::new(0.0),
+        };
+        progress.set_timing(timing)?;
+        Ok(progress)
+    }
+
+    /// Provides the duration in seconds (time based timing) or in beats (tempo based timing).
+    fn get_duration(&self) -> SampleCalc {
+        get_timing_duration(self.timing.get())
+    }
+
+    /// Provides the amount of elapsed time (or beats) during one sample.
+    fn get_step(&self) -> SampleCalc {
+        get_timing_step(self.timing.get(), self.sample_time, self.tempo.get())
+    }
+}
+
+/// Provides the duration in seconds (time based timing) or in beats (tempo based timing).
+fn get_timing_duration(timing: TimingOption) -> SampleCalc {
+    match timing {
+        TimingOption::None => 0.0,
+        TimingOption::TimeConst(duration) |
+        TimingOption::TimeRatio { duration, .. } => duration,
+        TimingOption::TempoConst(duration) |
+        TimingOption::TempoRatio { duration, .. } => duration.get_duration_in_beats(),
+    }
+}
+
+/// Provides the amount of elapsed time (or beats) during one sample.
+fn get_timing_step(timing: TimingOption, sample_time: SampleCalc, tempo: Tempo) -> SampleCalc {
+    match timing {
+        TimingOption::TempoConst(_) |
+        TimingOption::TempoRatio { .. } => sample_time * tempo.get_bpm() / 60.0,
+        _ => sample_time,
+    }
+}
+
+/// The glissando needs a duration, `TimingOption::None` is not allowed.
+fn check_glissando_timing(timing: TimingOption) -> SoundResult<()> {
+    if let TimingOption::None = timing {
+        return Err(Error::TimingInvalid);
+    }
+    Ok(())
+}
+
+impl HasTimer for PitchChangeProgress {
+    fn set_timing(&self, timing: TimingOption) -> SoundResult<()> {
+        match timing {
+            TimingOption::TimeConst(duration) |
+            TimingOption::TimeRatio { duration, .. } if duration <= 0.0 => {
+                return Err(Error::DurationInvalid);
+            }
+            _ => {}
+        }
+        self.timing.set(timing);
+        self.restart();
+        Ok(())
+    }
+
+    fn get_timing(&self) -> TimingOption {
+        self.timing.get()
+    }
+
+    fn restart(&self) {
+        self.elapsed.set(0.0);
+    }
+
+    fn apply_parent_timing(&self, parent_timing: TimingOption) -> SoundResult<()> {
+        self.timing.set(get_applied_timing(self.timing.get(), parent_timing)?);
+        Ok(())
+    }
+}
+
+/// Changing frequency linearly (glissando). Linearity means constant multiplication over time
+/// slices, so the pitch changes evenly. After the duration of the timing the frequency remains
+/// `frequency_end`.
+#[derive(Debug, Clone)]
+pub struct FrequencyChangeLinear {
     frequency_begin: SampleCalc,
     frequency_end: SampleCalc,
-    timeframe: SampleCalc,
-    time: SampleCalc,
+    /// The natural logarithm of the frequency ratio (end / begin).
+    pitch_change: SampleCalc,
+    progress: PitchChangeProgress,
+}
+
+impl FrequencyChangeLinear {
+    /// custom constructor, the duration of the change is given by the `timing`. For tempo
+    /// based timings the tempo can be set by `set_tempo()`, the default is 120 BPM.
+    pub fn new(sample_rate: SampleCalc,
+               frequency_begin: SampleCalc,
+               frequency_end: SampleCalc,
+               timing: TimingOption)
+               -> SoundResult<FrequencyChangeLinear> {
+        if (frequency_begin <= 0.0) || (frequency_end <= 0.0) {
+            return Err(Error::FrequencyInvalid);
+        }
+        check_glissando_timing(timing)?;
+        Ok(FrequencyChangeLinear {
+            frequency_begin: frequency_begin,
+            frequency_end: frequency_end,
+            pitch_change: (frequency_end / frequency_begin).ln(),
+            progress: PitchChangeProgress::new(sample_rate, timing)?,
+        })
+    }
+
+    /// Sets the tempo, which is used by the tempo based timings.
+    pub fn set_tempo(&self, tempo: Tempo) {
+        self.progress.tempo.set(tempo);
+    }
+
+    /// Provides the starting frequency.
+    pub fn get_frequency_begin(&self) -> SampleCalc {
+        self.frequency_begin
+    }
+
+    /// Provides the final frequency.
+    pub fn get_frequency_end(&self) -> SampleCalc {
+        self.frequency_end
+    }
+}
+
+impl HasTimer for FrequencyChangeLinear {
+    fn set_timing(&self, timing: TimingOption) -> SoundResult<()> {
+        check_glissando_timing(timing)?;
+        self.progress.set_timing(timing)
+    }
+
+    fn get_timing(&self) -> TimingOption {
+        self.progress.get_timing()
+    }
+
+    fn restart(&self) {
+        self.progress.restart();
+    }
+
+    fn apply_parent_timing(&self, parent_timing: TimingOption) -> SoundResult<()> {
+        self.progress.apply_parent_timing(parent_timing)
+    }
+}
+
+impl FrequencyFunction for FrequencyChangeLinear {
+    fn get(&self,
+           _time_begin: SampleCalc,
+           base_frequency: Option<&[SampleCalc]>,
+           result: &mut [SampleCalc])
+           -> SoundResult<()> {
+        if base_frequency.is_some() {
+            return Err(Error::FrequencySource);
+        }
+        let duration = self.progress.get_duration();
+        let step = self.progress.get_step();
+        let mut elapsed = self.progress.elapsed.get();
+        for item in result.iter_mut() {
+            *item = if elapsed < duration {
+                self.frequency_begin * (self.pitch_change * elapsed / duration).exp()
+            } else {
+                self.frequency_end
+            };
+            elapsed += step;
+        }
+        self.progress.elapsed.set(elapsed.min(duration));
+        Ok(())
+    }
+}
+
+/// Changing interval linearly (glissando relative to the input frequencies). The input
+/// frequencies are transposed from unison to the given `interval` with constant pitch change
+/// over time, like in `FrequencyChangeLinear`. After the duration of the timing the interval
+/// remains the given one.
+#[derive(Debug, Clone)]
+pub struct IntervalChangeLinear {
+    interval: Interval,
+    /// The natural logarithm of the interval's ratio.
+    pitch_change: SampleCalc,
+    progress: PitchChangeProgress,
+}
+
+impl IntervalChangeLinear {
+    /// custom constructor, the duration of the change is given by the `timing`. For tempo
+    /// based timings the tempo can be set by `set_tempo()`, the default is 120 BPM.
+    pub fn new(sample_rate: SampleCalc,
+               interval: Interval,
+               timing: TimingOption)
+               -> SoundResult<IntervalChangeLinear> {
+        check_glissando_timing(timing)?;
+        Ok(IntervalChangeLinear {
+            interval: interval,
+            pitch_change: interval.get_ratio().ln(),
+            progress: PitchChangeProgress::new(sample_rate, timing)?,
+        })
+    }
+
+    /// Sets the tempo, which is used by the tempo based timings.
+    pub fn set_tempo(&self, tempo: Tempo) {
+        self.progress.tempo.set(tempo);
+    }
+
+    /// Provides the final interval.
+    pub fn get_interval(&self) -> Interval {
+        self.interval
+    }
+}
+
+impl HasTimer for IntervalChangeLinear {
+    fn set_timing(&self, timing: TimingOption) -> SoundResult<()> {
+        check_glissando_timing(timing)?;
+        self.progress.set_timing(timing)
+    }
+
+    fn get_timing(&self) -> TimingOption {
+        self.progress.get_timing()
+    }
+
+    fn restart(&self) {
+        self.progress.restart();
+    }
+
+    fn apply_parent_timing(&self, parent_timing: TimingOption) -> SoundResult<()> {
+        self.progress.apply_parent_timing(parent_timing)
+    }
+}
+
+impl FrequencyFunction for IntervalChangeLinear {
+    fn get(&self,
+           _time_begin: SampleCalc,
+           base_frequency: Option<&[SampleCalc]>,
+           result: &mut [SampleCalc])
+           -> SoundResult<()> {
+        let base_frequency = base_frequency.ok_or(Error::FrequencyInputMissing)?;
+        if base_frequency.len() != result.len() {
+            return Err(Error::BufferSize);
+        }
+        let duration = self.progress.get_duration();
+        let step = self.progress.get_step();
+        let ratio_end = self.interval.get_ratio();
+        let mut elapsed = self.progress.elapsed.get();
+        for (item, frequency) in result.iter_mut().zip(base_frequency) {
+            *item = if elapsed < duration {
+                frequency * (self.pitch_change * elapsed / duration).exp()
+            } else {
+                frequency * ratio_end
+            };
+            elapsed += step;
+        }
+        self.progress.elapsed.set(elapsed.min(duration));
+        Ok(())
+    }
+}
+
+/// The shape of the portamento glides.
+#[derive(Debug, Copy, Clone)]
+pub enum GlideCurve {
+    /// Exponential in frequency, so the pitch changes evenly (like `FrequencyChangeLinear`).
+    Exponential,
+    /// Linear in Hz: the pitch changes faster at the beginning of an ascending glide.
+    Linear,
+}
+
+/// [Portamento](https://en.wikipedia.org/wiki/Portamento): gliding to a new frequency (or
+/// frequency ratio) instead of jumping. The glide time is given by a `TimingOption`, in
+/// seconds (`TimeConst`) or by a note value (`TempoConst`, the tempo can be set by
+/// `set_tempo()`, the default is 120 BPM). With `TimingOption::None` there is no glide.
+///
+/// A new target arriving during a glide starts a new glide from the actual value.
+#[derive(Debug, Clone)]
+pub struct Portamento {
+    curve: GlideCurve,
+    progress: PitchChangeProgress,
+    value_begin: Cell<SampleCalc>,
+    value_end: Cell<SampleCalc>,
+}
+
+impl Portamento {
+    /// custom constructor
+    pub fn new(sample_rate: SampleCalc,
+               timing: TimingOption,
+               curve: GlideCurve)
+               -> SoundResult<Portamento> {
+        Ok(Portamento {
+            curve: curve,
+            progress: PitchChangeProgress::new(sample_rate, timing)?,
+            value_begin: Cell::new(1.0),
+            value_end: Cell::new(1.0),
+        })
+    }
+
+    /// Sets the tempo, which is used by the tempo based timings.
+    pub fn set_tempo(&self, tempo: Tempo) {
+        self.progress.tempo.set(tempo);
+    }
+
+    /// Provides the shape of the glides.
+    pub fn get_curve(&self) -> GlideCurve {
+        self.curve
+    }
+
+    /// Starts a glide from the actual value to the new one.
+    pub fn set_target(&self, value: SampleCalc) -> SoundResult<()> {
+        if value <= 0.0 {
+            return Err(Error::FrequencyInvalid);
+        }
+        self.value_begin.set(self.get_value());
+        self.value_end.set(value);
+        self.progress.restart();
+        Ok(())
+    }
+
+    /// Sets the value immediately, without gliding.
+    pub fn jump(&self, value: SampleCalc) -> SoundResult<()> {
+        if value <= 0.0 {
+            return Err(Error::FrequencyInvalid);
+        }
+        self.value_begin.set(value);
+        self.value_end.set(value);
+        Ok(())
+    }
+
+    /// Provides the actual value.
+    pub fn get_value(&self) -> SampleCalc {
+        let duration = self.progress.get_duration();
+        let elapsed = self.progress.elapsed.get();
+        if elapsed < duration {
+            self.get_value_at(elapsed / duration)
+        } else {
+            self.value_end.get()
+        }
+    }
+
+    /// Provides the target value of the glide.
+    pub fn get_target(&self) -> SampleCalc {
+        self.value_end.get()
+    }
+
+    /// Provides the value at the given fraction of the glide.
+    fn get_value_at(&self, fraction: SampleCalc) -> SampleCalc {
+        let value_begin = self.value_begin.get();
+        let value_end = self.value_end.get();
+        match self.curve {
+            GlideCurve::Exponential => value_begin * (value_end / value_begin).powf(fraction),
+            GlideCurve::Linear => value_begin + (value_end - value_begin) * fraction,
+        }
+    }
+
+    /// Provides the next values of the glide.
+    pub fn get(&self, result: &mut [SampleCalc]) {
+        let duration = self.progress.get_duration();
+        let step = self.progress.get_step();
+        let value_end = self.value_end.get();
+        let mut elapsed = self.progress.elapsed.get();
+        for item in result.iter_mut() {
+            *item = if elapsed < duration {
+                self.get_value_at(elapsed / duration)
+            } else {
+                value_end
+            };
+            elapsed += step;
+        }
+        self.progress.elapsed.set(elapsed.min(duration));
+    }
+}
+
+impl HasTimer for Portamento {
+    fn set_timing(&self, timing: TimingOption) -> SoundResult<()> {
+        let value = self.get_value();
+        self.progress.set_timing(timing)?;
+        self.jump(value)
+    }
+
+    fn get_timing(&self) -> TimingOption {
+        self.progress.get_timing()
+    }
+
+    fn restart(&self) {
+        self.progress.restart();
+    }
+
+    fn apply_parent_timing(&self, parent_timing: TimingOption) -> SoundResult<()> {
+        self.progress.apply_parent_timing(parent_timing)
+    }
+}
+
+/// The reference of the intervals in a `Melody`.
+#[derive(Debug, Copy, Clone)]
+pub enum MelodyMode {
+    /// Each interval is relative to the previous note (the first one to the reference
+    /// frequency).
+    Relative,
+    /// Each interval is relative to the reference frequency (the tonic).
+    Tonic,
+}
+
+/// A melody as a sequence of intervals. Each step is an `Interval` and a `TimingOption` (the
+/// duration of the note). The note changes are sample accurate, the fractions of samples are
+/// carried over to the next steps, so there is no drift even in long melodies.
+///
+/// When the melody is finished, it returns the number of samples completed in the buffer
+/// wrapped in `Error::ItemsCompleted()` (like `Timer`), the rest of the buffer is filled with
+/// the frequency of the last note.
+#[derive(Debug, Clone)]
+pub struct Melody {
+    sample_time: SampleCalc,
+    mode: MelodyMode,
+    frequency_reference: SampleCalc,
+    steps: Vec<(Interval, TimingOption)>,
+    /// The frequencies of the steps.
+    frequencies: Vec<SampleCalc>,
+    /// It is used for tempo based timings.
+    tempo: Cell<Tempo>,
+    step_index: Cell<usize>,
+    /// Remaining time (in seconds or in beats, depending on the timing) of the actual step.
+    remaining: Cell<SampleCalc>,
+}
+
+impl Melody {
+    /// custom constructor. The timings must have a duration (`TimingOption::None` is invalid).
+    /// For tempo based timings the tempo can be set by `set_tempo()`, the default is 120 BPM.
+    pub fn new(sample_rate: SampleCalc,
+               frequency_reference: SampleCalc,
+               mode: MelodyMode,
+               steps: Vec<(Interval, TimingOption)>)
+               -> SoundResult<Melody> {
+        let sample_time = get_sample_time(sample_rate)?;
+        if frequency_reference <= 0.0 {
+            return Err(Error::FrequencyInvalid);
+        }
+        if steps.is_empty() {
+            return Err(Error::SequenceEmpty);
+        }
+        let mut frequencies = Vec::with_capacity(steps.len());
+        let mut frequency = frequency_reference;
+        for &(interval, timing) in &steps {
+            if get_timing_duration(timing) <= 0.0 {
+                return Err(Error::TimingInvalid);
+            }
+            frequency = match mode {
+                MelodyMode::Relative => interval.change_frequency(frequency)?,
+                MelodyMode::Tonic => interval.change_frequency(frequency_reference)?,
+            };
+            frequencies.push(frequency);
+        }
+        let remaining = get_timing_duration(steps[0].1);
+        Ok(Melody {
+            sample_time: sample_time,
+            mode: mode,
+            frequency_reference: frequency_reference,
+            steps: steps,
+            frequencies: frequencies,
+            tempo: Cell::new(Tempo::default()),
+            step_index: Cell::new(0),
+            remaining: Cell::new(remaining),
+        })
+    }
+
+    /// Sets the tempo, which is used by the tempo based timings.
+    pub fn set_tempo(&self, tempo: Tempo) {
+        self.tempo.set(tempo);
+    }
+
+    /// Provides the reference frequency.
+    pub fn get_frequency_reference(&self) -> SampleCalc {
+        self.frequency_reference
+    }
+
+    /// Provides the reference of the intervals.
+    pub fn get_mode(&self) -> MelodyMode {
+        self.mode
+    }
+
+    /// Provides the steps of the melody.
+    pub fn get_steps(&self) -> &[(Interval, TimingOption)] {
+        &self.steps
+    }
+
+    /// Provides the index of the actual step (it equals the number of steps when the melody is
+    /// finished).
+    pub fn get_step_index(&self) -> usize {
+        self.step_index.get()
+    }
+
+    /// Checks if the melody is finished.
+    pub fn is_finished(&self) -> bool {
+        self.step_index.get() >= self.steps.len()
+    }
+
+    /// Starts the melody from the beginning.
+    pub fn restart(&self) {
+        self.step_index.set(0);
+        self.remaining.set(get_timing_duration(self.steps[0].1));
+    }
+}
+
+impl FrequencyFunction for Melody {
+    fn get(&self,
+           _time_begin: SampleCalc,
+           base_frequency: Option<&[SampleCalc]>,
+           result: &mut [SampleCalc])
+           -> SoundResult<()> {
+        if base_frequency.is_some() {
+            return Err(Error::FrequencySource);
+        }
+        let tempo = self.tempo.get();
+        let mut step_index = self.step_index.get();
+        let mut remaining = self.remaining.get();
+        for index in 0..result.len() {
+            if step_index >= self.steps.len() {
+                let frequency_last = self.frequencies[self.frequencies.len() - 1];
+                for item in result.iter_mut().skip(index) {
+                    *item = frequency_last;
+                }
+                self.step_index.set(step_index);
+                self.remaining.set(0.0);
+                return Err(Error::ItemsCompleted(index));
+            }
+            result[index] = self.frequencies[step_index];
+            let step = get_timing_step(self.steps[step_index].1, self.sample_time, tempo);
+            remaining -= step;
+            // rounding errors below a thousandth of a sample are neglected
+            if remaining <= step * 0.001 {
+                // the overshoot (a fraction of the sample) is carried over to the next step
+                let overshoot = -remaining / step;
+                step_index += 1;
+                if let Some(&(_, timing)) = self.steps.get(step_index) {
+                    remaining = get_timing_duration(timing) -
+                                overshoot * get_timing_step(timing, self.sample_time, tempo);
+                }
+            }
+        }
+        self.step_index.set(step_index);
+        self.remaining.set(remaining);
+        if step_index >= self.steps.len() {
+            return Err(Error::ItemsCompleted(result.len()));
+        }
+        Ok(())
+    }
 }
 
 /// Provides rhythmic frequency changes. As phase depends on the integral of tempo, only
@@ -147,3 +745,299 @@ impl FrequencyModulator for Vibrato {
         Ok(())
     }
 }
+
+/// Applies a `FrequencyModulator` (e.g. `Vibrato`) on the base frequency of a sound structure.
+/// The tempo of the modulation is given by a `TempoProvider`.
+///
+/// Like a real player's vibrato, the modulation can start after an onset delay, and it's depth
+/// can be shaped by an amplitude function (e.g. a `FadeLinear` from 0.0 to 1.0), which starts
+/// at the onset. The depth scales the pitch deviation: with depth 0.5 the frequency ratio of
+/// the modulation is the square root of the original.
+pub struct FrequencyModulation {
+    sample_time: SampleCalc,
+    modulator: RefCell<Box<FrequencyModulator>>,
+    tempo: Rc<TempoProvider>,
+    sound: Rc<SoundStructure>,
+    /// The time before the modulation starts (in seconds).
+    onset_delay: SampleCalc,
+    depth: Option<Rc<AmplitudeJoinable>>,
+    /// Time elapsed since the start (in seconds).
+    time: Cell<SampleCalc>,
+    tempo_buffer: RefCell<Vec<SampleCalc>>,
+    frequency_buffer: RefCell<Vec<SampleCalc>>,
+    depth_buffer: RefCell<Vec<SampleCalc>>,
+}
+
+impl FrequencyModulation {
+    /// custom constructor, the modulation starts immediately with full depth. The buffers are
+    /// allocated here, at most `buffer_size` samples can be requested at once.
+    pub fn new(sample_rate: SampleCalc,
+               buffer_size: usize,
+               modulator: Box<FrequencyModulator>,
+               tempo: Rc<TempoProvider>,
+               sound: Rc<SoundStructure>)
+               -> SoundResult<FrequencyModulation> {
+        Ok(FrequencyModulation {
+            sample_time: get_sample_time(sample_rate)?,
+            modulator: RefCell::new(modulator),
+            tempo: tempo,
+            sound: sound,
+            onset_delay: 0.0,
+            depth: None,
+            time: Cell::new(0.0),
+            tempo_buffer: RefCell::new(vec![0.0; buffer_size]),
+            frequency_buffer: RefCell::new(vec![0.0; buffer_size]),
+            depth_buffer: RefCell::new(vec![0.0; buffer_size]),
+        })
+    }
+
+    /// Sets the time before the modulation starts (in seconds).
+    pub fn set_onset_delay(&mut self, onset_delay: SampleCalc) -> SoundResult<&mut Self> {
+        if onset_delay < 0.0 {
+            return Err(Error::DurationInvalid);
+        }
+        self.onset_delay = onset_delay;
+        Ok(self)
+    }
+
+    /// Sets the depth envelope, which starts at the onset. `None` means full depth.
+    pub fn set_depth(&mut self, depth: Option<Rc<AmplitudeJoinable>>) -> &mut Self {
+        self.depth = depth;
+        self
+    }
+
+    /// Provides the depth of the modulation for the samples after the onset.
+    fn get_depth(depth: &AmplitudeJoinable,
+                 tempo: &[SampleCalc],
+                 result: &mut [SampleCalc])
+                 -> SoundResult<()> {
+        for item in result.iter_mut() {
+            *item = 1.0;
+        }
+        let apply_result = match depth.apply(result) {
+            Err(Error::ProgressInvalid) => depth.apply_rhythmic(tempo, result),
+            apply_result => apply_result,
+        };
+        match apply_result {
+            Ok(()) => Ok(()),
+            Err(Error::ItemsCompleted(completed)) => {
+                // the envelope holds it's final value
+                let amplitude = depth.get_amplitude();
+                for item in result.iter_mut().skip(completed) {
+                    *item = amplitude;
+                }
+                Ok(())
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl HasTimer for FrequencyModulation {
+    fn set_timing(&self, timing: TimingOption) -> SoundResult<()> {
+        self.sound.set_timing(timing)?;
+        self.restart();
+        Ok(())
+    }
+
+    fn get_timing(&self) -> TimingOption {
+        self.sound.get_timing()
+    }
+
+    fn restart(&self) {
+        self.sound.restart();
+        self.time.set(0.0);
+        if let Some(ref depth) = self.depth {
+            depth.restart();
+        }
+    }
+
+    fn apply_parent_timing(&self, parent_timing: TimingOption) -> SoundResult<()> {
+        self.sound.apply_parent_timing(parent_timing)
+    }
+}
+
+impl SoundStructure for FrequencyModulation {
+    fn get(&self, base_frequency: &[SampleCalc], result: &mut [SampleCalc]) -> SoundResult<()> {
+        if base_frequency.len() != result.len() {
+            return Err(Error::BufferSize);
+        }
+        let time = self.time.get();
+        let sample_count = result.len();
+        let mut tempo_buffer = self.tempo_buffer.borrow_mut();
+        let mut frequency_buffer = self.frequency_buffer.borrow_mut();
+        if sample_count > frequency_buffer.len() {
+            return Err(Error::BufferSize);
+        }
+        let tempo_buffer = &mut tempo_buffer[..sample_count];
+        let frequency_buffer = &mut frequency_buffer[..sample_count];
+        let onset_index = if time >= self.onset_delay {
+            0
+        } else {
+            (((self.onset_delay - time) / self.sample_time).ceil() as usize).min(sample_count)
+        };
+        frequency_buffer[..onset_index].copy_from_slice(&base_frequency[..onset_index]);
+        if onset_index < sample_count {
+            let onset_time = time + onset_index as SampleCalc * self.sample_time;
+            self.tempo.get_beats_per_second(onset_time, &mut tempo_buffer[onset_index..]);
+            self.modulator
+                .borrow_mut()
+                .get(&tempo_buffer[onset_index..],
+                     &base_frequency[onset_index..],
+                     &mut frequency_buffer[onset_index..])?;
+            if let Some(ref depth) = self.depth {
+                let mut depth_buffer = self.depth_buffer.borrow_mut();
+                let depth_buffer = &mut depth_buffer[..(sample_count - onset_index)];
+                Self::get_depth(&**depth, &tempo_buffer[onset_index..], depth_buffer)?;
+                for ((item, frequency), depth) in frequency_buffer[onset_index..]
+                    .iter_mut()
+                    .zip(&base_frequency[onset_index..])
+                    .zip(depth_buffer.iter()) {
+                    *item = *frequency * (*item / *frequency).powf(*depth);
+                }
+            }
+        }
+        self.time.set(time + sample_count as SampleCalc * self.sample_time);
+        self.sound.get(frequency_buffer, result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sound::*;
+    use std::rc::Rc;
+
+    #[test]
+    fn frequency_modulation_buffer_size() {
+        let amplitude = AmplitudeConstOvertones::new(48000.0, 1, &[1.0]).unwrap();
+        let timbre = Timbre::new(48000.0, 4, Rc::new(amplitude), 1).unwrap();
+        let vibrato = Vibrato::new(48000.0, NoteValue::new(1, 8).unwrap(), 1.05).unwrap();
+        let modulation = FrequencyModulation::new(48000.0,
+                                                  4,
+                                                  Box::new(vibrato),
+                                                  Rc::new(Tempo::new(120.0).unwrap()),
+                                                  Rc::new(timbre))
+            .unwrap();
+        let mut result = [0.0; 4];
+        modulation.get(&[440.0; 4], &mut result).unwrap();
+        let mut result = [0.0; 8];
+        match modulation.get(&[440.0; 8], &mut result) {
+            Err(Error::BufferSize) => {}
+            _ => panic!("BufferSize expected"),
+        }
+    }
+
+    #[test]
+    fn glissando_timing() {
+        let glissando = FrequencyChangeLinear::new(1000.0,
+                                                   100.0,
+                                                   400.0,
+                                                   TimingOption::TimeRatio {
+                                                       ratio: 0.5,
+                                                       duration: 0.5,
+                                                   })
+            .unwrap();
+        glissando.apply_parent_timing(TimingOption::TimeConst(0.008)).unwrap();
+        let mut result = [0.0; 6];
+        glissando.get(0.0, None, &mut result).unwrap();
+        assert!((result[2] - 200.0).abs() < 1e-3);
+        assert_eq!(result[4], 400.0);
+        glissando.restart();
+        glissando.get(0.0, None, &mut result).unwrap();
+        assert_eq!(result[0], 100.0);
+        assert!(glissando.set_timing(TimingOption::TimeConst(0.0)).is_err());
+        assert!(glissando.set_timing(TimingOption::None).is_err());
+        assert!(glissando.apply_parent_timing(TimingOption::None).is_err());
+    }
+
+    fn new_melody(durations: &[SampleCalc]) -> Melody {
+        let intervals = [Interval::new(1, 1).unwrap(),
+                         Interval::new(3, 2).unwrap(),
+                         Interval::new(2, 1).unwrap()];
+        let steps = durations.iter()
+            .enumerate()
+            .map(|(index, duration)| {
+                (intervals[index % intervals.len()], TimingOption::TimeConst(*duration))
+            })
+            .collect();
+        Melody::new(1000.0, 100.0, MelodyMode::Tonic, steps).unwrap()
+    }
+
+    #[test]
+    fn melody_note_boundaries() {
+        // 2.5 + 2.5 + 3 samples: the half sample overshoot of the first note shortens the second
+        let melody = new_melody(&[0.0025, 0.0025, 0.003]);
+        let mut result = [0.0; 10];
+        match melody.get(0.0, None, &mut result) {
+            Err(Error::ItemsCompleted(8)) => {}
+            _ => panic!("ItemsCompleted(8) expected"),
+        }
+        assert_eq!(result,
+                   [100.0, 100.0, 100.0, 150.0, 150.0, 200.0, 200.0, 200.0, 200.0, 200.0]);
+        assert!(melody.is_finished());
+        assert_eq!(melody.get_step_index(), 3);
+        // the same boundaries in small buffers, and the end of the melody in the middle of one
+        melody.restart();
+        let mut parts = Vec::new();
+        for _ in 0..2 {
+            let mut buffer = [0.0; 3];
+            melody.get(0.0, None, &mut buffer).unwrap();
+            parts.extend_from_slice(&buffer);
+        }
+        let mut buffer = [0.0; 3];
+        match melody.get(0.0, None, &mut buffer) {
+            Err(Error::ItemsCompleted(2)) => {}
+            _ => panic!("ItemsCompleted(2) expected"),
+        }
+        parts.extend_from_slice(&buffer);
+        assert_eq!(parts[..], result[..9]);
+        // a finished melody completes no items
+        match melody.get(0.0, None, &mut buffer) {
+            Err(Error::ItemsCompleted(0)) => {}
+            _ => panic!("ItemsCompleted(0) expected"),
+        }
+        assert_eq!(buffer, [200.0; 3]);
+    }
+
+    #[test]
+    fn melody_without_drift() {
+        // 3000 notes of 4/3 samples are exactly 4000 samples
+        let melody = new_melody(&[0.004 / 3.0; 3000]);
+        let mut result = vec![0.0; 4100];
+        match melody.get(0.0, None, &mut result) {
+            Err(Error::ItemsCompleted(completed)) => assert_eq!(completed, 4000),
+            _ => panic!("ItemsCompleted expected"),
+        }
+        // each note starts at the first sample after its (fractional) start time
+        let mut note_index = 0;
+        for (index, item) in result.iter().enumerate().take(4000).skip(1) {
+            if *item != result[index - 1] {
+                note_index += 1;
+                assert_eq!(index, (note_index * 4 + 2) / 3);
+            }
+        }
+        assert_eq!(note_index, 2999);
+    }
+
+    #[test]
+    fn failed_change_keeps_the_drift() {
+        let frequency = FrequencyConst::new(20000.0).unwrap();
+        let portamento = Portamento::new(48000.0, TimingOption::TimeConst(0.1), GlideCurve::Linear)
+            .unwrap();
+        frequency.set_portamento(Some(portamento)).unwrap();
+        let mut drift_tracker = DriftTracker::new(440.0).unwrap();
+        let _ = drift_tracker.set_correction(DriftCorrection::Commas);
+        frequency.set_drift_tracker(Some(drift_tracker)).unwrap();
+        let fifth = Interval::new(3, 2).unwrap();
+        let fifth_down = Interval::new(2, 3).unwrap();
+        assert!(frequency.change(fifth).is_err());
+        let drift_tracker = frequency.get_drift_tracker().unwrap();
+        assert!(drift_tracker.get_product().is_unison());
+        assert_eq!(drift_tracker.get_frequency(), 20000.0);
+        let _ = frequency.change(fifth_down).unwrap();
+        let drift_tracker = frequency.get_drift_tracker().unwrap();
+        assert_eq!(drift_tracker.get_product(), Monzo::from(fifth_down));
+        assert_eq!(drift_tracker.get_change(fifth).unwrap().get_frequency(), 20000.0);
+        assert_eq!(drift_tracker.get_product(), Monzo::from(fifth_down));
+    }
+}
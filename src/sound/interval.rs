@@ -2,6 +2,10 @@ use num::*;
 use sound::*;
 use std::fmt;
 use std::ops::{Div, Mul};
+use std::str::FromStr;
+
+/// The tolerance (in cents) of the sizes given in cents by the text form of the intervals.
+pub const PARSE_CENTS_TOLERANCE: SampleCalc = 5.0;
 
 /// unison (1:1)
 pub const INTERVAL_UNISON: Interval = Interval {
@@ -11,11 +15,19 @@ pub const INTERVAL_UNISON: Interval = Interval {
     reciprocal: 1.0,
 };
 
+/// The integer type of the numerator and denominator of `Interval`. For intervals with larger
+/// terms (e.g. commas and long chains of intervals) see `WideInterval` (it needs the
+/// `interval-wide` feature).
+pub type IntervalTerm = u16;
+
 /// Harmonic musical interval (of frequencies), represented by a rational number.
+///
+/// The `*` and `/` operators panic on overflow of the terms (see `IntervalTerm`), use
+/// `checked_mul()` and `checked_div()` to handle it.
 #[derive(Debug, Copy, Clone)]
 pub struct Interval {
-    numerator: u16,
-    denominator: u16,
+    numerator: IntervalTerm,
+    denominator: IntervalTerm,
     ratio: SampleCalc,
     reciprocal: SampleCalc,
 }
@@ -33,7 +45,7 @@ impl Default for Interval {
 
 impl Interval {
     /// custom constructor
-    pub fn new(numerator: u16, denominator: u16) -> SoundResult<Interval> {
+    pub fn new(numerator: IntervalTerm, denominator: IntervalTerm) -> SoundResult<Interval> {
         let mut interval = Interval::default();
         interval.set(numerator, denominator)?;
         Ok(interval)
@@ -47,7 +59,7 @@ impl Interval {
     }
 
     /// Changes the interval.
-    pub fn set(&mut self, numerator: u16, denominator: u16) -> SoundResult<()> {
+    pub fn set(&mut self, numerator: IntervalTerm, denominator: IntervalTerm) -> SoundResult<()> {
         if numerator == 0 {
             return Err(Error::NumeratorInvalid);
         };
@@ -62,6 +74,48 @@ impl Interval {
         Ok(())
     }
 
+    /// Creates the interval from terms already reduced to lowest terms.
+    fn from_reduced(numerator: IntervalTerm, denominator: IntervalTerm) -> Interval {
+        Interval {
+            numerator: numerator,
+            denominator: denominator,
+            ratio: numerator as SampleCalc / denominator as SampleCalc,
+            reciprocal: denominator as SampleCalc / numerator as SampleCalc,
+        }
+    }
+
+    /// Multiplies the fractions `n1/d1` and `n2/d2` (in lowest terms).
+    fn multiply(n1: IntervalTerm,
+                d1: IntervalTerm,
+                n2: IntervalTerm,
+                d2: IntervalTerm)
+                -> Option<Interval> {
+        let (numerator, denominator) = try_opt!(multiply_terms(n1, d1, n2, d2));
+        Some(Interval::from_reduced(numerator, denominator))
+    }
+
+    /// Stacks the intervals (multiplies their ratios). Returns `Error::Overflow` if the result
+    /// can not be represented by `IntervalTerm`.
+    pub fn add_interval(&self, interval: Interval) -> SoundResult<Interval> {
+        self.checked_mul(&interval).ok_or(Error::Overflow)
+    }
+
+    /// Provides the difference of the intervals (divides their ratios). Returns
+    /// `Error::Overflow` if the result can not be represented by `IntervalTerm`.
+    pub fn sub_interval(&self, interval: Interval) -> SoundResult<Interval> {
+        self.checked_div(&interval).ok_or(Error::Overflow)
+    }
+
+    /// Provides the numerator (in lowest terms).
+    pub fn get_numerator(&self) -> IntervalTerm {
+        self.numerator
+    }
+
+    /// Provides the denominator (in lowest terms).
+    pub fn get_denominator(&self) -> IntervalTerm {
+        self.denominator
+    }
+
     /// Returns the ratio of the frequency interval.
     pub fn get_ratio(&self) -> SampleCalc {
         self.ratio
@@ -72,53 +126,138 @@ impl Interval {
         self.reciprocal
     }
 
+    /// Provides the size of the interval in [cents](https://en.wikipedia.org/wiki/Cent_(music))
+    /// (1200 cents is an octave).
+    pub fn get_cents(&self) -> SampleCalc {
+        (1200.0 * ((self.numerator as f64).log2() - (self.denominator as f64).log2())) as
+        SampleCalc
+    }
+
+    /// Provides the [prime limit](https://en.wikipedia.org/wiki/Limit_(music)): the largest
+    /// prime factor of the numerator and the denominator (1 for unison).
+    pub fn get_prime_limit(&self) -> u64 {
+        self.get_monzo().get_prime_limit()
+    }
+
+    /// Provides the [odd limit](https://en.wikipedia.org/wiki/Odd_limit): the largest odd
+    /// number of the numerator and denominator, after removing their factors of 2.
+    pub fn get_odd_limit(&self) -> IntervalTerm {
+        let mut numerator = self.numerator;
+        while numerator % 2 == 0 {
+            numerator /= 2;
+        }
+        let mut denominator = self.denominator;
+        while denominator % 2 == 0 {
+            denominator /= 2;
+        }
+        numerator.max(denominator)
+    }
+
+    /// Provides the [Tenney height](http://xenharmonic.wikispaces.com/Tenney+Height):
+    /// log2(numerator · denominator), a measure of the complexity of the interval.
+    pub fn get_tenney_height(&self) -> SampleCalc {
+        ((self.numerator as f64).log2() + (self.denominator as f64).log2()) as SampleCalc
+    }
+
+    /// Provides the [Benedetti height](http://xenharmonic.wikispaces.com/Benedetti+height):
+    /// numerator · denominator. Returns `Error::Overflow` if it does not fit in `u64`.
+    pub fn get_benedetti_height(&self) -> SoundResult<u64> {
+        let numerator: u64 = self.numerator.into();
+        numerator.checked_mul(self.denominator.into()).ok_or(Error::Overflow)
+    }
+
+    /// Provides the prime factorization of the interval.
+    pub fn get_monzo(&self) -> Monzo {
+        Monzo::from(*self)
+    }
+
     /// True, if the interval is `1:1`, aka. unison.
     pub fn is_unison(&self) -> bool {
         self.numerator == self.denominator
     }
 
-    /// Gives the common name of the interval (if there is any).
+    /// Gives the common name of the interval (if there is any), see `INTERVAL_NAMES`.
+    /// Descending intervals get the name of the ascending one.
     pub fn get_name(&self) -> &str {
         let ratio = if self.numerator > self.denominator {
             (self.numerator, self.denominator)
         } else {
             (self.denominator, self.numerator)
         };
-        // https://en.wikipedia.org/wiki/List_of_pitch_intervals
-        // https://gist.github.com/endolith/3098720
-        match ratio {
-            (1, 1) => "unison",
-            (2, 1) => "octave",
-            (3, 2) => "perfect fifth",
-            (4, 3) => "perfect fourth",
-            (5, 4) => "major third",
-            (5, 3) => "major sixth",
-            (6, 5) => "minor third",
-            (7, 6) => "septimal minor third",
-            (7, 5) => "lesser septimal tritone",
-            (7, 4) => "augmented sixth", // "harmonic seventh", "septimal minor seventh" too
-            (8, 7) => "septimal major second",
-            (8, 5) => "minor sixth",
-            (9, 8) => "major second", // "major tone" too
-            (9, 7) => "septimal major third",
-            (9, 5) => "minor seventh",
-
-            (10, 9) => "minor tone",
-            // (10, 8) => "",
-            (10, 7) => "greater septimal tritone",
-            // (11, 6) => "major seventh",
-            (11, 8) => "lesser undecimal tritone",
-            // (12, 11) => "minor second",
-            (13, 8) => "acute minor sixth",
-            (15, 8) => "major seventh",
-            (16, 15) => "semitone", // "minor second" too
-            (16, 9) => "grave minor seventh",
-            // (29, 16) => "minor seventh", // "twenty-ninth harmonic"
-            (31, 16) => "augmented seventh",
-            (45, 32) => "augmented fourth",
-            (64, 45) => "diminished fifth",
-            _ => "",
+        for &(numerator, denominator, names) in INTERVAL_NAMES {
+            if (numerator, denominator) == ratio {
+                return names[0];
+            }
+        }
+        ""
+    }
+
+    /// Finds the interval by it's name or alias (see `INTERVAL_NAMES`). The comparison is
+    /// case insensitive, and multiple spaces count as one.
+    pub fn from_name(name: &str) -> SoundResult<Interval> {
+        let name = normalize_name(name);
+        for &(numerator, denominator, names) in INTERVAL_NAMES {
+            if names.iter().any(|item| normalize_name(item) == name) {
+                return Interval::new(numerator, denominator);
+            }
         }
+        Err(Error::ParseInvalid)
+    }
+
+    /// Finds the simplest interval (with the smallest terms) within `tolerance` cents of the
+    /// given size. Negative `cents` give descending intervals.
+    pub fn from_cents(cents: SampleCalc, tolerance: SampleCalc) -> SoundResult<Interval> {
+        if !(tolerance > 0.0) || !cents.is_finite() {
+            return Err(Error::ToleranceInvalid);
+        }
+        let cents = cents as f64;
+        let tolerance = tolerance as f64;
+        let lower = ((cents - tolerance) / 1200.0).exp2();
+        let upper = ((cents + tolerance) / 1200.0).exp2();
+        // walking down the Stern-Brocot tree until a fraction gets into the range, jumping
+        // over the runs of steps in the same direction
+        let term_max: u64 = IntervalTerm::max_value().into();
+        let mut walk = SternBrocotWalk::new(term_max, term_max);
+        loop {
+            let (numerator, denominator) = walk.get_mediant().ok_or(Error::Overflow)?;
+            let ratio = numerator as f64 / denominator as f64;
+            if ratio < lower {
+                let steps = walk.get_steps_to(true, lower);
+                walk.advance(true, (steps - 1).min(walk.get_step_max(true)));
+            } else if ratio > upper {
+                let steps = walk.get_steps_to(false, upper);
+                walk.advance(false, (steps - 1).min(walk.get_step_max(false)));
+            } else {
+                let numerator: IntervalTerm = NumCast::from(numerator).ok_or(Error::Overflow)?;
+                let denominator: IntervalTerm = NumCast::from(denominator)
+                    .ok_or(Error::Overflow)?;
+                return Interval::new(numerator, denominator);
+            }
+        }
+    }
+
+    /// Parses one term of the text form: a ratio, a size in cents or a name.
+    fn parse_term(term: &str) -> SoundResult<Interval> {
+        let term = term.trim();
+        if let Some(position) = term.find(|c| c == ':' || c == '/') {
+            let numerator = term[..position].trim().parse().map_err(|_| Error::ParseInvalid)?;
+            let denominator = term[position + 1..]
+                .trim()
+                .parse()
+                .map_err(|_| Error::ParseInvalid)?;
+            return Interval::new(numerator, denominator);
+        }
+        if let Ok(numerator) = term.parse() {
+            return Interval::new(numerator, 1);
+        }
+        for suffix in &["cents", "cent", "c", "¢"] {
+            if term.ends_with(suffix) {
+                if let Ok(cents) = term[..term.len() - suffix.len()].trim().parse() {
+                    return Interval::from_cents(cents, PARSE_CENTS_TOLERANCE);
+                }
+            }
+        }
+        Interval::from_name(term)
     }
 
     /// Change a frequency according to the interval.
@@ -170,13 +309,13 @@ impl Mul for Interval {
     type Output = Interval;
 
     fn mul(self, rhs: Interval) -> Interval {
-        let mut interval = Interval::default();
-        interval.numerator = self.numerator * rhs.numerator;
-        interval.denominator = self.denominator * rhs.denominator;
-        interval.reduce();
-        interval.ratio = interval.numerator as SampleCalc / interval.denominator as SampleCalc;
-        interval.reciprocal = interval.denominator as SampleCalc / interval.numerator as SampleCalc;
-        interval
+        self.checked_mul(&rhs).expect("interval multiplication overflow")
+    }
+}
+
+impl CheckedMul for Interval {
+    fn checked_mul(&self, v: &Self) -> Option<Self> {
+        Interval::multiply(self.numerator, self.denominator, v.numerator, v.denominator)
     }
 }
 
@@ -184,16 +323,83 @@ impl Div for Interval {
     type Output = Interval;
 
     fn div(self, rhs: Interval) -> Interval {
-        let mut interval = Interval::default();
-        interval.numerator = self.numerator * rhs.denominator;
-        interval.denominator = self.denominator * rhs.numerator;
-        interval.reduce();
-        interval.ratio = interval.numerator as SampleCalc / interval.denominator as SampleCalc;
-        interval.reciprocal = interval.denominator as SampleCalc / interval.numerator as SampleCalc;
-        interval
+        self.checked_div(&rhs).expect("interval division overflow")
     }
 }
 
+impl CheckedDiv for Interval {
+    fn checked_div(&self, v: &Self) -> Option<Self> {
+        Interval::multiply(self.numerator, self.denominator, v.denominator, v.numerator)
+    }
+}
+
+/// Parses the text form of the intervals. The accepted forms are:
+///
+/// * ratios: `3:2`, `3/2` or `3` (= 3:1)
+/// * names and their aliases: `perfect fifth`, `septimal minor third` (see `INTERVAL_NAMES`)
+/// * sizes in cents: `702c`, `702 cents` or `702¢`, resolved to the simplest ratio within
+///   `PARSE_CENTS_TOLERANCE`
+/// * compound forms, adding and subtracting the above: `octave + 5:4`, `octave - fifth`
+///
+/// The `+` and `-` operators must be surrounded by whitespace, except for a leading sign (e.g.
+/// `-fifth` is a descending fifth). So the hyphenated names (e.g. `semi-augmented fourth`) and
+/// the negative sizes (e.g. `octave + -5c`) are not split.
+impl FromStr for Interval {
+    type Err = Error;
+
+    fn from_str(text: &str) -> SoundResult<Interval> {
+        let mut interval = INTERVAL_UNISON;
+        let mut descending = false;
+        let mut term_start = 0;
+        let text_start = text.len() - text.trim_start().len();
+        let terms = text.match_indices(|c| c == '+' || c == '-')
+            .filter(|&(index, _)| {
+                index == text_start ||
+                (text[..index].chars().next_back().map_or(false, char::is_whitespace) &&
+                 text[index + 1..].chars().next().map_or(false, char::is_whitespace))
+            })
+            .map(|(index, operator)| (index, operator == "-"))
+            .chain(Some((text.len(), false)));
+        for (term_end, next_descending) in terms {
+            let term = &text[term_start..term_end];
+            if term.trim().is_empty() {
+                // only a leading sign is allowed without a term
+                if term_start != 0 || term_end == text.len() {
+                    return Err(Error::ParseInvalid);
+                }
+            } else {
+                let term = Interval::parse_term(term)?;
+                interval = if descending {
+                    interval.sub_interval(term)?
+                } else {
+                    interval.add_interval(term)?
+                };
+            }
+            descending = next_descending;
+            term_start = term_end + 1;
+        }
+        Ok(interval)
+    }
+}
+
+/// Multiplies the fractions `n1/d1` and `n2/d2` (in lowest terms), provides the terms of the
+/// product (in lowest terms). Cross reducing before the multiplication keeps the products as
+/// small as possible, so it overflows only when the result can not be represented.
+pub(crate) fn multiply_terms<T>(n1: T, d1: T, n2: T, d2: T) -> Option<(T, T)>
+    where T: Integer + CheckedMul + Copy
+{
+    let gcd1 = n1.gcd(&d2);
+    let gcd2 = n2.gcd(&d1);
+    let numerator = try_opt!((n1 / gcd1).checked_mul(&(n2 / gcd2)));
+    let denominator = try_opt!((d1 / gcd2).checked_mul(&(d2 / gcd1)));
+    Some((numerator, denominator))
+}
+
+/// Lowercase name without the extra whitespaces.
+fn normalize_name(name: &str) -> String {
+    name.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
 impl From<Interval> for SampleCalc {
     fn from(interval: Interval) -> Self {
         interval.ratio
@@ -205,3 +411,136 @@ impl fmt::Display for Interval {
         write!(f, "{}:{}", self.numerator, self.denominator)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use num::*;
+    use sound::*;
+
+    fn interval(numerator: IntervalTerm, denominator: IntervalTerm) -> Interval {
+        Interval::new(numerator, denominator).unwrap()
+    }
+
+    fn get_terms(interval: Interval) -> (IntervalTerm, IntervalTerm) {
+        (interval.get_numerator(), interval.get_denominator())
+    }
+
+    #[test]
+    fn metrics() {
+        let comma = Interval::new(81, 80).unwrap();
+        assert_eq!(comma.get_prime_limit(), 5);
+        assert_eq!(comma.get_odd_limit(), 81);
+        assert_eq!(comma.get_benedetti_height().unwrap(), 6480);
+        assert!((comma.get_tenney_height() - 6480.0f32.log2()).abs() < 1e-4);
+        assert_eq!(comma.get_monzo().to_string(), "[-4 4 -1>");
+        let seventh = Interval::new(7, 4).unwrap();
+        assert_eq!(seventh.get_prime_limit(), 7);
+        assert_eq!(seventh.get_odd_limit(), 7);
+        assert_eq!(Interval::new(5, 3).unwrap().get_odd_limit(), 5);
+        assert_eq!(Interval::new(16, 15).unwrap().get_odd_limit(), 15);
+        assert_eq!(INTERVAL_UNISON.get_prime_limit(), 1);
+        assert_eq!(INTERVAL_UNISON.get_odd_limit(), 1);
+        assert_eq!(INTERVAL_UNISON.get_tenney_height(), 0.0);
+        let large = Interval::new(65535, 65534).unwrap();
+        assert_eq!(large.get_benedetti_height().unwrap(), 65535 * 65534);
+    }
+
+    #[test]
+    fn checked_arithmetic() {
+        assert_eq!(get_terms(interval(6, 4)), (3, 2));
+        assert_eq!(get_terms(interval(3, 2) * interval(4, 3)), (2, 1));
+        assert_eq!(get_terms(interval(2, 1) / interval(3, 2)), (4, 3));
+        // cross reduced, so the products do not overflow
+        let large = interval(65535, 65534);
+        assert_eq!(get_terms(large * interval(65534, 65535)), (1, 1));
+        assert_eq!(get_terms(large.sub_interval(large).unwrap()), (1, 1));
+        assert!(large.checked_mul(&large).is_none());
+        assert!(large.checked_div(&interval(65534, 65535)).is_none());
+        match large.add_interval(interval(3, 2)) {
+            Err(Error::Overflow) => {}
+            _ => panic!("Overflow expected"),
+        }
+    }
+
+    fn parse(text: &str) -> (IntervalTerm, IntervalTerm) {
+        get_terms(text.parse::<Interval>().unwrap())
+    }
+
+    #[test]
+    fn parse_forms() {
+        assert_eq!(parse("3:2"), (3, 2));
+        assert_eq!(parse(" 3 / 2 "), (3, 2));
+        assert_eq!(parse("3"), (3, 1));
+        assert_eq!(parse("702c"), (3, 2));
+        assert_eq!(parse("386 cents"), (5, 4));
+        assert_eq!(parse("Perfect  FIFTH"), (3, 2));
+        assert_eq!(parse("octave + 5:4"), (5, 2));
+        assert_eq!(parse("octave - fifth"), (4, 3));
+        assert_eq!(parse("-fifth"), (2, 3));
+        assert_eq!(parse("  -fifth"), (2, 3));
+        assert_eq!(parse("+ fifth"), (3, 2));
+        assert_eq!(parse("1200c + -702c"), (4, 3));
+        assert_eq!(parse("undecimal semi-augmented fourth"), (11, 8));
+        assert_eq!(parse("thirty-first harmonic"), (31, 16));
+        assert_eq!(parse("al-Farabi's quarter tone - undecimal comma"), (1, 1));
+        assert_eq!(parse("octave - septimal third-tone"), (27, 14));
+        for text in &["", "octave +", "octave + + fifth", "fifth-", "3:0", "no such interval",
+                      "octave+fifth"] {
+            assert!(text.parse::<Interval>().is_err(), "{:?} should be invalid", text);
+        }
+    }
+
+    #[test]
+    fn names_table() {
+        let mut ratios = Vec::new();
+        let mut names = Vec::new();
+        for &(numerator, denominator, aliases) in INTERVAL_NAMES {
+            assert_eq!(get_terms(interval(numerator, denominator)), (numerator, denominator));
+            ratios.push((numerator, denominator));
+            for name in aliases {
+                assert!(!name.contains(" - ") && !name.contains(" + "), "{}", name);
+                assert!(!name.contains(':') && !name.contains('/'), "{}", name);
+                assert_eq!(get_terms(Interval::from_name(name).unwrap()),
+                           (numerator, denominator));
+                names.push(name.to_lowercase());
+            }
+        }
+        let ratio_count = ratios.len();
+        ratios.sort();
+        ratios.dedup();
+        assert_eq!(ratios.len(), ratio_count);
+        let name_count = names.len();
+        names.sort();
+        names.dedup();
+        assert_eq!(names.len(), name_count);
+        for &(ratio, name) in &[((11, 10), "greater undecimal neutral second"),
+                                ((11, 6), "undecimal neutral seventh"),
+                                ((13, 12), "tridecimal neutral second"),
+                                ((7, 2), "septimal minor fourteenth")] {
+            assert_eq!(interval(ratio.0, ratio.1).get_name(), name);
+            assert_eq!(interval(ratio.1, ratio.0).get_name(), name);
+        }
+    }
+
+    #[test]
+    fn comma_pump_overflows() {
+        let mut pump = INTERVAL_UNISON;
+        let mut result = Ok(pump);
+        for _ in 0..12 {
+            result = pump.add_interval(interval(3, 2));
+            match result {
+                Ok(next) => pump = next,
+                Err(_) => break,
+            }
+        }
+        assert!(result.is_err());
+    }
+
+    #[cfg(feature = "interval-wide")]
+    #[test]
+    fn comma_pump_with_wide_terms() {
+        let wide = WideInterval::from(interval(3, 2));
+        let comma = (0..11).fold(wide, |comma, _| comma * wide);
+        assert_eq!(comma.get_numerator(), 531441);
+    }
+}
@@ -0,0 +1,124 @@
+use sound::*;
+
+/// Names of the just intervals, based on the
+/// [list of pitch intervals](https://en.wikipedia.org/wiki/List_of_pitch_intervals). Each item
+/// is (numerator, denominator, names), the first name is the primary one, the others are
+/// aliases. Only the ratios representable by `u16` terms are listed. The names are unique
+/// (case insensitively), and they contain no `+` or `-` characters surrounded by whitespace, so
+/// they can be used in compound forms (see `Interval::from_str()`).
+pub const INTERVAL_NAMES: &'static [(IntervalTerm, IntervalTerm, &'static [&'static str])] = &[
+    (1, 1, &["unison", "perfect prime"]),
+    (2, 1, &["octave", "perfect octave"]),
+    (3, 1, &["perfect twelfth", "tritave"]),
+    (4, 1, &["double octave", "perfect fifteenth"]),
+    (3, 2, &["perfect fifth", "fifth", "just perfect fifth"]),
+    (4, 3, &["perfect fourth", "fourth", "just perfect fourth"]),
+    (5, 4, &["major third", "just major third"]),
+    (5, 3, &["major sixth", "just major sixth"]),
+    (5, 2, &["major tenth", "just major tenth"]),
+    (6, 5, &["minor third", "just minor third"]),
+    (7, 6, &["septimal minor third", "subminor third"]),
+    (7, 5, &["lesser septimal tritone", "septimal tritone", "Huygens' tritone"]),
+    (7, 4, &["augmented sixth", "harmonic seventh", "septimal minor seventh"]),
+    (7, 3, &["septimal minor tenth"]),
+    (7, 2, &["septimal minor fourteenth"]),
+    (8, 7, &["septimal major second", "septimal whole tone"]),
+    (8, 5, &["minor sixth", "just minor sixth"]),
+    (8, 3, &["perfect eleventh"]),
+    (9, 8, &["major second", "major tone", "whole tone", "greater whole tone",
+             "Pythagorean major second"]),
+    (9, 7, &["septimal major third", "supermajor third"]),
+    (9, 5, &["minor seventh", "greater just minor seventh"]),
+    (9, 4, &["major ninth"]),
+    (10, 9, &["minor tone", "lesser whole tone"]),
+    (10, 7, &["greater septimal tritone", "Euler's tritone"]),
+    (11, 10, &["greater undecimal neutral second", "undecimal submajor second",
+               "Ptolemy's second"]),
+    (11, 9, &["undecimal neutral third", "neutral third"]),
+    (11, 8, &["lesser undecimal tritone", "undecimal semi-augmented fourth"]),
+    (11, 7, &["undecimal minor sixth", "undecimal augmented fifth"]),
+    (11, 6, &["undecimal neutral seventh", "large undecimal neutral seventh"]),
+    (12, 11, &["undecimal neutral second", "neutral second"]),
+    (12, 7, &["septimal major sixth", "supermajor sixth"]),
+    (12, 5, &["minor tenth", "just minor tenth"]),
+    (13, 12, &["tridecimal neutral second", "greater tridecimal two-thirds tone"]),
+    (13, 11, &["tridecimal minor third"]),
+    (13, 10, &["tridecimal semi-diminished fourth"]),
+    (13, 9, &["tridecimal diminished fifth"]),
+    (13, 8, &["acute minor sixth", "tridecimal neutral sixth", "overtone sixth"]),
+    (14, 13, &["lesser tridecimal two-thirds tone"]),
+    (14, 11, &["undecimal diminished fourth", "undecimal major third"]),
+    (14, 9, &["septimal minor sixth", "subminor sixth"]),
+    (15, 11, &["undecimal augmented fourth"]),
+    (15, 14, &["septimal diatonic semitone"]),
+    (15, 8, &["major seventh", "just major seventh"]),
+    (16, 15, &["semitone", "minor second", "just diatonic semitone", "diatonic semitone"]),
+    (16, 13, &["tridecimal neutral third"]),
+    (16, 11, &["undecimal semi-diminished fifth"]),
+    (16, 9, &["grave minor seventh", "lesser just minor seventh", "Pythagorean minor seventh"]),
+    (17, 16, &["seventeenth harmonic"]),
+    (17, 12, &["lesser septendecimal tritone"]),
+    (18, 17, &["Arabic lute index finger"]),
+    (19, 16, &["nineteenth harmonic", "overtone minor third"]),
+    (19, 18, &["undevicesimal semitone"]),
+    (20, 11, &["lesser undecimal neutral seventh"]),
+    (21, 20, &["septimal chromatic semitone"]),
+    (21, 16, &["septimal fourth", "narrow fourth"]),
+    (22, 21, &["undecimal minor semitone"]),
+    (22, 15, &["undecimal diminished fifth"]),
+    (25, 24, &["just chromatic semitone", "lesser chromatic semitone", "minor chroma"]),
+    (25, 16, &["augmented fifth", "just augmented fifth"]),
+    (27, 25, &["great limma", "large limma"]),
+    (27, 20, &["acute fourth"]),
+    (27, 16, &["Pythagorean major sixth"]),
+    (28, 27, &["septimal minor second", "septimal third-tone"]),
+    (29, 16, &["twenty-ninth harmonic"]),
+    (31, 16, &["augmented seventh", "thirty-first harmonic"]),
+    (32, 27, &["Pythagorean minor third", "semiditone"]),
+    (32, 25, &["diminished fourth", "classic diminished fourth"]),
+    (33, 32, &["undecimal comma", "al-Farabi's quarter tone"]),
+    (36, 35, &["septimal diesis", "septimal quarter tone"]),
+    (40, 27, &["grave fifth"]),
+    (45, 32, &["augmented fourth", "diatonic tritone", "just augmented fourth"]),
+    (49, 48, &["slendro diesis"]),
+    (50, 49, &["tritonic diesis", "Erlich's decatonic comma"]),
+    (55, 54, &["telepathma"]),
+    (56, 55, &["undecimal diesis", "Ptolemy's enharmonic"]),
+    (64, 63, &["septimal comma", "Archytas' comma"]),
+    (64, 45, &["diminished fifth", "just diminished fifth"]),
+    (65, 64, &["thirteenth-partial chroma"]),
+    (81, 80, &["syntonic comma", "comma of Didymus", "Didymus comma"]),
+    (81, 64, &["Pythagorean major third", "ditone"]),
+    (100, 99, &["Ptolemy's comma"]),
+    (121, 120, &["undecimal seconds comma"]),
+    (125, 64, &["classic augmented seventh"]),
+    (125, 96, &["classic augmented third"]),
+    (126, 125, &["small septimal semicomma", "starling comma"]),
+    (128, 125, &["diesis", "lesser diesis", "enharmonic diesis"]),
+    (128, 81, &["Pythagorean minor sixth"]),
+    (135, 128, &["major chroma", "greater chromatic semitone"]),
+    (225, 224, &["septimal kleisma"]),
+    (243, 128, &["Pythagorean major seventh"]),
+    (245, 243, &["sensamagic comma", "minor BP diesis"]),
+    (256, 243, &["limma", "Pythagorean limma", "Pythagorean minor second"]),
+    (385, 384, &["undecimal kleisma"]),
+    (648, 625, &["greater diesis", "major diesis"]),
+    (729, 512, &["Pythagorean augmented fourth", "Pythagorean tritone"]),
+    (1024, 729, &["Pythagorean diminished fifth"]),
+    (1029, 1024, &["gamelisma"]),
+    (1728, 1715, &["orwellisma"]),
+    (2048, 2025, &["diaschisma"]),
+    (2187, 2048, &["apotome", "Pythagorean chromatic semitone"]),
+    (2401, 2400, &["breedsma"]),
+    (3125, 3072, &["small diesis", "magic comma"]),
+    (4000, 3969, &["septimal semicomma", "octagar"]),
+    (4375, 4374, &["ragisma"]),
+    (6144, 6125, &["porwell comma"]),
+    (6561, 4096, &["Pythagorean augmented fifth"]),
+    (8192, 6561, &["Pythagorean diminished fourth"]),
+    (15625, 15552, &["kleisma"]),
+    (19683, 16384, &["Pythagorean augmented second"]),
+    (32805, 32768, &["schisma"]),
+    (32768, 19683, &["Pythagorean diminished seventh"]),
+    (59049, 32768, &["Pythagorean augmented sixth"]),
+];
@@ -1,7 +1,30 @@
+/// See: [RFC #1394](https://github.com/rust-lang/rfcs/pull/1394),
+/// [RFC #1465](https://github.com/rust-lang/rfcs/pull/1465)
+// https://github.com/crumblingstatue/try_opt
+macro_rules! try_opt {
+    ($e:expr) =>(
+        match $e {
+            Some(v) => v,
+            None => return None,
+        }
+    )
+}
+
 /// Error messages.
 pub mod errors;
 /// Frequency interval.
 pub mod interval;
+/// Names of the intervals.
+pub mod interval_names;
+/// Frequency interval with wide terms.
+#[cfg(feature = "interval-wide")]
+pub mod wide_interval;
+/// Prime factorization of intervals.
+pub mod monzo;
+/// Rational approximation of ratios.
+pub mod approximation;
+/// Tracking of the pitch drift caused by commas.
+pub mod comma_drift;
 /// Fuctions which provide frequency changes.
 pub mod frequency;
 /// Fuctions which provide amplitude changes.
@@ -10,6 +33,14 @@ pub mod amplitude;
 pub mod amplitude_overtones;
 /// Fuctions which provide complete waveforms.
 pub mod wave;
+/// Sine oscillator cores.
+pub mod oscillator;
+/// Band-limited classic waveforms.
+pub mod waveform;
+/// Wavetable oscillator with morphing.
+pub mod wavetable;
+/// Noise generators.
+pub mod noise;
 /// Rhythm section.
 pub mod rhythm;
 /// Time and tempo based progress measurement.
@@ -18,25 +49,59 @@ pub mod progress;
 pub mod timing;
 /// Musical note structures.
 pub mod note;
+/// Spatial sound effects.
+pub mod spatial;
+/// Command scheduling for the sound generators.
+pub mod command;
+/// Conversion to the output sample formats.
+pub mod output;
+/// Pseudo-random number generation.
+pub mod random;
+/// Sample rate conversion.
+pub mod resample;
+
+/// Real-time-safe command queue of the sound playback backends.
+pub mod command_queue;
 
 /// [`PortAudio`](https://github.com/RustAudio/rust-portaudio) backend for sound playback.
 #[cfg(feature = "be-portaudio")]
 pub mod backend_portaudio;
 
+/// [WAVE](https://en.wikipedia.org/wiki/WAV) file backend for offline rendering.
+pub mod backend_wav;
+
+/// Virtual-clock backend without sound output, for headless testing of sound generators.
+pub mod backend_null;
+
 /// [`libsoundio`](https://github.com/klingtnet/rsoundio) backend for sound playback.
 #[cfg(feature = "be-rsoundio")]
 pub mod backend_rsoundio;
 
 pub use self::amplitude::*;
 pub use self::amplitude_overtones::*;
+pub use self::approximation::*;
+pub use self::comma_drift::*;
+pub use self::command::*;
 pub use self::errors::*;
 pub use self::frequency::*;
 pub use self::interval::*;
+pub use self::interval_names::*;
+pub use self::monzo::*;
+pub use self::noise::*;
 pub use self::note::*;
+pub use self::oscillator::*;
+pub use self::output::*;
 pub use self::progress::*;
+pub use self::random::*;
+pub use self::resample::*;
 pub use self::rhythm::*;
+pub use self::spatial::*;
 pub use self::timing::*;
 pub use self::wave::*;
+pub use self::waveform::*;
+pub use self::wavetable::*;
+#[cfg(feature = "interval-wide")]
+pub use self::wide_interval::*;
 
 /// Precision of the finally produced samples.
 pub type SampleOutput = f32;
@@ -75,6 +140,19 @@ pub trait SoundGenerator {
     type Command;
     /// Get the next `sample_count` amount of samples, put them in `result`
     fn get_samples(&mut self, sample_count: usize, result: &mut Vec<SampleCalc>);
+    /// Get the next `sample_count` amount of samples for each output channel, put them in the
+    /// `result` buffers (one buffer per channel). Multichannel generators shall override it,
+    /// the default implementation copies the output of `get_samples()` into every channel.
+    fn get_channel_samples(&mut self, sample_count: usize, result: &mut [Vec<SampleCalc>]) {
+        if let Some((first, others)) = result.split_first_mut() {
+            self.get_samples(sample_count, first);
+            for channel in others.iter_mut() {
+                for (item, sample) in channel.iter_mut().zip(first.iter()).take(sample_count) {
+                    *item = *sample;
+                }
+            }
+        }
+    }
     /// Send a message to the `SoundGenerator`.
     fn process_command(&mut self, command: Self::Command);
 }
@@ -85,6 +163,15 @@ pub trait SoundStructure: HasTimer {
     fn get(&self, base_frequency: &[SampleCalc], result: &mut [SampleCalc]) -> SoundResult<()>;
 }
 
+/// A sound component with multichannel output (e.g. stereo).
+pub trait SoundStructureMultichannel: HasTimer {
+    /// Returns the calculated samples of each output channel in the `result` buffers.
+    fn get_channels(&self,
+                    base_frequency: &[SampleCalc],
+                    result: &mut [Vec<SampleCalc>])
+                    -> SoundResult<()>;
+}
+
 /// A structure of music.
 pub trait MusicStructure {
     /// Returns the calculated samples in the `result` buffer.
@@ -95,6 +182,26 @@ pub trait MusicStructure {
            -> SoundResult<()>;
 }
 
+/// Interleaves the first `frame_count` samples of each channel buffer into `result`, which
+/// must hold at least `frame_count` frames.
+pub fn interleave(channels: &[Vec<SampleCalc>],
+                  frame_count: usize,
+                  result: &mut [SampleOutput])
+                  -> SoundResult<()> {
+    if result.len() < frame_count * channels.len() {
+        return Err(Error::BufferSize);
+    }
+    for (channel_index, channel) in channels.iter().enumerate() {
+        if channel.len() < frame_count {
+            return Err(Error::BufferSize);
+        }
+        for (frame, sample) in channel.iter().take(frame_count).enumerate() {
+            result[frame * channels.len() + channel_index] = *sample;
+        }
+    }
+    Ok(())
+}
+
 /// Calculates the period of one sample for the given sample rate.
 /// Sample rates below 1.0 are considered to be invalid.
 pub fn get_sample_time(sample_rate: SampleCalc) -> SoundResult<SampleCalc> {
@@ -0,0 +1,358 @@
+use num::*;
+use sound::*;
+use std::fmt;
+use std::ops::{Add, Mul, Neg, Sub};
+
+/// Prime-exponent vector of a rational number, also known as
+/// [monzo](http://xenharmonic.wikispaces.com/Monzos). E.g. the syntonic comma (81:80) is
+/// `[-4 4 -1>`, because 81/80 = 2^-4 · 3^4 · 5^-1.
+///
+/// Multiplication of intervals is addition of their monzos, so they can be used for lattice
+/// ([Tonnetz](https://en.wikipedia.org/wiki/Tonnetz)) calculations. Only the primes with
+/// non-zero exponents are stored, so large prime factors do not make the vector long.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Monzo {
+    /// (prime, exponent) pairs in ascending order of the primes, without zero exponents.
+    factors: Vec<(u64, i32)>,
+}
+
+impl Monzo {
+    /// custom constructor, the exponents belong to the primes in ascending order (2, 3, 5, 7,
+    /// 11, ...).
+    pub fn from_exponents(exponents: &[i32]) -> Monzo {
+        let primes = get_primes(exponents.len());
+        let factors = primes.into_iter()
+            .zip(exponents.iter().cloned())
+            .filter(|&(_, exponent)| exponent != 0)
+            .collect();
+        Monzo { factors: factors }
+    }
+
+    /// Provides the exponents of the primes in ascending order, up to the prime limit.
+    pub fn get_exponents(&self) -> Vec<i32> {
+        let prime_limit = self.get_prime_limit();
+        let mut exponents = Vec::new();
+        let mut prime = 1;
+        while prime < prime_limit {
+            prime = next_prime(prime);
+            exponents.push(self.get_exponent(prime));
+        }
+        exponents
+    }
+
+    /// Provides the exponent of the given prime (0 if it is not a factor).
+    pub fn get_exponent(&self, prime: u64) -> i32 {
+        match self.factors.binary_search_by(|&(p, _)| p.cmp(&prime)) {
+            Ok(index) => self.factors[index].1,
+            Err(_) => 0,
+        }
+    }
+
+    /// Provides the (prime, exponent) pairs with non-zero exponents, in ascending order of the
+    /// primes.
+    pub fn get_factors(&self) -> &[(u64, i32)] {
+        &self.factors
+    }
+
+    /// True, if all the exponents are zero (unison).
+    pub fn is_unison(&self) -> bool {
+        self.factors.is_empty()
+    }
+
+    /// Provides the largest prime with non-zero exponent (1 for unison).
+    pub fn get_prime_limit(&self) -> u64 {
+        match self.factors.last() {
+            Some(&(prime, _)) => prime,
+            None => 1,
+        }
+    }
+
+    /// Provides the size of the interval in [cents](https://en.wikipedia.org/wiki/Cent_(music)).
+    pub fn get_cents(&self) -> SampleCalc {
+        let cents: f64 = self.factors
+            .iter()
+            .map(|&(prime, exponent)| exponent as f64 * 1200.0 * (prime as f64).log2())
+            .sum();
+        cents as SampleCalc
+    }
+
+    /// Provides the [Tenney height](http://xenharmonic.wikispaces.com/Tenney+Height):
+    /// log2(numerator · denominator).
+    pub fn get_tenney_height(&self) -> SampleCalc {
+        let height: f64 = self.factors
+            .iter()
+            .map(|&(prime, exponent)| exponent.abs() as f64 * (prime as f64).log2())
+            .sum();
+        height as SampleCalc
+    }
+
+    /// Converts the monzo to an interval. Returns `Error::Overflow` if the numerator or the
+    /// denominator can not be represented by `IntervalTerm`.
+    pub fn get_interval(&self) -> SoundResult<Interval> {
+        let (numerator, denominator) = self.get_terms()?;
+        let numerator: IntervalTerm = NumCast::from(numerator).ok_or(Error::Overflow)?;
+        let denominator: IntervalTerm = NumCast::from(denominator).ok_or(Error::Overflow)?;
+        Interval::new(numerator, denominator)
+    }
+
+    /// Converts the monzo to a wide interval. Returns `Error::Overflow` if the numerator or
+    /// the denominator does not fit in `u64`.
+    #[cfg(feature = "interval-wide")]
+    pub fn get_wide_interval(&self) -> SoundResult<WideInterval> {
+        let (numerator, denominator) = self.get_terms()?;
+        WideInterval::new(numerator, denominator)
+    }
+
+    /// Provides the (numerator, denominator) of the ratio. Returns `Error::Overflow` if they
+    /// do not fit in `u64`.
+    fn get_terms(&self) -> SoundResult<(u64, u64)> {
+        let mut numerator: u64 = 1;
+        let mut denominator: u64 = 1;
+        for &(prime, exponent) in &self.factors {
+            let term = if exponent > 0 {
+                &mut numerator
+            } else {
+                &mut denominator
+            };
+            for _ in 0..exponent.abs() {
+                *term = term.checked_mul(prime).ok_or(Error::Overflow)?;
+            }
+        }
+        Ok((numerator, denominator))
+    }
+
+    /// Factorizes the ratio, the terms must be coprime.
+    fn from_terms(numerator: u64, denominator: u64) -> Monzo {
+        let mut factors = Vec::new();
+        factorize(numerator, 1, &mut factors);
+        factorize(denominator, -1, &mut factors);
+        // the terms are coprime, so every prime occurs only once
+        factors.sort_by(|a, b| a.0.cmp(&b.0));
+        Monzo { factors: factors }
+    }
+
+    /// Adds the exponents of `rhs` multiplied by `multiplier`.
+    pub(crate) fn add_multiple(&self, rhs: &Monzo, multiplier: i32) -> Monzo {
+        let mut factors = Vec::with_capacity(self.factors.len() + rhs.factors.len());
+        let mut left = self.factors.iter().peekable();
+        let mut right = rhs.factors.iter().peekable();
+        loop {
+            let (prime, exponent) = match (left.peek().cloned(), right.peek().cloned()) {
+                (None, None) => break,
+                (Some(&l), None) => {
+                    let _ = left.next();
+                    l
+                }
+                (None, Some(&(prime, exponent))) => {
+                    let _ = right.next();
+                    (prime, exponent * multiplier)
+                }
+                (Some(&(prime_left, exponent_left)), Some(&(prime_right, exponent_right))) => {
+                    if prime_left < prime_right {
+                        let _ = left.next();
+                        (prime_left, exponent_left)
+                    } else if prime_right < prime_left {
+                        let _ = right.next();
+                        (prime_right, exponent_right * multiplier)
+                    } else {
+                        let _ = left.next();
+                        let _ = right.next();
+                        (prime_left, exponent_left + exponent_right * multiplier)
+                    }
+                }
+            };
+            if exponent != 0 {
+                factors.push((prime, exponent));
+            }
+        }
+        Monzo { factors: factors }
+    }
+}
+
+/// Provides the first `count` primes.
+fn get_primes(count: usize) -> Vec<u64> {
+    let mut primes = Vec::with_capacity(count);
+    let mut prime = 1;
+    while primes.len() < count {
+        prime = next_prime(prime);
+        primes.push(prime);
+    }
+    primes
+}
+
+/// Provides the smallest prime greater than `value`.
+fn next_prime(value: u64) -> u64 {
+    let mut candidate = value + 1;
+    loop {
+        if is_prime(candidate) {
+            return candidate;
+        }
+        candidate += 1;
+    }
+}
+
+/// Checks primality by trial division.
+pub(crate) fn is_prime(value: u64) -> bool {
+    if value < 2 {
+        return false;
+    }
+    let mut divisor = 2;
+    while divisor <= value / divisor {
+        if value % divisor == 0 {
+            return false;
+        }
+        divisor += 1;
+    }
+    true
+}
+
+/// Factorizes `value` by trial division, adds the prime factors with the exponents multiplied
+/// by `sign`.
+fn factorize(mut value: u64, sign: i32, factors: &mut Vec<(u64, i32)>) {
+    let mut divisor = 2;
+    while divisor <= value / divisor {
+        let mut exponent = 0;
+        while value % divisor == 0 {
+            value /= divisor;
+            exponent += 1;
+        }
+        if exponent > 0 {
+            factors.push((divisor, exponent * sign));
+        }
+        divisor += if divisor == 2 { 1 } else { 2 };
+    }
+    if value > 1 {
+        factors.push((value, sign));
+    }
+}
+
+impl From<Interval> for Monzo {
+    fn from(interval: Interval) -> Self {
+        Monzo::from_terms(interval.get_numerator().into(), interval.get_denominator().into())
+    }
+}
+
+#[cfg(feature = "interval-wide")]
+impl From<WideInterval> for Monzo {
+    fn from(interval: WideInterval) -> Self {
+        Monzo::from_terms(interval.get_numerator(), interval.get_denominator())
+    }
+}
+
+impl Add for Monzo {
+    type Output = Monzo;
+
+    fn add(self, rhs: Monzo) -> Monzo {
+        self.add_multiple(&rhs, 1)
+    }
+}
+
+impl Sub for Monzo {
+    type Output = Monzo;
+
+    fn sub(self, rhs: Monzo) -> Monzo {
+        self.add_multiple(&rhs, -1)
+    }
+}
+
+impl Neg for Monzo {
+    type Output = Monzo;
+
+    fn neg(self) -> Monzo {
+        Monzo {
+            factors: self.factors
+                .into_iter()
+                .map(|(prime, exponent)| (prime, -exponent))
+                .collect(),
+        }
+    }
+}
+
+impl Mul<i32> for Monzo {
+    type Output = Monzo;
+
+    fn mul(self, rhs: i32) -> Monzo {
+        Monzo::default().add_multiple(&self, rhs)
+    }
+}
+
+impl fmt::Display for Monzo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let exponents = self.get_exponents();
+        write!(f, "[")?;
+        for (index, exponent) in exponents.iter().enumerate() {
+            if index > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{}", exponent)?;
+        }
+        write!(f, ">")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sound::*;
+
+    #[test]
+    fn exponents_and_display() {
+        let syntonic_comma = Monzo::from_exponents(&[-4, 4, -1]);
+        assert_eq!(syntonic_comma.get_exponents(), vec![-4, 4, -1]);
+        assert_eq!(syntonic_comma.get_factors(), &[(2, -4), (3, 4), (5, -1)]);
+        assert_eq!(syntonic_comma.to_string(), "[-4 4 -1>");
+        assert_eq!(syntonic_comma.get_exponent(7), 0);
+        // the zero exponents are not stored, but they are displayed below the prime limit
+        let septimal = Monzo::from_exponents(&[0, 0, 0, 1]);
+        assert_eq!(septimal.get_factors(), &[(7, 1)]);
+        assert_eq!(septimal.to_string(), "[0 0 0 1>");
+        assert_eq!(Monzo::from_exponents(&[0, 0]).get_exponents(), Vec::<i32>::new());
+        assert_eq!(Monzo::default().to_string(), "[>");
+        assert!(Monzo::from_exponents(&[0, 0]).is_unison());
+    }
+
+    #[test]
+    fn arithmetic() {
+        let fifth = Monzo::from(Interval::new(3, 2).unwrap());
+        let fourth = Monzo::from(Interval::new(4, 3).unwrap());
+        let major_third = Monzo::from_exponents(&[-2, 0, 1]);
+        // the merge drops the cancelled exponents
+        assert_eq!(fifth.clone() + fourth.clone(), Monzo::from_exponents(&[1]));
+        assert_eq!((fifth.clone() + fourth.clone()).get_factors(), &[(2, 1)]);
+        assert_eq!(fifth.clone() - fifth.clone(), Monzo::default());
+        assert_eq!(fifth.clone() + major_third.clone(),
+                   Monzo::from(Interval::new(15, 8).unwrap()));
+        assert_eq!(major_third.clone() - fifth.clone(), Monzo::from_exponents(&[-1, -1, 1]));
+        assert_eq!(-fifth.clone(), Monzo::from_exponents(&[1, -1]));
+        assert_eq!(fifth.clone() * 4 - Monzo::from_exponents(&[2]) - major_third,
+                   Monzo::from_exponents(&[-4, 4, -1]));
+        assert_eq!(fifth.clone() * 0, Monzo::default());
+        assert_eq!(fifth.clone() * -2, Monzo::from_exponents(&[2, -2]));
+    }
+
+    #[test]
+    fn conversions() {
+        let comma = Monzo::from_exponents(&[-4, 4, -1]);
+        let interval = comma.get_interval().unwrap();
+        assert_eq!((interval.get_numerator(), interval.get_denominator()), (81, 80));
+        assert_eq!(Monzo::from(Interval::new(81, 80).unwrap()), comma);
+        assert!((comma.get_cents() - 21.506).abs() < 0.001);
+        assert!((comma.get_tenney_height() - 6480.0f32.log2()).abs() < 1e-4);
+        match Monzo::from_exponents(&[0, -11]).get_interval() {
+            Err(Error::Overflow) => {}
+            _ => panic!("Overflow expected"),
+        }
+    }
+
+    #[cfg(feature = "interval-wide")]
+    #[test]
+    fn wide_conversions() {
+        // 3^40 fits in u64, 3^41 does not
+        let wide = Monzo::from_exponents(&[0, 40]).get_wide_interval().unwrap();
+        assert_eq!(wide.get_numerator(), 3u64.pow(40));
+        assert_eq!(Monzo::from(wide), Monzo::from_exponents(&[0, 40]));
+        match Monzo::from_exponents(&[0, 41]).get_wide_interval() {
+            Err(Error::Overflow) => {}
+            _ => panic!("Overflow expected"),
+        }
+    }
+}
@@ -0,0 +1,304 @@
+use sound::*;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+/// The lowest frequency of the pink and brown noise spectrum slope.
+const NOISE_FREQUENCY_LOW: SampleCalc = 10.0;
+
+/// The number of samples used for measuring the power gain of the pink noise filter.
+const NOISE_IMPULSE_LENGTH: usize = 1 << 16;
+
+/// Spectral types of the noise.
+#[derive(Debug, Copy, Clone)]
+pub enum NoiseColor {
+    /// Equal power at every frequency.
+    White,
+    /// The power decreases by 3 dB per octave (equal power in every octave).
+    Pink,
+    /// The power decreases by 6 dB per octave (random walk).
+    Brown,
+    /// Band of noise, centered on the base frequency. The interval is the bandwidth (e.g.
+    /// 2:1 is one octave).
+    Band(Interval),
+}
+
+/// Seedable, deterministic noise generator. The same seed always produces the same noise, it
+/// restarts from the seed on `restart()`. All the colors have about the same RMS level as
+/// the white noise (1/√3), with an amplitude function applied.
+///
+/// The pink and brown noise is made by first order filters from `NOISE_FREQUENCY_LOW` (10 Hz)
+/// up to the half of the sample rate, independently of the sample rate. The pink filter has
+/// one pole-zero pair in every octave, it's deviation from the ideal slope is within ±0.35 dB
+/// from 20 Hz to 10 kHz (at 48 kHz sample rate it rises by up to 1.4 dB between 10 kHz and
+/// 20 kHz, at 192 kHz it stays within 0.1 dB). The band noise uses a
+/// [band-pass filter](http://www.musicdsp.org/files/Audio-EQ-Cookbook.txt), which follows the
+/// base frequency, so it can be transposed by intervals like the other sound structures. It
+/// is silent while the base frequency is above `TONE_FREQUENCY_MAX` or the half of the sample
+/// rate.
+pub struct Noise {
+    sample_rate: SampleCalc,
+    frequency_limit: SampleCalc,
+    color: NoiseColor,
+    seed: u64,
+    random: Cell<Random>,
+    amplitude: Rc<AmplitudeJoinable>,
+    /// (pole, zero) of the first order filter sections of pink and brown noise.
+    sections: Vec<(SampleCalc, SampleCalc)>,
+    /// Normalization gain of the filter.
+    gain: SampleCalc,
+    /// (previous input, previous output) of each filter section.
+    section_states: RefCell<Vec<(SampleCalc, SampleCalc)>>,
+    /// Quality factor of the band-pass filter.
+    quality: SampleCalc,
+    /// Band-pass filter state: x1, x2, y1, y2.
+    band_state: RefCell<[SampleCalc; 4]>,
+}
+
+impl Noise {
+    /// custom constructor
+    pub fn new(sample_rate: SampleCalc,
+               color: NoiseColor,
+               seed: u64,
+               amplitude: Rc<AmplitudeJoinable>)
+               -> SoundResult<Noise> {
+        let sample_time = get_sample_time(sample_rate)?;
+        let mut sections = Vec::new();
+        let mut gain = 1.0;
+        let mut quality = 0.0;
+        match color {
+            NoiseColor::White => {}
+            NoiseColor::Pink => {
+                let mut frequency = NOISE_FREQUENCY_LOW;
+                while frequency < sample_rate * 0.5 {
+                    let pole = (-PI2 * frequency * sample_time).exp();
+                    let zero = (-PI2 * frequency * ::std::f32::consts::SQRT_2 * sample_time)
+                        .exp();
+                    sections.push((pole, zero));
+                    frequency *= 2.0;
+                }
+                gain = 1.0 / get_power_gain(&sections).sqrt();
+            }
+            NoiseColor::Brown => {
+                let pole = (-PI2 * NOISE_FREQUENCY_LOW * sample_time).exp();
+                sections.push((pole, 0.0));
+                gain = (1.0 - pole * pole).sqrt();
+            }
+            NoiseColor::Band(bandwidth) => {
+                let ratio = bandwidth.get_ratio().max(bandwidth.get_recip());
+                if ratio <= 1.0 {
+                    return Err(Error::BandwidthInvalid);
+                }
+                quality = ratio.sqrt() / (ratio - 1.0);
+            }
+        }
+        Ok(Noise {
+            sample_rate: sample_rate,
+            frequency_limit: TONE_FREQUENCY_MAX.min(sample_rate * 0.5),
+            color: color,
+            seed: seed,
+            random: Cell::new(Random::new(seed)),
+            amplitude: amplitude,
+            section_states: RefCell::new(vec![(0.0, 0.0); sections.len()]),
+            sections: sections,
+            gain: gain,
+            quality: quality,
+            band_state: RefCell::new([0.0; 4]),
+        })
+    }
+
+    /// Provides the color of the noise.
+    pub fn get_color(&self) -> NoiseColor {
+        self.color
+    }
+
+    /// Provides the seed of the noise.
+    pub fn get_seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Applies the filter sections of the pink and brown noise.
+    fn get_filtered(&self, result: &mut [SampleCalc]) {
+        let mut random = self.random.get();
+        let mut section_states = self.section_states.borrow_mut();
+        for item in result.iter_mut() {
+            let mut sample = random.next_bipolar() as SampleCalc;
+            for (&(pole, zero), state) in self.sections.iter().zip(section_states.iter_mut()) {
+                let output = sample - zero * state.0 + pole * state.1;
+                *state = (sample, output);
+                sample = output;
+            }
+            *item = sample * self.gain;
+        }
+        self.random.set(random);
+    }
+
+    /// Applies the band-pass filter, with the center frequencies of `base_frequency`.
+    fn get_band(&self, base_frequency: &[SampleCalc], result: &mut [SampleCalc]) {
+        let mut random = self.random.get();
+        let mut state = self.band_state.borrow_mut();
+        let mut frequency_last = 0.0;
+        let (mut b0, mut a1, mut a2, mut gain) = (0.0, 0.0, 0.0, 0.0);
+        for (item, frequency) in result.iter_mut().zip(base_frequency) {
+            if *frequency != frequency_last {
+                frequency_last = *frequency;
+                if (*frequency > 0.0) && (*frequency < self.frequency_limit) {
+                    let omega = PI2 * frequency / self.sample_rate;
+                    let (sin, cos) = omega.sin_cos();
+                    let alpha = sin / (2.0 * self.quality);
+                    b0 = alpha / (1.0 + alpha);
+                    a1 = -2.0 * cos / (1.0 + alpha);
+                    a2 = (1.0 - alpha) / (1.0 + alpha);
+                    // the equivalent noise bandwidth is π/2 times the bandwidth
+                    gain = (self.quality * self.sample_rate / (PI2 * 0.5 * frequency)).sqrt();
+                } else {
+                    b0 = 0.0;
+                    gain = 0.0;
+                }
+            }
+            let input = random.next_bipolar() as SampleCalc;
+            let output = b0 * (input - state[1]) - a1 * state[2] - a2 * state[3];
+            *state = [input, state[0], output, state[2]];
+            *item = output * gain;
+        }
+        self.random.set(random);
+    }
+}
+
+/// Calculates the power gain of the filter sections for white noise (the sum of the squared
+/// impulse response).
+fn get_power_gain(sections: &[(SampleCalc, SampleCalc)]) -> SampleCalc {
+    let mut states = vec![(0.0, 0.0); sections.len()];
+    let mut power: f64 = 0.0;
+    for index in 0..NOISE_IMPULSE_LENGTH {
+        let mut sample = if index == 0 { 1.0 } else { 0.0 };
+        for (&(pole, zero), state) in sections.iter().zip(states.iter_mut()) {
+            let output: SampleCalc = sample - zero * state.0 + pole * state.1;
+            *state = (sample, output);
+            sample = output;
+        }
+        power += (sample * sample) as f64;
+    }
+    power as SampleCalc
+}
+
+impl HasTimer for Noise {
+    fn set_timing(&self, timing: TimingOption) -> SoundResult<()> {
+        self.amplitude.set_timing(timing)?;
+        self.restart();
+        Ok(())
+    }
+
+    fn get_timing(&self) -> TimingOption {
+        self.amplitude.get_timing()
+    }
+
+    fn restart(&self) {
+        self.amplitude.restart();
+        self.random.set(Random::new(self.seed));
+        for state in self.section_states.borrow_mut().iter_mut() {
+            *state = (0.0, 0.0);
+        }
+        *self.band_state.borrow_mut() = [0.0; 4];
+    }
+
+    fn apply_parent_timing(&self, parent_timing: TimingOption) -> SoundResult<()> {
+        self.amplitude.apply_parent_timing(parent_timing)
+    }
+}
+
+impl SoundStructure for Noise {
+    fn get(&self, base_frequency: &[SampleCalc], result: &mut [SampleCalc]) -> SoundResult<()> {
+        if base_frequency.len() != result.len() {
+            return Err(Error::BufferSize);
+        }
+        match self.color {
+            NoiseColor::White => {
+                let mut random = self.random.get();
+                for item in result.iter_mut() {
+                    *item = random.next_bipolar() as SampleCalc;
+                }
+                self.random.set(random);
+            }
+            NoiseColor::Pink | NoiseColor::Brown => self.get_filtered(result),
+            NoiseColor::Band(_) => self.get_band(base_frequency, result),
+        }
+        self.amplitude.apply(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sound::*;
+    use std::rc::Rc;
+
+    fn new_noise(color: NoiseColor, seed: u64) -> Noise {
+        let amplitude = AmplitudeConst::new(48000.0).unwrap();
+        Noise::new(48000.0, color, seed, Rc::new(amplitude)).unwrap()
+    }
+
+    fn render(noise: &Noise, frequency: SampleCalc, sample_count: usize) -> Vec<SampleCalc> {
+        let mut result = vec![0.0; sample_count];
+        noise.get(&vec![frequency; sample_count], &mut result).unwrap();
+        result
+    }
+
+    fn get_rms(samples: &[SampleCalc]) -> SampleCalc {
+        let power: f64 = samples.iter().map(|sample| (sample * sample) as f64).sum();
+        (power / samples.len() as f64).sqrt() as SampleCalc
+    }
+
+    fn get_colors() -> Vec<NoiseColor> {
+        vec![NoiseColor::White,
+             NoiseColor::Pink,
+             NoiseColor::Brown,
+             NoiseColor::Band(Interval::new(2, 1).unwrap())]
+    }
+
+    #[test]
+    fn deterministic() {
+        for color in get_colors() {
+            let noise = new_noise(color, 42);
+            let first = render(&noise, 1000.0, 1000);
+            assert_eq!(first, render(&new_noise(color, 42), 1000.0, 1000));
+            assert!(first != render(&new_noise(color, 43), 1000.0, 1000));
+            let second = render(&noise, 1000.0, 1000);
+            assert!(first != second);
+            noise.restart();
+            assert_eq!(render(&noise, 1000.0, 1000), first);
+            assert_eq!(noise.get_seed(), 42);
+        }
+    }
+
+    #[test]
+    fn rms_level() {
+        let level = 1.0 / 3.0f32.sqrt();
+        for color in get_colors() {
+            let noise = new_noise(color, 7);
+            // the filters settle in the first second
+            let _ = render(&noise, 1000.0, 48000);
+            let rms = get_rms(&render(&noise, 1000.0, 8 * 48000));
+            assert!((rms / level - 1.0).abs() < 0.1);
+        }
+    }
+
+    #[test]
+    fn band_noise() {
+        let amplitude = Rc::new(AmplitudeConst::new(48000.0).unwrap());
+        for &(numerator, denominator) in &[(1, 1), (3, 3)] {
+            let bandwidth = NoiseColor::Band(Interval::new(numerator, denominator).unwrap());
+            match Noise::new(48000.0, bandwidth, 1, amplitude.clone()) {
+                Err(Error::BandwidthInvalid) => {}
+                _ => panic!("BandwidthInvalid expected"),
+            }
+        }
+        // a downward interval is the same bandwidth
+        let down = new_noise(NoiseColor::Band(Interval::new(1, 2).unwrap()), 1);
+        let up = new_noise(NoiseColor::Band(Interval::new(2, 1).unwrap()), 1);
+        assert_eq!(render(&down, 1000.0, 100), render(&up, 1000.0, 100));
+        // silent above the half of the sample rate
+        let noise = new_noise(NoiseColor::Band(Interval::new(2, 1).unwrap()), 1);
+        assert!(render(&noise, 30000.0, 100).iter().all(|item| *item == 0.0));
+        let mut result = vec![0.0; 10];
+        assert!(noise.get(&[1000.0; 5], &mut result).is_err());
+    }
+}
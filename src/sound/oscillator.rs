@@ -0,0 +1,210 @@
+use sound::*;
+
+/// The number of samples in one period of the `SineTable`.
+const SINE_TABLE_SIZE: usize = 4096;
+
+/// The rotation oscillator is resynchronized to the exact phase after this many samples.
+pub const ROTATION_SYNC_PERIOD: usize = 1024;
+
+/// Sine oscillator cores of `Wave`. Each of them keeps the phase continuous under per-sample
+/// frequency changes, and they can be switched at any time.
+///
+/// Measured maximal errors against a sine with `f64` phase accumulation, in buffers of 512
+/// samples at 192 kHz, with sweeping frequencies from 20 Hz to 20 kHz (each buffer starts from
+/// the exact phase, it is checked by a test). Most of the error of `Exact` comes from the `f32`
+/// phase, which grows during the buffer. Speeds are measured by the `wave_*` benchmarks
+/// (constant frequency), the results depend on the CPU.
+///
+/// | oscillator | max. error | speed (relative to `Exact`) |
+/// |------------|------------|-----------------------------|
+/// | `Exact`    | 1.3e-4     | 1                           |
+/// | `Rotation` | 2.0e-5     | ~1.7× faster                |
+/// | `Table`    | 9.0e-6     | about the same              |
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Oscillator {
+    /// `sin()` is called for every sample.
+    Exact,
+    /// Recursive rotation of a phasor. The rotation is only recalculated when the frequency
+    /// changes, and the phasor is resynchronized to the exact phase after every
+    /// `ROTATION_SYNC_PERIOD` samples (and at the start of each buffer), so the errors can not
+    /// accumulate. The error bound is about `ROTATION_SYNC_PERIOD` × `f32::EPSILON`.
+    Rotation,
+    /// Linear interpolation in a table of 4096 samples. The interpolation error is
+    /// `(2π / 4096)² / 8` ≈ 3e-7, the rest comes from the `f32` table position, which is
+    /// advanced sample by sample.
+    Table,
+}
+
+/// One period of a sine wave, for the `Oscillator::Table` oscillator.
+#[derive(Debug, Clone)]
+pub struct SineTable {
+    /// `SINE_TABLE_SIZE + 1` samples, the last one is equal to the first one.
+    values: Vec<SampleCalc>,
+    /// Converts phase to table position.
+    scale: SampleCalc,
+}
+
+impl SineTable {
+    /// custom constructor
+    pub fn new() -> SineTable {
+        let values = (0..(SINE_TABLE_SIZE + 1))
+            .map(|index| {
+                (index as f64 * ::std::f64::consts::PI * 2.0 / SINE_TABLE_SIZE as f64).sin() as
+                SampleCalc
+            })
+            .collect();
+        SineTable {
+            values: values,
+            scale: SINE_TABLE_SIZE as SampleCalc / PI2,
+        }
+    }
+
+    /// Returns the sine of the `phase` (in radians), which can be any finite value.
+    pub fn get(&self, phase: SampleCalc) -> SampleCalc {
+        self.get_at(self.get_position(phase))
+    }
+
+    /// Converts the `phase` (in radians) to table position, in the [0, 4096) range.
+    pub fn get_position(&self, phase: SampleCalc) -> SampleCalc {
+        self.wrap_position((phase % PI2) * self.scale)
+    }
+
+    /// Provides the table positions per radian.
+    pub fn get_position_scale(&self) -> SampleCalc {
+        self.scale
+    }
+
+    /// Moves the `position` into the [0, 4096) range. It is fast if the position is at most
+    /// one period outside of the range.
+    pub fn wrap_position(&self, position: SampleCalc) -> SampleCalc {
+        let size = SINE_TABLE_SIZE as SampleCalc;
+        let position = if position >= size {
+            position - size
+        } else if position < 0.0 {
+            position + size
+        } else {
+            position
+        };
+        if (position >= 0.0) && (position < size) {
+            return position;
+        }
+        // far outside, or rounded to `size` (e.g. a tiny negative position)
+        let position = position % size;
+        if position < 0.0 {
+            (position + size) % size
+        } else {
+            position
+        }
+    }
+
+    /// Advances the table `position` by `increment`, and moves it into the [0, 4096) range.
+    /// The increment can be more than one period (above the Nyquist frequency, e.g. for high
+    /// overtones after a frequency jump).
+    pub fn advance_position(&self, position: SampleCalc, increment: SampleCalc) -> SampleCalc {
+        self.wrap_position(position + increment)
+    }
+
+    /// Returns the interpolated value at the table `position`, which must be in the
+    /// [0, 4096) range.
+    pub fn get_at(&self, position: SampleCalc) -> SampleCalc {
+        let index = position as usize;
+        let fraction = position - index as SampleCalc;
+        let value = self.values[index];
+        value + (self.values[index + 1] - value) * fraction
+    }
+}
+
+impl Default for SineTable {
+    fn default() -> SineTable {
+        SineTable::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sound::*;
+
+    #[test]
+    fn advance_position_more_than_one_period() {
+        let sine_table = SineTable::new();
+        let size = 4096.0;
+        assert_eq!(sine_table.advance_position(4000.0, 100.0), 4.0);
+        assert_eq!(sine_table.advance_position(100.0, 2.5 * size), 100.0 + 0.5 * size);
+        assert_eq!(sine_table.advance_position(100.0, -3.0 * size - 200.0), size - 100.0);
+        let position = sine_table.advance_position(-1e-6, 0.0);
+        assert!((position >= 0.0) && (position < size));
+    }
+
+    #[test]
+    fn table_wave_above_nyquist_frequency() {
+        // the 17th partial of 3000 Hz at 44.1 kHz is 51 kHz: more than 2π per sample
+        let sine_table = SineTable::new();
+        let mut wave = Wave::new(44_100.0, 16).unwrap();
+        let base_frequency = [1000.0, 1000.0, 3000.0, 3000.0, 3000.0];
+        let mut result = [0.0; 5];
+        wave.get_table(&sine_table, &base_frequency, &mut result).unwrap();
+        let mut exact_wave = Wave::new(44_100.0, 16).unwrap();
+        let mut exact = [0.0; 5];
+        exact_wave.get(&base_frequency, &mut exact).unwrap();
+        for (item, exact_item) in result.iter().zip(&exact) {
+            assert!((item - exact_item).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn tiny_negative_phase() {
+        let sine_table = SineTable::new();
+        for &phase in &[-1e-8, -1e-30, -PI2, PI2, -3.0, 10.0] {
+            let position = sine_table.get_position(phase);
+            assert!((position >= 0.0) && (position < 4096.0));
+            assert!((sine_table.get(phase) - phase.sin()).abs() < 1e-3);
+        }
+        assert_eq!(sine_table.wrap_position(-1e-8), 0.0);
+    }
+
+    #[test]
+    fn error_bounds_of_the_documentation() {
+        // 512 samples buffers at 192 kHz, sweeping from 20 Hz to 20 kHz
+        let sample_rate = 192_000.0;
+        let buffer_size = 512;
+        let buffer_count = 200;
+        let sine_table = SineTable::new();
+        let mut exact_wave = Wave::new(sample_rate, 0).unwrap();
+        let mut rotation_wave = Wave::new(sample_rate, 0).unwrap();
+        let mut table_wave = Wave::new(sample_rate, 0).unwrap();
+        let mut exact = vec![0.0; buffer_size];
+        let mut rotation = vec![0.0; buffer_size];
+        let mut table = vec![0.0; buffer_size];
+        let mut error_max = [0.0f64; 3];
+        for buffer_index in 0..buffer_count {
+            // the error of the phase accumulated during the previous buffers is not measured
+            let phase_begin = (buffer_index as SampleCalc * 0.7) % PI2;
+            exact_wave.set_phase(phase_begin);
+            rotation_wave.set_phase(phase_begin);
+            table_wave.set_phase(phase_begin);
+            let mut phase = phase_begin as f64;
+            let frequency: Vec<SampleCalc> = (0..buffer_size)
+                .map(|index| {
+                    let position = (buffer_index * buffer_size + index) as SampleCalc /
+                                   (buffer_count * buffer_size) as SampleCalc;
+                    20.0 * (1000.0 as SampleCalc).powf(position)
+                })
+                .collect();
+            exact_wave.get(&frequency, &mut exact).unwrap();
+            rotation_wave.get_rotation(&frequency, &mut rotation).unwrap();
+            table_wave.get_table(&sine_table, &frequency, &mut table).unwrap();
+            for index in 0..buffer_size {
+                phase += frequency[index] as f64 * 2.0 * ::std::f64::consts::PI /
+                         sample_rate as f64;
+                let reference = phase.sin();
+                for (error, item) in error_max.iter_mut()
+                    .zip(&[exact[index], rotation[index], table[index]]) {
+                    *error = error.max((*item as f64 - reference).abs());
+                }
+            }
+        }
+        assert!(error_max[0] < 1.3e-4);
+        assert!(error_max[1] < 2.0e-5);
+        assert!(error_max[2] < 9.0e-6);
+    }
+}
@@ -0,0 +1,343 @@
+use sound::*;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The input level where the soft saturation starts.
+const SATURATION_THRESHOLD: f64 = 0.9;
+
+/// Seed of the dither noise.
+const DITHER_SEED: u64 = 0x5DEE_CE66_D1CE_F00D;
+
+/// Output sample formats.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SampleFormat {
+    /// 16-bit integer PCM.
+    Int16,
+    /// 24-bit integer PCM. Backends without packed 24-bit support get it in the upper 24 bits
+    /// of a 32-bit integer.
+    Int24,
+    /// 32-bit integer PCM.
+    Int32,
+    /// 32-bit floating point PCM.
+    Float32,
+}
+
+impl SampleFormat {
+    /// Returns the size of one sample in bytes.
+    pub fn get_bytes_per_sample(&self) -> u16 {
+        match *self {
+            SampleFormat::Int16 => 2,
+            SampleFormat::Int24 => 3,
+            SampleFormat::Int32 |
+            SampleFormat::Float32 => 4,
+        }
+    }
+
+    /// Returns `true` for the integer formats.
+    pub fn is_integer(&self) -> bool {
+        *self != SampleFormat::Float32
+    }
+
+    /// Returns the largest integer value, which represents the amplitude of 1.0. For the
+    /// floating point format it is 1.0.
+    pub fn get_full_scale(&self) -> f64 {
+        match *self {
+            SampleFormat::Int16 => 32767.0,
+            SampleFormat::Int24 => 8388607.0,
+            SampleFormat::Int32 => 2147483647.0,
+            SampleFormat::Float32 => 1.0,
+        }
+    }
+}
+
+/// Dither options for the conversion to integer formats.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Dither {
+    /// Simple rounding, the quantization error correlates with the signal.
+    None,
+    /// Triangular probability density function dither of ±1 LSB. The quantization error
+    /// becomes white noise, independent of the signal.
+    Tpdf,
+    /// TPDF dither with second order noise shaping (error feedback filter: `(1 - z⁻¹)²`). The
+    /// noise is moved to the high frequencies, which is most effective at high sample rates.
+    TpdfShaped,
+}
+
+/// Handling of the samples exceeding the [-1.0, 1.0] range.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ClipMode {
+    /// Out of range samples are cut to the limits.
+    Hard,
+    /// Samples above 90% of the full scale are compressed smoothly (with a `tanh` curve), so
+    /// they never exceed the limits.
+    Soft,
+}
+
+/// Types of the samples passed to the output backends.
+pub trait OutputSample: Copy {
+    /// Converts the value produced by `SampleConverter::convert_sample()`.
+    fn from_converted(value: f64, sample_format: SampleFormat) -> Self;
+}
+
+impl OutputSample for i16 {
+    fn from_converted(value: f64, sample_format: SampleFormat) -> Self {
+        match sample_format {
+            SampleFormat::Float32 => (value * 32767.0).round() as i16,
+            SampleFormat::Int16 => value as i16,
+            _ => (value / sample_format.get_full_scale() * 32767.0).round() as i16,
+        }
+    }
+}
+
+impl OutputSample for i32 {
+    fn from_converted(value: f64, sample_format: SampleFormat) -> Self {
+        match sample_format {
+            SampleFormat::Int24 => (value as i32) << 8,
+            SampleFormat::Int32 => value as i32,
+            _ => (value / sample_format.get_full_scale() * 2147483647.0).round() as i32,
+        }
+    }
+}
+
+impl OutputSample for f32 {
+    fn from_converted(value: f64, sample_format: SampleFormat) -> Self {
+        (value / sample_format.get_full_scale()) as f32
+    }
+}
+
+/// Converts the calculated samples into an output sample format, with explicit clipping
+/// (or saturation) and optional dithering. The samples exceeding the [-1.0, 1.0] range are
+/// counted.
+#[derive(Debug, Clone)]
+pub struct SampleConverter {
+    sample_format: SampleFormat,
+    channel_count: usize,
+    dither: Dither,
+    clip_mode: ClipMode,
+    random: Random,
+    /// The last two quantization errors of each channel, for the noise shaping.
+    errors: Vec<[f64; 2]>,
+    /// The channel of the next interleaved sample.
+    channel_index: usize,
+    clipped_count: Arc<AtomicUsize>,
+}
+
+impl SampleConverter {
+    /// custom constructor, without dither and with hard clipping.
+    pub fn new(sample_format: SampleFormat,
+               channel_count: usize)
+               -> SoundResult<SampleConverter> {
+        if channel_count == 0 {
+            return Err(Error::ChannelInvalid);
+        }
+        Ok(SampleConverter {
+            sample_format: sample_format,
+            channel_count: channel_count,
+            dither: Dither::None,
+            clip_mode: ClipMode::Hard,
+            random: Random::new(DITHER_SEED),
+            errors: vec![[0.0; 2]; channel_count],
+            channel_index: 0,
+            clipped_count: Arc::new(AtomicUsize::new(0)),
+        })
+    }
+
+    /// Sets the dither option. It has no effect on the floating point format.
+    pub fn set_dither(&mut self, dither: Dither) {
+        self.dither = dither;
+    }
+
+    /// Sets the handling of out of range samples.
+    pub fn set_clip_mode(&mut self, clip_mode: ClipMode) {
+        self.clip_mode = clip_mode;
+    }
+
+    /// Provides the sample format.
+    pub fn get_sample_format(&self) -> SampleFormat {
+        self.sample_format
+    }
+
+    /// Provides the channel count.
+    pub fn get_channel_count(&self) -> usize {
+        self.channel_count
+    }
+
+    /// Returns the number of samples which exceeded the [-1.0, 1.0] range.
+    pub fn get_clipped_count(&self) -> usize {
+        self.clipped_count.load(Ordering::Relaxed)
+    }
+
+    /// Returns the shared counter of the clipped samples. It can be read from another thread,
+    /// while the converter is used by a backend.
+    pub fn get_clipped_counter(&self) -> Arc<AtomicUsize> {
+        self.clipped_count.clone()
+    }
+
+    /// Converts the next sample of the interleaved stream. For integer formats the result is
+    /// the integer value (as a float), for the floating point format it is the limited
+    /// sample. NaN and infinite samples are converted to silence (and counted as clipped).
+    pub fn convert_sample(&mut self, sample: SampleCalc) -> f64 {
+        let channel = self.channel_index;
+        self.channel_index = (self.channel_index + 1) % self.channel_count;
+        let mut value = sample as f64;
+        if !(value.abs() <= 1.0) {
+            let _ = self.clipped_count.fetch_add(1, Ordering::Relaxed);
+            if !value.is_finite() {
+                value = 0.0;
+            }
+        }
+        value = match self.clip_mode {
+            ClipMode::Hard => value.max(-1.0).min(1.0),
+            ClipMode::Soft => saturate(value),
+        };
+        if !self.sample_format.is_integer() {
+            return value;
+        }
+        let full_scale = self.sample_format.get_full_scale();
+        value *= full_scale;
+        let quantized = match self.dither {
+            Dither::None => value.round(),
+            Dither::Tpdf => {
+                let noise = self.random.next_f64() - self.random.next_f64();
+                (value + noise).round()
+            }
+            Dither::TpdfShaped => {
+                let errors = &mut self.errors[channel];
+                // the limits are applied inside the loop, so the fed back error is the
+                // difference of the output value and the (limited) shaped value, and the filter
+                // stays stable near full scale
+                let shaped = (value - (2.0 * errors[0] - errors[1]))
+                    .max(-full_scale - 1.0)
+                    .min(full_scale);
+                let noise = self.random.next_f64() - self.random.next_f64();
+                let quantized = (shaped + noise).round().max(-full_scale - 1.0).min(full_scale);
+                errors[1] = errors[0];
+                errors[0] = quantized - shaped;
+                quantized
+            }
+        };
+        quantized.max(-full_scale - 1.0).min(full_scale)
+    }
+
+    /// Converts interleaved samples into the `result` buffer. The buffers must have the same
+    /// length, which must be divisible by the channel count.
+    pub fn convert<S: OutputSample>(&mut self,
+                                    samples: &[SampleCalc],
+                                    result: &mut [S])
+                                    -> SoundResult<()> {
+        if (samples.len() != result.len()) || (samples.len() % self.channel_count != 0) {
+            return Err(Error::BufferSize);
+        }
+        self.channel_index = 0;
+        for (item, sample) in result.iter_mut().zip(samples) {
+            *item = S::from_converted(self.convert_sample(*sample), self.sample_format);
+        }
+        Ok(())
+    }
+
+    /// Converts interleaved samples into little-endian bytes, appended to `result`.
+    pub fn convert_to_bytes(&mut self,
+                            samples: &[SampleCalc],
+                            result: &mut Vec<u8>)
+                            -> SoundResult<()> {
+        if samples.len() % self.channel_count != 0 {
+            return Err(Error::BufferSize);
+        }
+        self.channel_index = 0;
+        for sample in samples {
+            let value = self.convert_sample(*sample);
+            match self.sample_format {
+                SampleFormat::Int16 => push_bytes(result, value as i16 as u32, 2),
+                SampleFormat::Int24 => push_bytes(result, value as i32 as u32, 3),
+                SampleFormat::Int32 => push_bytes(result, value as i32 as u32, 4),
+                SampleFormat::Float32 => push_bytes(result, (value as f32).to_bits(), 4),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Soft saturation: linear below `SATURATION_THRESHOLD`, above it the `tanh` curve
+/// approaches the full scale.
+fn saturate(value: f64) -> f64 {
+    let magnitude = value.abs();
+    if magnitude <= SATURATION_THRESHOLD {
+        return value;
+    }
+    let headroom = 1.0 - SATURATION_THRESHOLD;
+    let saturated = SATURATION_THRESHOLD +
+                    headroom * ((magnitude - SATURATION_THRESHOLD) / headroom).tanh();
+    saturated.min(1.0) * value.signum()
+}
+
+fn push_bytes(buffer: &mut Vec<u8>, value: u32, byte_count: usize) {
+    for byte_index in 0..byte_count {
+        buffer.push((value >> (byte_index * 8)) as u8);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sound::*;
+
+    #[test]
+    fn soft_clip() {
+        let mut converter = SampleConverter::new(SampleFormat::Float32, 1).unwrap();
+        converter.set_clip_mode(ClipMode::Soft);
+        assert_eq!(converter.convert_sample(0.8), 0.8f32 as f64);
+        assert_eq!(converter.convert_sample(-0.9), -0.9f32 as f64);
+        let saturated = converter.convert_sample(1.5);
+        assert!((saturated > 0.99) && (saturated <= 1.0));
+        assert_eq!(converter.get_clipped_count(), 1);
+    }
+
+    #[test]
+    fn noise_shaping_at_full_scale() {
+        let mut converter = SampleConverter::new(SampleFormat::Int16, 1).unwrap();
+        converter.set_dither(Dither::TpdfShaped);
+        let count = 10000;
+        let mut sum = 0.0;
+        for _ in 0..count {
+            let value = converter.convert_sample(1.0);
+            assert!((value >= 32760.0) && (value <= 32767.0));
+            sum += value;
+        }
+        assert!(sum / count as f64 > 32765.0);
+        for index in 0..count {
+            let sample = if index % 2 == 0 { 0.25 } else { -0.25 };
+            let value = converter.convert_sample(sample) / 32767.0;
+            assert!((value - sample as f64).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn non_finite_samples() {
+        let formats = [SampleFormat::Int16, SampleFormat::Int24, SampleFormat::Float32];
+        for &sample_format in &formats {
+            for &clip_mode in &[ClipMode::Hard, ClipMode::Soft] {
+                let mut converter = SampleConverter::new(sample_format, 1).unwrap();
+                converter.set_clip_mode(clip_mode);
+                for &sample in &[::std::f32::NAN, ::std::f32::INFINITY, ::std::f32::NEG_INFINITY] {
+                    assert_eq!(converter.convert_sample(sample), 0.0);
+                }
+                assert_eq!(converter.get_clipped_count(), 3);
+            }
+        }
+    }
+
+    #[test]
+    fn noise_shaping_mean_near_full_scale() {
+        // the output often hits the limit, the mean is still right when the error is
+        // measured on the limited output
+        let mut converter = SampleConverter::new(SampleFormat::Int16, 1).unwrap();
+        converter.set_dither(Dither::TpdfShaped);
+        let value = 32764.0 / 32767.0;
+        let count = 400_000;
+        let mut sum = 0.0;
+        for _ in 0..count {
+            sum += converter.convert_sample(value);
+        }
+        // (it is 8e-4 when the error is measured before the limit)
+        assert!((sum / count as f64 - value as f64 * 32767.0).abs() < 3e-4);
+    }
+}
@@ -0,0 +1,36 @@
+/// Seed used instead of 0, which is not allowed for the xorshift algorithm.
+const SEED_DEFAULT: u64 = 0x9E37_79B9_7F4A_7C15;
+
+/// Fast, seedable pseudo-random number generator
+/// ([xorshift64*](https://en.wikipedia.org/wiki/Xorshift#xorshift*)). The same seed always
+/// produces the same sequence, so the results are reproducible. It is not suitable for
+/// cryptography.
+#[derive(Debug, Copy, Clone)]
+pub struct Random {
+    state: u64,
+}
+
+impl Random {
+    /// custom constructor
+    pub fn new(seed: u64) -> Random {
+        Random { state: if seed == 0 { SEED_DEFAULT } else { seed } }
+    }
+
+    /// Returns the next random number.
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// Returns a random number with uniform distribution in the [0.0, 1.0) range.
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Returns a random number with uniform distribution in the [-1.0, 1.0) range.
+    pub fn next_bipolar(&mut self) -> f64 {
+        self.next_f64() * 2.0 - 1.0
+    }
+}
@@ -0,0 +1,510 @@
+use sound::*;
+use std::f64::consts::PI;
+
+/// The number of precalculated filter phases between two input samples. The coefficients of
+/// the intermediate positions are linearly interpolated.
+const PHASE_COUNT: usize = 512;
+
+/// The number of input frames which can be pushed in advance into a `Resampler` created by
+/// `Resampler::new()`.
+pub const RESAMPLER_INPUT_CAPACITY_DEFAULT: usize = 8192;
+
+/// Quality presets of the `Resampler`. Higher quality needs longer filters, so more CPU.
+///
+/// Measured with sine waves, converting 192 kHz to 48 kHz. The passband is the range where
+/// the gain error stays within the given limit, the alias rejection is the level of the
+/// aliases of 26 kHz ... 60 kHz input sines (relative to the input level):
+///
+/// | quality | taps (192 → 48 kHz) | passband            | -3 dB point | alias rejection |
+/// |---------|---------------------|---------------------|-------------|-----------------|
+/// | Low     | 76                  | ±0.05 dB to 16 kHz  | 19.3 kHz    | > 62 dB         |
+/// | Medium  | 144                 | ±0.005 dB to 18 kHz | 20.9 kHz    | > 96 dB         |
+/// | High    | 274                 | ±0.005 dB to 20 kHz | 22.1 kHz    | > 119 dB        |
+///
+/// For upsampling the tap count is smaller (20, 36 and 70), the passband extends to 85%,
+/// 90% and 94% of the input Nyquist frequency.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ResamplerQuality {
+    /// Short filter, for real-time use on slow machines.
+    Low,
+    /// Good compromise for real-time playback.
+    Medium,
+    /// Long filter, for offline rendering.
+    High,
+}
+
+impl ResamplerQuality {
+    /// Returns the (zero crossings on each side, Kaiser window β, cutoff relative to the
+    /// Nyquist frequency) parameters.
+    fn get_parameters(&self) -> (usize, f64, f64) {
+        match *self {
+            ResamplerQuality::Low => (8, 6.0, 0.85),
+            ResamplerQuality::Medium => (16, 9.0, 0.9),
+            ResamplerQuality::High => (32, 12.0, 0.94),
+        }
+    }
+}
+
+/// Band-limited sample rate converter: polyphase windowed-sinc filter with a
+/// [Kaiser window](https://en.wikipedia.org/wiki/Kaiser_window). When the output rate is lower
+/// than the input rate, the cutoff frequency follows the output Nyquist frequency, so there is
+/// no aliasing. The conversion does not shift the signal in time: the first output sample
+/// belongs to the first input sample.
+///
+/// The input is stored in preallocated ring buffers, so the conversion does not allocate
+/// memory (it can be used in real-time callbacks).
+#[derive(Debug, Clone)]
+pub struct Resampler {
+    input_rate: u32,
+    output_rate: u32,
+    /// Input samples per output sample.
+    step: f64,
+    /// The number of input samples before (and including) the output position, which are
+    /// used for one output sample.
+    half_tap_count: usize,
+    tap_count: usize,
+    /// Filter coefficients of `PHASE_COUNT + 1` phases, each of them has `tap_count` items.
+    coefficients: Vec<SampleCalc>,
+    /// Position of the next output sample, measured in input samples from the oldest sample
+    /// in the ring buffers.
+    position: f64,
+    /// Ring buffers of the input samples not yet consumed, for each channel. Each sample is
+    /// stored twice (at `index` and `index + ring_size`), so the samples of one output sample
+    /// are always contiguous.
+    history: Vec<Vec<SampleCalc>>,
+    ring_size: usize,
+    /// Index of the oldest sample in the ring buffers.
+    history_start: usize,
+    /// The number of samples in the ring buffers.
+    history_length: usize,
+}
+
+impl Resampler {
+    /// custom constructor, up to `RESAMPLER_INPUT_CAPACITY_DEFAULT` input frames can be pushed
+    /// in advance.
+    pub fn new(input_rate: u32,
+               output_rate: u32,
+               channel_count: usize,
+               quality: ResamplerQuality)
+               -> SoundResult<Resampler> {
+        Resampler::new_with_capacity(input_rate,
+                                     output_rate,
+                                     channel_count,
+                                     quality,
+                                     RESAMPLER_INPUT_CAPACITY_DEFAULT)
+    }
+
+    /// custom constructor, up to `input_capacity` input frames can be pushed in advance
+    /// (besides the samples kept for the filter).
+    pub fn new_with_capacity(input_rate: u32,
+                             output_rate: u32,
+                             channel_count: usize,
+                             quality: ResamplerQuality,
+                             input_capacity: usize)
+                             -> SoundResult<Resampler> {
+        if (input_rate == 0) || (output_rate == 0) {
+            return Err(Error::SampleRateInvalid);
+        }
+        if channel_count == 0 {
+            return Err(Error::ChannelInvalid);
+        }
+        let (zero_crossings, beta, rolloff) = quality.get_parameters();
+        let ratio = (output_rate as f64 / input_rate as f64).min(1.0);
+        // cutoff frequency in cycles per input sample
+        let cutoff = 0.5 * ratio * rolloff;
+        let half_width = zero_crossings as f64 / (2.0 * cutoff);
+        let half_tap_count = half_width.ceil() as usize;
+        let tap_count = half_tap_count * 2;
+        let mut coefficients = Vec::with_capacity((PHASE_COUNT + 1) * tap_count);
+        let bessel_beta = bessel_i0(beta);
+        for phase in 0..(PHASE_COUNT + 1) {
+            let fraction = phase as f64 / PHASE_COUNT as f64;
+            let phase_start = coefficients.len();
+            let mut sum = 0.0;
+            for tap in 0..tap_count {
+                let distance = tap as f64 + 1.0 - half_tap_count as f64 - fraction;
+                let window_position = distance / half_width;
+                let coefficient = if window_position.abs() >= 1.0 {
+                    0.0
+                } else {
+                    let window = bessel_i0(beta * (1.0 - window_position * window_position)
+                        .sqrt()) / bessel_beta;
+                    2.0 * cutoff * sinc(2.0 * cutoff * distance) * window
+                };
+                sum += coefficient;
+                coefficients.push(coefficient);
+            }
+            // unity gain for DC at every phase
+            for coefficient in &mut coefficients[phase_start..] {
+                *coefficient /= sum;
+            }
+        }
+        Ok(Resampler {
+            input_rate: input_rate,
+            output_rate: output_rate,
+            step: input_rate as f64 / output_rate as f64,
+            half_tap_count: half_tap_count,
+            tap_count: tap_count,
+            coefficients: coefficients.iter().map(|c| *c as SampleCalc).collect(),
+            position: (half_tap_count - 1) as f64,
+            history: vec![vec![0.0; 2 * (tap_count + input_capacity)]; channel_count],
+            ring_size: tap_count + input_capacity,
+            history_start: 0,
+            history_length: half_tap_count - 1,
+        })
+    }
+
+    /// Returns the number of input frames still needed for producing `frame_count` output
+    /// frames.
+    pub fn get_required_input(&self, frame_count: usize) -> usize {
+        if frame_count == 0 {
+            return 0;
+        }
+        let last_position = self.position + (frame_count - 1) as f64 * self.step;
+        let required_length = last_position.floor() as usize + self.half_tap_count + 1;
+        required_length.saturating_sub(self.history_length)
+    }
+
+    /// Appends the first `frame_count` samples of each input channel buffer. Returns
+    /// `Error::BufferSize` if the input capacity would be exceeded.
+    pub fn push_input(&mut self,
+                      input: &[Vec<SampleCalc>],
+                      frame_count: usize)
+                      -> SoundResult<()> {
+        if input.len() != self.history.len() {
+            return Err(Error::ChannelInvalid);
+        }
+        if (self.history_length + frame_count > self.ring_size) ||
+           input.iter().any(|channel| channel.len() < frame_count) {
+            return Err(Error::BufferSize);
+        }
+        let ring_size = self.ring_size;
+        let end = (self.history_start + self.history_length) % ring_size;
+        for (history, channel) in self.history.iter_mut().zip(input) {
+            for (offset, sample) in channel[..frame_count].iter().enumerate() {
+                let index = (end + offset) % ring_size;
+                history[index] = *sample;
+                history[index + ring_size] = *sample;
+            }
+        }
+        self.history_length += frame_count;
+        Ok(())
+    }
+
+    /// Produces `frame_count` output frames into the `result` buffers (one buffer per
+    /// channel). Returns `Error::BufferSize` if there is not enough input (see
+    /// `get_required_input()`).
+    pub fn get_output(&mut self,
+                      frame_count: usize,
+                      result: &mut [Vec<SampleCalc>])
+                      -> SoundResult<()> {
+        if result.len() != self.history.len() {
+            return Err(Error::ChannelInvalid);
+        }
+        if self.get_required_input(frame_count) > 0 {
+            return Err(Error::BufferSize);
+        }
+        for channel in result.iter() {
+            if channel.len() < frame_count {
+                return Err(Error::BufferSize);
+            }
+        }
+        for (history, output) in self.history.iter().zip(result.iter_mut()) {
+            let mut position = self.position;
+            for item in output.iter_mut().take(frame_count) {
+                let index = position.floor();
+                let phase_position = (position - index) * PHASE_COUNT as f64;
+                let phase = phase_position.floor();
+                let interpolation = (phase_position - phase) as SampleCalc;
+                let start = (self.history_start + index as usize + 1 - self.half_tap_count) %
+                            self.ring_size;
+                let phase_start = phase as usize * self.tap_count;
+                let coefficients = &self.coefficients[phase_start..
+                                                      (phase_start + 2 * self.tap_count)];
+                let (coefficients0, coefficients1) = coefficients.split_at(self.tap_count);
+                let mut sum = 0.0;
+                for ((sample, c0), c1) in history[start..(start + self.tap_count)]
+                    .iter()
+                    .zip(coefficients0)
+                    .zip(coefficients1) {
+                    sum += sample * (c0 + (c1 - c0) * interpolation);
+                }
+                *item = sum;
+                position += self.step;
+            }
+        }
+        self.position += frame_count as f64 * self.step;
+        // drop the samples which are not needed anymore
+        let consumed = (self.position.floor() as usize + 1).saturating_sub(self.half_tap_count);
+        self.history_start = (self.history_start + consumed) % self.ring_size;
+        self.history_length -= consumed;
+        self.position -= consumed as f64;
+        Ok(())
+    }
+
+    /// Provides the input sample rate.
+    pub fn get_input_rate(&self) -> u32 {
+        self.input_rate
+    }
+
+    /// Provides the output sample rate.
+    pub fn get_output_rate(&self) -> u32 {
+        self.output_rate
+    }
+
+    /// Provides the number of input frames which can be pushed in advance.
+    pub fn get_input_capacity(&self) -> usize {
+        self.ring_size - self.tap_count
+    }
+
+    /// Provides the number of input samples used for one output sample.
+    pub fn get_tap_count(&self) -> usize {
+        self.tap_count
+    }
+}
+
+/// A `SoundGenerator` running at a different sample rate than it's output. The generator
+/// renders `input_buffer_size` samples at a time, as many times as the `Resampler` needs.
+///
+/// Commands are passed to the generator immediately, which is ahead of the output by up to
+/// one input buffer plus the half of the filter length. Requests of more than
+/// `output_buffer_size` samples are answered with silence.
+pub struct ResampledGenerator<G: SoundGenerator> {
+    generator: G,
+    resampler: Resampler,
+    input_buffer_size: usize,
+    input_buffers: Vec<Vec<SampleCalc>>,
+    /// Used by `get_samples()`.
+    output_buffers: Vec<Vec<SampleCalc>>,
+}
+
+impl<G: SoundGenerator> ResampledGenerator<G> {
+    /// custom constructor, `output_buffer_size` is the largest sample count requested by the
+    /// backend.
+    pub fn new(generator: G,
+               input_rate: u32,
+               input_buffer_size: usize,
+               output_rate: u32,
+               output_buffer_size: usize,
+               channel_count: usize,
+               quality: ResamplerQuality)
+               -> SoundResult<ResampledGenerator<G>> {
+        if (input_buffer_size == 0) || (output_buffer_size == 0) {
+            return Err(Error::BufferSize);
+        }
+        // enough for the input of the largest output buffer, and an extra input buffer
+        let step = input_rate as f64 / output_rate as f64;
+        let input_capacity = input_buffer_size + (output_buffer_size as f64 * step).ceil() as usize;
+        Ok(ResampledGenerator {
+            generator: generator,
+            resampler: Resampler::new_with_capacity(input_rate,
+                                                    output_rate,
+                                                    channel_count,
+                                                    quality,
+                                                    input_capacity)?,
+            input_buffer_size: input_buffer_size,
+            input_buffers: vec![vec![0.0; input_buffer_size]; channel_count],
+            output_buffers: vec![vec![0.0; output_buffer_size]; channel_count],
+        })
+    }
+
+    /// Provides the resampler.
+    pub fn get_resampler(&self) -> &Resampler {
+        &self.resampler
+    }
+
+    /// Provides the wrapped generator.
+    pub fn get_generator(&mut self) -> &mut G {
+        &mut self.generator
+    }
+
+    fn render(&mut self,
+              sample_count: usize,
+              result: &mut [Vec<SampleCalc>])
+              -> SoundResult<()> {
+        while self.resampler.get_required_input(sample_count) > 0 {
+            self.generator.get_channel_samples(self.input_buffer_size, &mut self.input_buffers);
+            self.resampler.push_input(&self.input_buffers, self.input_buffer_size)?;
+        }
+        self.resampler.get_output(sample_count, result)
+    }
+}
+
+impl<G: SoundGenerator> SoundGenerator for ResampledGenerator<G> {
+    type Command = G::Command;
+
+    fn get_samples(&mut self, sample_count: usize, result: &mut Vec<SampleCalc>) {
+        let mut output_buffers = ::std::mem::replace(&mut self.output_buffers, Vec::new());
+        if self.render(sample_count, &mut output_buffers).is_ok() {
+            for (item, sample) in result.iter_mut().zip(&output_buffers[0]).take(sample_count) {
+                *item = *sample;
+            }
+        }
+        self.output_buffers = output_buffers;
+    }
+
+    fn get_channel_samples(&mut self, sample_count: usize, result: &mut [Vec<SampleCalc>]) {
+        if self.render(sample_count, result).is_err() {
+            for channel in result.iter_mut() {
+                for item in channel.iter_mut() {
+                    *item = 0.0;
+                }
+            }
+        }
+    }
+
+    fn process_command(&mut self, command: Self::Command) {
+        self.generator.process_command(command);
+    }
+}
+
+/// Normalized sinc function: `sin(π x) / (π x)`.
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-12 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+/// Zeroth order modified Bessel function of the first kind (power series).
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let half_x = x * 0.5;
+    for k in 1..64 {
+        term *= half_x / k as f64;
+        let term_squared = term * term;
+        sum += term_squared;
+        if term_squared < sum * 1e-17 {
+            break;
+        }
+    }
+    sum
+}
+
+#[cfg(test)]
+mod tests {
+    use sound::*;
+
+    /// Sine wave source, with the sample rate of 192 kHz.
+    struct Sine {
+        frequency: f64,
+        position: usize,
+    }
+
+    impl SoundGenerator for Sine {
+        type Command = ();
+
+        fn get_samples(&mut self, sample_count: usize, result: &mut Vec<SampleCalc>) {
+            for item in result.iter_mut().take(sample_count) {
+                let phase = self.position as f64 * self.frequency / 192_000.0;
+                *item = (phase * ::std::f64::consts::PI * 2.0).sin() as SampleCalc;
+                self.position += 1;
+            }
+        }
+
+        fn process_command(&mut self, _command: ()) {}
+    }
+
+    /// Resamples the sine from 192 kHz to 48 kHz, and returns 0.1 s of the output after the
+    /// filter is filled.
+    fn resample_sine(frequency: f64, quality: ResamplerQuality) -> Vec<SampleCalc> {
+        let sine = Sine {
+            frequency: frequency,
+            position: 0,
+        };
+        let mut generator =
+            ResampledGenerator::new(sine, 192_000, 256, 48_000, 600, 1, quality).unwrap();
+        let mut result = vec![0.0; 600];
+        generator.get_samples(600, &mut result);
+        let mut output = Vec::with_capacity(4800);
+        for _ in 0..8 {
+            generator.get_samples(600, &mut result);
+            output.extend_from_slice(&result);
+        }
+        output
+    }
+
+    fn get_level(samples: &[SampleCalc]) -> f64 {
+        let power: f64 = samples.iter().map(|sample| (sample * sample) as f64).sum();
+        (power * 2.0 / samples.len() as f64).sqrt()
+    }
+
+    fn get_tap_count(input_rate: u32, output_rate: u32, quality: ResamplerQuality) -> usize {
+        Resampler::new(input_rate, output_rate, 1, quality).unwrap().get_tap_count()
+    }
+
+    #[test]
+    fn tap_counts_of_the_documentation() {
+        let qualities = [ResamplerQuality::Low, ResamplerQuality::Medium, ResamplerQuality::High];
+        let downsampling: Vec<usize> =
+            qualities.iter().map(|quality| get_tap_count(192_000, 48_000, *quality)).collect();
+        assert_eq!(downsampling, vec![76, 144, 274]);
+        let upsampling: Vec<usize> =
+            qualities.iter().map(|quality| get_tap_count(48_000, 192_000, *quality)).collect();
+        assert_eq!(upsampling, vec![20, 36, 70]);
+    }
+
+    #[test]
+    fn sines_of_the_documentation() {
+        // (quality, passband, passband error in dB, alias rejection in dB)
+        let qualities = [(ResamplerQuality::Low, 16_000.0, 0.05, 62.0),
+                         (ResamplerQuality::Medium, 18_000.0, 0.005, 96.0),
+                         (ResamplerQuality::High, 20_000.0, 0.005, 119.0)];
+        for &(quality, passband, passband_error, rejection) in &qualities {
+            for &frequency in &[1000.0, passband * 0.5, passband] {
+                let level_db = 20.0 * get_level(&resample_sine(frequency, quality)).log10();
+                assert!(level_db.abs() < passband_error);
+            }
+            // the aliases would be between 22 kHz and 12 kHz
+            for &frequency in &[26_000.0, 30_000.0, 36_000.0, 48_000.0, 60_000.0] {
+                let level_db = 20.0 * get_level(&resample_sine(frequency, quality)).log10();
+                assert!(level_db < -rejection);
+            }
+        }
+    }
+
+    #[test]
+    fn blocks_of_any_size() {
+        // the same output with different input and output buffer sizes (ring buffer wrapping)
+        let mut outputs = Vec::new();
+        for &(input_buffer_size, output_buffer_size) in &[(1000, 2000), (7, 13), (64, 1)] {
+            let sine = Sine {
+                frequency: 440.0,
+                position: 0,
+            };
+            let mut generator = ResampledGenerator::new(sine,
+                                                        192_000,
+                                                        input_buffer_size,
+                                                        44_100,
+                                                        output_buffer_size,
+                                                        1,
+                                                        ResamplerQuality::Medium)
+                .unwrap();
+            let mut output = Vec::new();
+            let mut result = vec![0.0; output_buffer_size];
+            while output.len() < 2000 {
+                generator.get_samples(output_buffer_size, &mut result);
+                output.extend_from_slice(&result);
+            }
+            output.truncate(2000);
+            outputs.push(output);
+        }
+        for output in &outputs[1..] {
+            for (item, first_item) in output.iter().zip(&outputs[0]) {
+                assert!((item - first_item).abs() < 1e-6);
+            }
+        }
+        // the capacity is checked
+        let quality = ResamplerQuality::Low;
+        let mut resampler = Resampler::new_with_capacity(48_000, 44_100, 1, quality, 10).unwrap();
+        assert_eq!(resampler.get_input_capacity(), 10);
+        let frame_count = resampler.get_tap_count() + 10;
+        match resampler.push_input(&[vec![0.0; frame_count]], frame_count) {
+            Err(Error::BufferSize) => {}
+            _ => panic!("BufferSize expected"),
+        }
+        resampler.push_input(&[vec![0.0; 10]], 10).unwrap();
+    }
+}
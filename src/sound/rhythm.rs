@@ -3,18 +3,6 @@ use sound::*;
 use std::fmt;
 use std::ops::{Add, Mul};
 
-/// See: [RFC #1394](https://github.com/rust-lang/rfcs/pull/1394),
-/// [RFC #1465](https://github.com/rust-lang/rfcs/pull/1465)
-// https://github.com/crumblingstatue/try_opt
-macro_rules! try_opt {
-    ($e:expr) =>(
-        match $e {
-            Some(v) => v,
-            None => return None,
-        }
-    )
-}
-
 /// The `TempoProvider` trait is used to provide tempo.
 pub trait TempoProvider {
     /// Returns the beat duration for each sample in the `result` buffer.
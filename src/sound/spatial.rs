@@ -0,0 +1,389 @@
+use sound::*;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+/// Average radius of the human head (in meters).
+pub const HEAD_RADIUS: SampleCalc = 0.0875;
+
+/// Speed of sound in air at 20 °C (in meters per second).
+pub const SPEED_OF_SOUND: SampleCalc = 343.0;
+
+/// Minimal amplitude ratio of the head shadow, for high frequencies.
+const SHADOW_ALPHA_MIN: SampleCalc = 0.1;
+
+/// The angle of incidence (measured from the ear axis) with the deepest head shadow (150°).
+const SHADOW_ANGLE_MIN: SampleCalc = PI2 * 150.0 / 360.0;
+
+/// State of the signal path of one ear.
+#[derive(Debug, Clone)]
+struct Ear {
+    /// Ring buffer of the delay line, it's size is a power of two.
+    delay_line: Vec<SampleCalc>,
+    write_index: usize,
+    /// Delay (in samples) at the end of the previous buffer.
+    delay: SampleCalc,
+    // Head shadow filter coefficients and state.
+    b0: SampleCalc,
+    b1: SampleCalc,
+    a1: SampleCalc,
+    x1: SampleCalc,
+    y1: SampleCalc,
+}
+
+/// Binaural spatialization of a mono sound structure, for headphones. It produces a stereo
+/// signal (left, right) using the spherical head model of
+/// [Brown and Duda](http://interface.cipic.ucdavis.edu/sound/tutorial/hrtf.html):
+///
+/// - [interaural time difference](https://en.wikipedia.org/wiki/Interaural_time_difference):
+/// each ear gets a sub-sample accurate delay (3rd order Lagrange interpolation), so high
+/// sample rates can reproduce differences of a few microseconds.
+/// - interaural level difference: each ear gets a frequency dependent head shadow filter,
+/// which damps the high frequencies on the far side of the head.
+///
+/// The elevation only changes the lateral angle, there are no pinna (spectral) cues.
+pub struct Binaural {
+    sample_rate: SampleCalc,
+    sound: Rc<SoundStructure>,
+    /// Horizontal angle in radians: 0 is front, positive values are on the right side.
+    azimuth: Cell<SampleCalc>,
+    /// Vertical angle in radians, in the [-π/2, π/2] range.
+    elevation: Cell<SampleCalc>,
+    /// 0: left ear, 1: right ear
+    ears: RefCell<Vec<Ear>>,
+    wave_buffer: RefCell<Vec<SampleCalc>>,
+}
+
+impl Binaural {
+    /// custom constructor
+    pub fn new(sample_rate: SampleCalc,
+               buffer_size: usize,
+               sound: Rc<SoundStructure>)
+               -> SoundResult<Binaural> {
+        let _ = get_sample_time(sample_rate)?;
+        // the longest delay, plus room for the interpolation
+        let delay_max = (HEAD_RADIUS / SPEED_OF_SOUND) * (1.0 + PI2 * 0.25) * sample_rate + 4.0;
+        let delay_line_size = (delay_max.ceil() as usize).next_power_of_two();
+        let ear = Ear {
+            delay_line: vec![0.0; delay_line_size],
+            write_index: 0,
+            delay: 0.0,
+            b0: 1.0,
+            b1: 0.0,
+            a1: 0.0,
+            x1: 0.0,
+            y1: 0.0,
+        };
+        let binaural = Binaural {
+            sample_rate: sample_rate,
+            sound: sound,
+            azimuth: Cell::new(0.0),
+            elevation: Cell::new(0.0),
+            ears: RefCell::new(vec![ear.clone(), ear]),
+            wave_buffer: RefCell::new(vec![0.0; buffer_size]),
+        };
+        for (ear_index, ear) in binaural.ears.borrow_mut().iter_mut().enumerate() {
+            ear.delay = binaural.get_delay(ear_index);
+        }
+        Ok(binaural)
+    }
+
+    /// Sets the direction of the sound source. `azimuth` is the horizontal angle in radians
+    /// (0 is front, positive values are on the right side), `elevation` is the vertical angle
+    /// in radians, in the [-π/2, π/2] range. The delays glide to their new values during the
+    /// next buffer.
+    pub fn set_direction(&self, azimuth: SampleCalc, elevation: SampleCalc) -> SoundResult<()> {
+        if !(-PI2 * 0.25 <= elevation && elevation <= PI2 * 0.25) {
+            return Err(Error::AngleInvalid);
+        }
+        if !azimuth.is_finite() {
+            return Err(Error::AngleInvalid);
+        }
+        self.azimuth.set(azimuth % PI2);
+        self.elevation.set(elevation);
+        Ok(())
+    }
+
+    /// Returns the (azimuth, elevation) pair.
+    pub fn get_direction(&self) -> (SampleCalc, SampleCalc) {
+        (self.azimuth.get(), self.elevation.get())
+    }
+
+    /// Returns the interaural time difference (in seconds) of the actual direction. It is
+    /// positive when the sound reaches the left ear later.
+    pub fn get_itd(&self) -> SampleCalc {
+        (self.get_delay(0) - self.get_delay(1)) / self.sample_rate
+    }
+
+    /// The angle between the direction of the sound source and the axis of the ear.
+    fn get_incidence_angle(&self, ear_index: usize) -> SampleCalc {
+        let lateral = self.azimuth.get().sin() * self.elevation.get().cos();
+        let side = if ear_index == 0 { -1.0 } else { 1.0 };
+        (side * lateral).max(-1.0).min(1.0).acos()
+    }
+
+    /// Delay (in samples) of the sound reaching the ear. A constant latency is added to keep it
+    /// positive, and to make the interpolation possible.
+    fn get_delay(&self, ear_index: usize) -> SampleCalc {
+        let angle = self.get_incidence_angle(ear_index);
+        let quarter = PI2 * 0.25;
+        let time = if angle < quarter {
+            1.0 - angle.cos()
+        } else {
+            1.0 + angle - quarter
+        } * (HEAD_RADIUS / SPEED_OF_SOUND);
+        (time * self.sample_rate) + 1.0
+    }
+
+    /// Calculates the coefficients of the head shadow filter, which is a one-pole, one-zero
+    /// filter: `H(s) = (1 + α s / 2ω0) / (1 + s / 2ω0)`, with `ω0 = c / a`.
+    fn set_shadow_filter(&self, ear: &mut Ear, ear_index: usize) {
+        let angle = self.get_incidence_angle(ear_index);
+        let alpha = (1.0 + SHADOW_ALPHA_MIN * 0.5) +
+                    (1.0 - SHADOW_ALPHA_MIN * 0.5) *
+                    (angle / SHADOW_ANGLE_MIN * PI2 * 0.5).cos();
+        let beta = 2.0 * SPEED_OF_SOUND / HEAD_RADIUS;
+        // bilinear transform
+        let k = 2.0 * self.sample_rate;
+        let a0 = beta + k;
+        ear.b0 = (beta + alpha * k) / a0;
+        ear.b1 = (beta - alpha * k) / a0;
+        ear.a1 = (beta - k) / a0;
+    }
+}
+
+impl HasTimer for Binaural {
+    fn set_timing(&self, timing: TimingOption) -> SoundResult<()> {
+        self.sound.set_timing(timing)
+    }
+
+    fn get_timing(&self) -> TimingOption {
+        self.sound.get_timing()
+    }
+
+    fn restart(&self) {
+        self.sound.restart();
+    }
+
+    fn apply_parent_timing(&self, parent_timing: TimingOption) -> SoundResult<()> {
+        self.sound.apply_parent_timing(parent_timing)
+    }
+}
+
+impl SoundStructureMultichannel for Binaural {
+    fn get_channels(&self,
+                    base_frequency: &[SampleCalc],
+                    result: &mut [Vec<SampleCalc>])
+                    -> SoundResult<()> {
+        if result.len() != 2 {
+            return Err(Error::ChannelInvalid);
+        }
+        let mut wave_buffer = self.wave_buffer.borrow_mut();
+        if base_frequency.len() != wave_buffer.len() {
+            return Err(Error::BufferSize);
+        }
+        for output in result.iter() {
+            if output.len() != wave_buffer.len() {
+                return Err(Error::BufferSize);
+            }
+        }
+        self.sound.get(base_frequency, &mut wave_buffer)?;
+        let sample_count = wave_buffer.len() as SampleCalc;
+        for (ear_index, (ear, output)) in self.ears
+            .borrow_mut()
+            .iter_mut()
+            .zip(result.iter_mut())
+            .enumerate() {
+            self.set_shadow_filter(ear, ear_index);
+            let delay_end = self.get_delay(ear_index);
+            let delay_change = (delay_end - ear.delay) / sample_count;
+            let mask = ear.delay_line.len() - 1;
+            for (item, sample) in output.iter_mut().zip(wave_buffer.iter()) {
+                ear.delay_line[ear.write_index] = *sample;
+                ear.delay += delay_change;
+                let delay_int = ear.delay.floor();
+                let fraction = ear.delay - delay_int;
+                // the sample delayed by `delay_int`
+                let read_index = ear.write_index + ear.delay_line.len() - delay_int as usize;
+                let x_m1 = ear.delay_line[(read_index + 1) & mask];
+                let x_0 = ear.delay_line[read_index & mask];
+                let x_1 = ear.delay_line[(read_index + mask) & mask];
+                let x_2 = ear.delay_line[(read_index + mask - 1) & mask];
+                let delayed = -fraction * (fraction - 1.0) * (fraction - 2.0) / 6.0 * x_m1 +
+                              (fraction + 1.0) * (fraction - 1.0) * (fraction - 2.0) * 0.5 * x_0 -
+                              (fraction + 1.0) * fraction * (fraction - 2.0) * 0.5 * x_1 +
+                              (fraction + 1.0) * fraction * (fraction - 1.0) / 6.0 * x_2;
+                *item = ear.b0 * delayed + ear.b1 * ear.x1 - ear.a1 * ear.y1;
+                ear.x1 = delayed;
+                ear.y1 = *item;
+                ear.write_index = (ear.write_index + 1) & mask;
+            }
+            ear.delay = delay_end;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sound::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    const SAMPLE_RATE: SampleCalc = 48000.0;
+    const BUFFER_SIZE: usize = 256;
+
+    /// A test signal given by a function of the sample index.
+    struct Signal {
+        function: fn(usize) -> SampleCalc,
+        position: Cell<usize>,
+    }
+
+    impl HasTimer for Signal {
+        fn set_timing(&self, _timing: TimingOption) -> SoundResult<()> {
+            Ok(())
+        }
+
+        fn get_timing(&self) -> TimingOption {
+            TimingOption::None
+        }
+
+        fn restart(&self) {
+            self.position.set(0);
+        }
+
+        fn apply_parent_timing(&self, _parent_timing: TimingOption) -> SoundResult<()> {
+            Ok(())
+        }
+    }
+
+    impl SoundStructure for Signal {
+        fn get(&self,
+               _base_frequency: &[SampleCalc],
+               result: &mut [SampleCalc])
+               -> SoundResult<()> {
+            for item in result.iter_mut() {
+                *item = (self.function)(self.position.get());
+                self.position.set(self.position.get() + 1);
+            }
+            Ok(())
+        }
+    }
+
+    fn new_binaural(function: fn(usize) -> SampleCalc) -> Binaural {
+        let signal = Signal {
+            function: function,
+            position: Cell::new(0),
+        };
+        Binaural::new(SAMPLE_RATE, BUFFER_SIZE, Rc::new(signal)).unwrap()
+    }
+
+    /// Renders `buffer_count` buffers, provides the last one of each channel.
+    fn render(binaural: &Binaural, buffer_count: usize) -> Vec<Vec<SampleCalc>> {
+        let mut result = vec![vec![0.0; BUFFER_SIZE]; 2];
+        for _ in 0..buffer_count {
+            binaural.get_channels(&[440.0; BUFFER_SIZE], &mut result).unwrap();
+        }
+        result
+    }
+
+    fn get_rms(samples: &[SampleCalc]) -> SampleCalc {
+        (samples.iter().map(|sample| sample * sample).sum::<SampleCalc>() /
+         samples.len() as SampleCalc)
+            .sqrt()
+    }
+
+    fn ramp(index: usize) -> SampleCalc {
+        index as SampleCalc * 1e-3
+    }
+
+    fn sine_8k(index: usize) -> SampleCalc {
+        (index as SampleCalc * PI2 * 8000.0 / SAMPLE_RATE).sin()
+    }
+
+    fn sine_100(index: usize) -> SampleCalc {
+        (index as SampleCalc * PI2 * 100.0 / SAMPLE_RATE).sin()
+    }
+
+    #[test]
+    fn interaural_time_difference() {
+        let binaural = new_binaural(ramp);
+        assert_eq!(binaural.get_itd(), 0.0);
+        // (1 + π/2) · a / c ≈ 0.66 ms
+        let itd_max = (1.0 + PI2 * 0.25) * HEAD_RADIUS / SPEED_OF_SOUND;
+        binaural.set_direction(PI2 * 0.25, 0.0).unwrap();
+        assert!((binaural.get_itd() - itd_max).abs() < 1e-6);
+        assert!((binaural.get_itd() - 0.00066).abs() < 0.00001);
+        binaural.set_direction(-PI2 * 0.25, 0.0).unwrap();
+        assert!((binaural.get_itd() + itd_max).abs() < 1e-6);
+        binaural.set_direction(PI2 * 0.125, 0.0).unwrap();
+        assert!(binaural.get_itd() > 0.0 && binaural.get_itd() < itd_max);
+        // straight above, there is no lateral angle
+        binaural.set_direction(PI2 * 0.25, PI2 * 0.25).unwrap();
+        assert!(binaural.get_itd().abs() < 1e-6);
+    }
+
+    #[test]
+    fn sub_sample_delay() {
+        // A ramp is delayed exactly by the Lagrange interpolation. The head shadow filter
+        // delays it further by it's group delay at DC.
+        let binaural = new_binaural(ramp);
+        binaural.set_direction(PI2 * 0.25, 0.0).unwrap();
+        let result = render(&binaural, 3);
+        // the far ear's delay is not an integer
+        let delay_far = binaural.get_delay(0);
+        assert!(delay_far.fract() > 0.1 && delay_far.fract() < 0.9);
+        let ears = binaural.ears.borrow();
+        for (ear_index, (ear, output)) in ears.iter().zip(&result).enumerate() {
+            let delay = binaural.get_delay(ear_index);
+            let group_delay = ear.b1 / (ear.b0 + ear.b1) - ear.a1 / (1.0 + ear.a1);
+            for (index, item) in output.iter().enumerate() {
+                let expected = ramp(2 * BUFFER_SIZE + index) - (delay + group_delay) * 1e-3;
+                assert!((item - expected).abs() < 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn far_ear_attenuation() {
+        let binaural = new_binaural(sine_8k);
+        let result = render(&binaural, 4);
+        let front = get_rms(&result[0]);
+        assert!((front - get_rms(&result[1])).abs() < 1e-3);
+        binaural.set_direction(PI2 * 0.25, 0.0).unwrap();
+        let result = render(&binaural, 4);
+        let input = 0.5f32.sqrt();
+        assert!(get_rms(&result[0]) < 0.4 * input);
+        assert!(get_rms(&result[1]) > 1.5 * input);
+        // low frequencies are not shadowed
+        let binaural = new_binaural(sine_100);
+        binaural.set_direction(PI2 * 0.25, 0.0).unwrap();
+        let mut result = vec![vec![0.0; BUFFER_SIZE]; 2];
+        let mut rms = [0.0; 2];
+        for _ in 0..10 {
+            binaural.get_channels(&[440.0; BUFFER_SIZE], &mut result).unwrap();
+            for (rms, output) in rms.iter_mut().zip(&result) {
+                *rms += get_rms(output) / 10.0;
+            }
+        }
+        assert!((rms[0] / rms[1] - 1.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn invalid_angles() {
+        let binaural = new_binaural(ramp);
+        for &(azimuth, elevation) in &[(0.0, PI2 * 0.3),
+                                       (0.0, -PI2 * 0.3),
+                                       (0.0, ::std::f32::NAN),
+                                       (::std::f32::NAN, 0.0),
+                                       (::std::f32::INFINITY, 0.0)] {
+            match binaural.set_direction(azimuth, elevation) {
+                Err(Error::AngleInvalid) => {}
+                _ => panic!("AngleInvalid expected"),
+            }
+        }
+        binaural.set_direction(PI2 * 1.25, 0.0).unwrap();
+        assert!((binaural.get_direction().0 - PI2 * 0.25).abs() < 1e-5);
+        let mut result = vec![vec![0.0; BUFFER_SIZE]; 3];
+        assert!(binaural.get_channels(&[440.0; BUFFER_SIZE], &mut result).is_err());
+    }
+}
@@ -205,39 +205,47 @@ impl HasTimer for Timer {
     }
 
     fn apply_parent_timing(&self, parent_timing: TimingOption) -> SoundResult<()> {
-        match self.timing.get() {
-            TimingOption::None |
-            TimingOption::TimeConst(..) |
-            TimingOption::TempoConst(..) => {}
-            TimingOption::TimeRatio { ratio, duration } => {
-                let parent_duration = match parent_timing {
-                    TimingOption::None |
-                    TimingOption::TempoConst(_) |
-                    TimingOption::TempoRatio { .. } => return Err(Error::TimingInvalid),
-                    TimingOption::TimeConst(duration) |
-                    TimingOption::TimeRatio { duration, .. } => duration,
-                };
-                self.timing.set(TimingOption::TimeRatio {
-                    ratio: ratio,
-                    duration: duration * parent_duration,
-                });
-            }
-            TimingOption::TempoRatio { ratio, duration } => {
-                let parent_duration = match parent_timing {
-                    TimingOption::None |
-                    TimingOption::TimeConst(_) |
-                    TimingOption::TimeRatio { .. } => return Err(Error::TimingInvalid),
-                    TimingOption::TempoConst(duration) |
-                    TimingOption::TempoRatio { duration, .. } => duration,
-                };
-                let new_duration = duration.checked_mul(&parent_duration)
-                    .ok_or(Error::Overflow)?;
-                self.timing.set(TimingOption::TempoRatio {
-                    ratio: ratio,
-                    duration: new_duration,
-                });
-            }
-        }
+        self.timing.set(get_applied_timing(self.timing.get(), parent_timing)?);
         Ok(())
     }
 }
+
+/// Provides the `timing` of a sequence item, with the relative durations calculated from the
+/// `parent_timing`.
+pub(crate) fn get_applied_timing(timing: TimingOption,
+                                 parent_timing: TimingOption)
+                                 -> SoundResult<TimingOption> {
+    match timing {
+        TimingOption::None |
+        TimingOption::TimeConst(..) |
+        TimingOption::TempoConst(..) => Ok(timing),
+        TimingOption::TimeRatio { ratio, duration } => {
+            let parent_duration = match parent_timing {
+                TimingOption::None |
+                TimingOption::TempoConst(_) |
+                TimingOption::TempoRatio { .. } => return Err(Error::TimingInvalid),
+                TimingOption::TimeConst(duration) |
+                TimingOption::TimeRatio { duration, .. } => duration,
+            };
+            Ok(TimingOption::TimeRatio {
+                ratio: ratio,
+                duration: duration * parent_duration,
+            })
+        }
+        TimingOption::TempoRatio { ratio, duration } => {
+            let parent_duration = match parent_timing {
+                TimingOption::None |
+                TimingOption::TimeConst(_) |
+                TimingOption::TimeRatio { .. } => return Err(Error::TimingInvalid),
+                TimingOption::TempoConst(duration) |
+                TimingOption::TempoRatio { duration, .. } => duration,
+            };
+            let new_duration = duration.checked_mul(&parent_duration)
+                .ok_or(Error::Overflow)?;
+            Ok(TimingOption::TempoRatio {
+                ratio: ratio,
+                duration: new_duration,
+            })
+        }
+    }
+}
@@ -1,31 +1,47 @@
 use sound::*;
+use std::cell::Cell;
 use std::cell::RefCell;
 use std::rc::Rc;
 // use rayon::prelude::*;
 
+/// The fade time of the overtones, which get in or out of the audible frequency range.
+const OVERTONE_FADE_TIME: SampleCalc = 0.005;
+
+/// The overtones are faded out above this part of the frequency limit, so the fading is done
+/// below the half of the sample rate.
+const OVERTONE_FADE_LIMIT: SampleCalc = 0.9;
+
 /// A sinusoidal wave generator, with variable frequency.
 #[derive(Debug, Copy, Clone)]
 pub struct Wave {
     sample_time: SampleCalc,
     /// The interval is used for transposition of the input frequencies
     interval: Interval,
-    overtone: SampleCalc,
+    /// The ratio of the wave's frequency to the base frequency.
+    ratio: SampleCalc,
     frequency_multiplier: SampleCalc,
     /// The phase value is always kept close to zero for maximizing the floating point precision.
     phase: SampleCalc,
 }
 
-// TODO: speed optimization
-// e.g. https://pizer.wordpress.com/2010/02/08/fast-digital-sine-oscillator/
 impl Wave {
-    /// custom constructor
+    /// custom constructor, for the harmonic partials. For the fundamental tone `overtone = 0`.
     pub fn new(sample_rate: SampleCalc, overtone: usize) -> SoundResult<Wave> {
+        Wave::new_with_ratio(sample_rate, overtone as SampleCalc + 1.0)
+    }
+
+    /// custom constructor, for partials of any frequency `ratio` (relative to the base
+    /// frequency).
+    pub fn new_with_ratio(sample_rate: SampleCalc, ratio: SampleCalc) -> SoundResult<Wave> {
         let sample_time = get_sample_time(sample_rate)?;
+        if !(ratio > 0.0) {
+            return Err(Error::FrequencyInvalid);
+        }
         Ok(Wave {
             sample_time: sample_time,
             interval: INTERVAL_UNISON,
-            overtone: overtone as SampleCalc,
-            frequency_multiplier: (overtone as SampleCalc + 1.0) * PI2 * sample_time,
+            ratio: ratio,
+            frequency_multiplier: ratio * PI2 * sample_time,
             phase: 0.0,
         })
     }
@@ -43,30 +59,108 @@ impl Wave {
         Ok(())
     }
 
+    /// Gets the next samples of the wave, with the `Oscillator::Rotation` method.
+    /// See also: [fast digital sine oscillator]
+    /// (https://pizer.wordpress.com/2010/02/08/fast-digital-sine-oscillator/)
+    pub fn get_rotation(&mut self,
+                        base_frequency: &[SampleCalc],
+                        result: &mut [SampleCalc])
+                        -> SoundResult<()> {
+        let mut increment_last = 0.0;
+        let (mut rotation_sin, mut rotation_cos) = (0.0, 1.0);
+        for (items, frequencies) in result.chunks_mut(ROTATION_SYNC_PERIOD)
+            .zip(base_frequency.chunks(ROTATION_SYNC_PERIOD)) {
+            let (mut sine, mut cosine) = self.phase.sin_cos();
+            for (item, frequency) in items.iter_mut().zip(frequencies) {
+                let increment = frequency * self.frequency_multiplier;
+                if increment != increment_last {
+                    let (sin, cos) = increment.sin_cos();
+                    rotation_sin = sin;
+                    rotation_cos = cos;
+                    increment_last = increment;
+                }
+                self.phase += increment;
+                let sine_next = sine * rotation_cos + cosine * rotation_sin;
+                cosine = cosine * rotation_cos - sine * rotation_sin;
+                sine = sine_next;
+                *item = sine;
+            }
+        }
+        self.phase %= PI2;
+        Ok(())
+    }
+
+    /// Gets the next samples of the wave, with the `Oscillator::Table` method.
+    pub fn get_table(&mut self,
+                     sine_table: &SineTable,
+                     base_frequency: &[SampleCalc],
+                     result: &mut [SampleCalc])
+                     -> SoundResult<()> {
+        // The table position is kept in one period, for better precision than the phase.
+        let mut position = sine_table.get_position(self.phase);
+        let scale = sine_table.get_position_scale();
+        for (item, frequency) in result.iter_mut().zip(base_frequency) {
+            let increment = frequency * self.frequency_multiplier;
+            self.phase += increment;
+            position = sine_table.advance_position(position, increment * scale);
+            *item = sine_table.get_at(position);
+        }
+        self.phase %= PI2;
+        Ok(())
+    }
+
     /// Sets a new frequency interval.
     pub fn set_interval(&mut self, interval: Interval) {
         self.interval = interval;
-        self.frequency_multiplier = (self.overtone + 1.0) * PI2 * self.sample_time *
-                                    interval.get_ratio();
+        self.frequency_multiplier = self.ratio * PI2 * self.sample_time * interval.get_ratio();
     }
 
     /// Sets a new phase value.
     pub fn set_phase(&mut self, phase: SampleCalc) {
         self.phase = phase % PI2;
     }
+
+    /// Provides the ratio of the wave's frequency to the base frequency (the partial's ratio,
+    /// transposed by the interval).
+    pub fn get_frequency_ratio(&self) -> SampleCalc {
+        self.ratio * self.interval.get_ratio()
+    }
 }
 
 /// A tone with optional overtones and amplitude modulation.
 /// Some examples: https://youtu.be/VRAXK4QKJ1Q?t=25s
+///
+/// The partials are harmonic overtones by default, inharmonic partials can be given by their
+/// frequency ratios (see `new_with_ratios()`).
+///
+/// The overtones are not calculated while their frequency is below `TONE_FREQUENCY_MIN`, or
+/// above `TONE_FREQUENCY_MAX` or the half of the sample rate (which would cause
+/// [aliasing](https://en.wikipedia.org/wiki/Aliasing)). The check is done for each buffer,
+/// overtones getting out of (or back into) the range are faded out (in) smoothly. At the high
+/// end the fading starts at 90% of the limit, and overtones above the limit are muted
+/// immediately (even if their fade out is not finished), so they never alias.
 #[derive(Clone)]
 pub struct Timbre {
     // sample_time: SampleCalc,
     /// The interval is used for transposition of the input frequencies
     interval: Interval,
     waves: RefCell<Vec<Wave>>,
+    /// The actual fade gains of the waves, 0.0 means the wave is not calculated.
+    wave_gains: RefCell<Vec<SampleCalc>>,
+    /// Gain change per sample while fading.
+    fade_step: SampleCalc,
+    /// The highest frequency, which is calculated.
+    frequency_limit: SampleCalc,
+    /// Overtones above this frequency are faded out.
+    fade_limit: SampleCalc,
+    /// It is `false` until the first buffer.
+    range_checked: Cell<bool>,
     amplitude_overtones: Rc<AmplitudeOvertonesProvider>,
     wave_buffer: RefCell<Vec<SampleCalc>>,
     overtone_max: usize,
+    oscillator: Oscillator,
+    /// Only used by `Oscillator::Table`.
+    sine_table: Option<Rc<SineTable>>,
 }
 
 impl Timbre {
@@ -80,15 +174,88 @@ impl Timbre {
         for overtone in 0..overtone_max {
             wave_vec.push(Wave::new(sample_rate, overtone)?);
         }
+        Timbre::new_with_waves(sample_rate, buffer_size, amplitude_overtones, wave_vec)
+    }
+
+    /// Custom constructor for inharmonic sounds (bells, bars, membranes, etc.). The partials
+    /// are given by their frequency `ratios` to the base frequency, in the order of the
+    /// partial indexes of the `amplitude_overtones`.
+    pub fn new_with_ratios(sample_rate: SampleCalc,
+                           buffer_size: usize,
+                           amplitude_overtones: Rc<AmplitudeOvertonesProvider>,
+                           ratios: &[SampleCalc])
+                           -> SoundResult<Timbre> {
+        let mut wave_vec = Vec::with_capacity(ratios.len());
+        for ratio in ratios {
+            wave_vec.push(Wave::new_with_ratio(sample_rate, *ratio)?);
+        }
+        Timbre::new_with_waves(sample_rate, buffer_size, amplitude_overtones, wave_vec)
+    }
+
+    /// Custom constructor, the partials are given by their `intervals` to the base frequency.
+    pub fn new_with_intervals(sample_rate: SampleCalc,
+                              buffer_size: usize,
+                              amplitude_overtones: Rc<AmplitudeOvertonesProvider>,
+                              intervals: &[Interval])
+                              -> SoundResult<Timbre> {
+        let ratios: Vec<SampleCalc> = intervals.iter()
+            .map(|interval| interval.get_ratio())
+            .collect();
+        Timbre::new_with_ratios(sample_rate, buffer_size, amplitude_overtones, &ratios)
+    }
+
+    /// Custom constructor for stiff strings (e.g. piano), see `get_stiff_string_ratios()`.
+    pub fn new_stiff_string(sample_rate: SampleCalc,
+                            buffer_size: usize,
+                            amplitude_overtones: Rc<AmplitudeOvertonesProvider>,
+                            partial_count: usize,
+                            inharmonicity: SampleCalc)
+                            -> SoundResult<Timbre> {
+        let ratios = get_stiff_string_ratios(partial_count, inharmonicity)?;
+        Timbre::new_with_ratios(sample_rate, buffer_size, amplitude_overtones, &ratios)
+    }
+
+    fn new_with_waves(sample_rate: SampleCalc,
+                      buffer_size: usize,
+                      amplitude_overtones: Rc<AmplitudeOvertonesProvider>,
+                      wave_vec: Vec<Wave>)
+                      -> SoundResult<Timbre> {
+        let sample_time = get_sample_time(sample_rate)?;
+        let overtone_max = wave_vec.len();
         Ok(Timbre {
             interval: INTERVAL_UNISON,
             waves: RefCell::new(wave_vec),
+            wave_gains: RefCell::new(vec![1.0; overtone_max]),
+            fade_step: sample_time / OVERTONE_FADE_TIME,
+            frequency_limit: TONE_FREQUENCY_MAX.min(sample_rate * 0.5),
+            fade_limit: TONE_FREQUENCY_MAX.min(sample_rate * 0.5) * OVERTONE_FADE_LIMIT,
+            range_checked: Cell::new(false),
             amplitude_overtones: amplitude_overtones,
             wave_buffer: RefCell::new(vec![0.0; buffer_size]),
             overtone_max: overtone_max,
+            oscillator: Oscillator::Exact,
+            sine_table: None,
         })
     }
 
+    /// Selects the sine oscillator core of the waves, the default is `Oscillator::Exact`.
+    pub fn set_oscillator(&mut self, oscillator: Oscillator) {
+        if (oscillator == Oscillator::Table) && self.sine_table.is_none() {
+            self.sine_table = Some(Rc::new(SineTable::new()));
+        }
+        self.oscillator = oscillator;
+    }
+
+    /// Provides the selected sine oscillator core.
+    pub fn get_oscillator(&self) -> Oscillator {
+        self.oscillator
+    }
+
+    /// Returns the number of waves calculated in the last buffer (including the fading ones).
+    pub fn get_active_count(&self) -> usize {
+        self.wave_gains.borrow().iter().filter(|gain| **gain > 0.0).count()
+    }
+
     /// Sets a new frequency interval.
     pub fn set_interval(&mut self, interval: Interval) {
         self.interval = interval;
@@ -127,7 +294,6 @@ impl HasTimer for Timbre {
 }
 
 impl SoundStructure for Timbre {
-    // TODO: filtering out frequencies from the calculations which are out of range
     fn get(&self, base_frequency: &[SampleCalc], result: &mut [SampleCalc]) -> SoundResult<()> {
         let mut wave_buffer = self.wave_buffer.borrow_mut();
         let buffer_size = wave_buffer.len();
@@ -140,18 +306,121 @@ impl SoundStructure for Timbre {
         for item in result.iter_mut() {
             *item = 0.0;
         }
-        for (overtone, wave) in self.waves.borrow_mut().iter_mut().enumerate() {
-            wave.get(base_frequency, &mut wave_buffer)?;
+        let mut frequency_min = base_frequency.first().cloned().unwrap_or(0.0);
+        let mut frequency_max = frequency_min;
+        for frequency in base_frequency {
+            if *frequency < frequency_min {
+                frequency_min = *frequency;
+            } else if *frequency > frequency_max {
+                frequency_max = *frequency;
+            }
+        }
+        let range_checked = self.range_checked.replace(true);
+        let mut wave_gains = self.wave_gains.borrow_mut();
+        for (overtone, (wave, gain)) in self.waves
+            .borrow_mut()
+            .iter_mut()
+            .zip(wave_gains.iter_mut())
+            .enumerate() {
+            let frequency_ratio = wave.get_frequency_ratio();
+            let in_range = (frequency_min * frequency_ratio >= TONE_FREQUENCY_MIN) &&
+                           (frequency_max * frequency_ratio <= self.fade_limit);
+            if !range_checked {
+                // no fading in the first buffer
+                *gain = if in_range { 1.0 } else { 0.0 };
+            }
+            if frequency_max * frequency_ratio > self.frequency_limit {
+                // it would alias, even while fading out
+                *gain = 0.0;
+            }
+            if !in_range && (*gain == 0.0) {
+                // The amplitude function is still applied, so it does not lag behind.
+                self.amplitude_overtones.apply(overtone, &mut wave_buffer)?;
+                continue;
+            }
+            match (self.oscillator, &self.sine_table) {
+                (Oscillator::Rotation, _) => wave.get_rotation(base_frequency, &mut wave_buffer)?,
+                (Oscillator::Table, &Some(ref sine_table)) => {
+                    wave.get_table(sine_table, base_frequency, &mut wave_buffer)?
+                }
+                _ => wave.get(base_frequency, &mut wave_buffer)?,
+            }
             self.amplitude_overtones.apply(overtone, &mut wave_buffer)?;
-            for (item, wave) in result.iter_mut()
-                .zip(wave_buffer.iter()) {
-                *item += *wave;
+            if in_range && (*gain == 1.0) {
+                for (item, wave) in result.iter_mut()
+                    .zip(wave_buffer.iter()) {
+                    *item += *wave;
+                }
+            } else {
+                let gain_step = if in_range {
+                    self.fade_step
+                } else {
+                    -self.fade_step
+                };
+                for (item, wave) in result.iter_mut()
+                    .zip(wave_buffer.iter()) {
+                    *gain = (*gain + gain_step).max(0.0).min(1.0);
+                    *item += *wave * *gain;
+                }
+                // the rounding errors of the steps would leave a tiny gain at the end of the fade
+                if *gain < self.fade_step * 0.5 {
+                    *gain = 0.0;
+                } else if *gain > 1.0 - self.fade_step * 0.5 {
+                    *gain = 1.0;
+                }
             }
         }
         Ok(())
     }
 }
 
+/// Provides the frequency ratios of the partials of a stiff string:
+/// `n * sqrt(1 + B * n²)`, where `n` is the harmonic number (starting from 1) and `B` is the
+/// `inharmonicity` coefficient. For piano strings `B` is about 0.0001 ... 0.01, higher for the
+/// short bass and treble strings.
+/// See also: [Inharmonicity](https://en.wikipedia.org/wiki/Inharmonicity)
+pub fn get_stiff_string_ratios(partial_count: usize,
+                               inharmonicity: SampleCalc)
+                               -> SoundResult<Vec<SampleCalc>> {
+    if !(inharmonicity >= 0.0) {
+        return Err(Error::InharmonicityInvalid);
+    }
+    Ok((1..(partial_count + 1))
+        .map(|harmonic| {
+            let n = harmonic as SampleCalc;
+            n * (1.0 + inharmonicity * n * n).sqrt()
+        })
+        .collect())
+}
+
+/// Pan laws, for placing a mono sound between two output channels.
+/// See also: [Panning law](https://en.wikipedia.org/wiki/Panning_law)
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PanLaw {
+    /// The gains are proportional to the position, the center is -6 dB on both sides.
+    Linear,
+    /// The sum of the powers is constant, the center is -3 dB on both sides.
+    ConstantPower,
+}
+
+impl PanLaw {
+    /// Provides the (left, right) gains for the `pan` position, which goes from -1.0 (left)
+    /// to 1.0 (right).
+    pub fn get_gains(&self, pan: SampleCalc) -> SoundResult<(SampleCalc, SampleCalc)> {
+        if !(-1.0 <= pan && pan <= 1.0) {
+            return Err(Error::PanInvalid);
+        }
+        let position = (pan + 1.0) * 0.5;
+        match *self {
+            PanLaw::Linear => Ok((1.0 - position, position)),
+            PanLaw::ConstantPower => {
+                // sin() is exact at the ends (cos(π/2) would be slightly negative)
+                Ok((((1.0 - position) * PI2 * 0.25).sin(), (position * PI2 * 0.25).sin()))
+            }
+        }
+    }
+}
+
 /// Channel structure used for mixing sound structures.
 #[derive(Clone)]
 struct MixerChannel {
@@ -161,11 +430,42 @@ struct MixerChannel {
     sound: Rc<SoundStructure>,
     volume_relative: SampleCalc,
     volume_normalized: SampleCalc,
+    /// Gains for the output channels of multichannel mixing. When it is empty, the sound goes
+    /// to every output channel with unity gain.
+    output_gain: Vec<SampleCalc>,
+    /// Optional glide between the interval ratios.
+    portamento: Option<Portamento>,
     frequency_buffer: Vec<SampleCalc>,
     wave_buffer: Vec<SampleCalc>,
 }
 
-/// Mixes sound channels (structures).
+impl MixerChannel {
+    /// Transposes the mixer's input frequencies to the channel's frequency buffer.
+    fn transpose(&mut self, base_frequency: &[SampleCalc]) -> SoundResult<()> {
+        match self.portamento {
+            Some(ref portamento) => {
+                if base_frequency.len() != self.frequency_buffer.len() {
+                    return Err(Error::BufferSize);
+                }
+                portamento.get(&mut self.frequency_buffer);
+                for (item, frequency) in self.frequency_buffer.iter_mut().zip(base_frequency) {
+                    *item *= *frequency;
+                    if *item < TONE_FREQUENCY_MIN {
+                        return Err(Error::FrequencyTooLow);
+                    };
+                    if *item > TONE_FREQUENCY_MAX {
+                        return Err(Error::FrequencyTooHigh);
+                    };
+                }
+                Ok(())
+            }
+            None => self.interval.transpose(base_frequency, &mut self.frequency_buffer),
+        }
+    }
+}
+
+/// Mixes sound channels (structures). Besides mono output, it can produce stereo or N-channel
+/// output, routing each channel by it's pan position or output gains.
 #[derive(Clone)]
 pub struct Mixer {
     timer: Timer,
@@ -198,6 +498,8 @@ impl Mixer {
             sound: sound,
             volume_relative: volume,
             volume_normalized: 0.0,
+            output_gain: Vec::new(),
+            portamento: None,
             frequency_buffer: vec![1.0; self.buffer_size],
             wave_buffer: vec![0.0; self.buffer_size],
         };
@@ -225,8 +527,12 @@ impl Mixer {
     }
 
     /// Sets a new interval for the channel, relative to the base frequency of the mixer.
+    /// With portamento the channel glides from it's actual interval to the new one.
     pub fn set_interval(&self, channel: usize, interval: Interval) -> SoundResult<()> {
         if let Some(ch) = self.channels.borrow_mut().get_mut(channel) {
+            if let Some(ref portamento) = ch.portamento {
+                portamento.set_target(interval.get_ratio())?;
+            }
             ch.interval = interval;
         } else {
             return Err(Error::ChannelInvalid);
@@ -234,6 +540,23 @@ impl Mixer {
         Ok(())
     }
 
+    /// Sets the portamento used by `set_interval()` for the channel, `None` switches it off
+    /// (the interval changes immediately).
+    pub fn set_portamento(&self,
+                          channel: usize,
+                          portamento: Option<Portamento>)
+                          -> SoundResult<()> {
+        if let Some(ch) = self.channels.borrow_mut().get_mut(channel) {
+            if let Some(ref portamento) = portamento {
+                portamento.jump(ch.interval.get_ratio())?;
+            }
+            ch.portamento = portamento;
+        } else {
+            return Err(Error::ChannelInvalid);
+        }
+        Ok(())
+    }
+
     /// Sets the relative volume of the channel.
     pub fn set_volume(&self, channel: usize, volume: SampleCalc) -> SoundResult<()> {
         if let Some(ch) = self.channels.borrow_mut().get_mut(channel) {
@@ -247,6 +570,30 @@ impl Mixer {
         }
         Ok(())
     }
+
+    /// Places the channel in the stereo field. `pan` goes from -1.0 (left) to 1.0 (right).
+    /// It applies to the first two output channels of multichannel mixing.
+    pub fn set_pan(&self, channel: usize, pan: SampleCalc, pan_law: PanLaw) -> SoundResult<()> {
+        let (gain_left, gain_right) = pan_law.get_gains(pan)?;
+        self.set_output_gain(channel, &[gain_left, gain_right])
+    }
+
+    /// Sets the gains of the channel for each output channel of multichannel mixing.
+    /// Output channels without a given gain get no sound from this channel. An empty `gain`
+    /// routes the sound to every output channel with unity gain (this is the default).
+    pub fn set_output_gain(&self, channel: usize, gain: &[SampleCalc]) -> SoundResult<()> {
+        for item in gain {
+            if *item < 0.0 {
+                return Err(Error::AmplitudeInvalid);
+            }
+        }
+        if let Some(ch) = self.channels.borrow_mut().get_mut(channel) {
+            ch.output_gain = gain.to_vec();
+        } else {
+            return Err(Error::ChannelInvalid);
+        }
+        Ok(())
+    }
 }
 
 impl HasTimer for Mixer {
@@ -288,7 +635,7 @@ impl SoundStructure for Mixer {
             *item = 0.0;
         }
         for channel in self.channels.borrow_mut().iter_mut() {
-            channel.interval.transpose(base_frequency, &mut channel.frequency_buffer)?;
+            channel.transpose(base_frequency)?;
             channel.sound.get(&channel.frequency_buffer, &mut channel.wave_buffer)?;
             for (item, wave) in result.iter_mut().zip(channel.wave_buffer.iter()) {
                 *item += *wave * channel.volume_normalized;
@@ -298,6 +645,41 @@ impl SoundStructure for Mixer {
     }
 }
 
+impl SoundStructureMultichannel for Mixer {
+    fn get_channels(&self,
+                    base_frequency: &[SampleCalc],
+                    result: &mut [Vec<SampleCalc>])
+                    -> SoundResult<()> {
+        for output in result.iter_mut() {
+            if base_frequency.len() != output.len() {
+                return Err(Error::BufferSize);
+            }
+            for item in output.iter_mut() {
+                *item = 0.0;
+            }
+        }
+        for channel in self.channels.borrow_mut().iter_mut() {
+            channel.transpose(base_frequency)?;
+            channel.sound.get(&channel.frequency_buffer, &mut channel.wave_buffer)?;
+            for (output_index, output) in result.iter_mut().enumerate() {
+                let gain = if channel.output_gain.is_empty() {
+                    1.0
+                } else {
+                    match channel.output_gain.get(output_index) {
+                        Some(gain) => *gain,
+                        None => continue,
+                    }
+                };
+                let volume = channel.volume_normalized * gain;
+                for (item, wave) in output.iter_mut().zip(channel.wave_buffer.iter()) {
+                    *item += *wave * volume;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
 // TODO: `FadeOutLinear` and `FadeInLinear` replaced by `FadeLinear`, ProgressOption shall be used
 // too here.
 // https://en.wikipedia.org/wiki/Fade_(audio_engineering)#Crossfading
@@ -388,3 +770,213 @@ impl Crossfader {
 // Ok(())
 // }
 // }
+
+#[cfg(test)]
+mod tests {
+    use sound::*;
+    use std::rc::Rc;
+
+    fn new_mixer(portamento: bool) -> Mixer {
+        let amplitude = AmplitudeConstOvertones::new(48000.0, 1, &[1.0]).unwrap();
+        let timbre = Timbre::new(48000.0, 4, Rc::new(amplitude), 1).unwrap();
+        let mixer = Mixer::new(48000.0, 4).unwrap();
+        let _ = mixer.add(Interval::new(2, 1).unwrap(), Rc::new(timbre), 1.0).unwrap();
+        if portamento {
+            let portamento = Portamento::new(48000.0,
+                                             TimingOption::TimeConst(0.05),
+                                             GlideCurve::Exponential)
+                .unwrap();
+            mixer.set_portamento(0, Some(portamento)).unwrap();
+        }
+        mixer
+    }
+
+    #[test]
+    fn transposed_frequency_range() {
+        for &portamento in &[false, true] {
+            let mixer = new_mixer(portamento);
+            let mut result = [0.0; 4];
+            assert!(mixer.get(&[440.0; 4], &mut result).is_ok());
+            match mixer.get(&[20000.0; 4], &mut result) {
+                Err(Error::FrequencyTooHigh) => {}
+                _ => panic!("FrequencyTooHigh expected"),
+            }
+            match mixer.get(&[1.0; 4], &mut result) {
+                Err(Error::FrequencyTooLow) => {}
+                _ => panic!("FrequencyTooLow expected"),
+            }
+        }
+    }
+
+    fn new_timbre(ratios: &[SampleCalc]) -> Timbre {
+        let amplitude = AmplitudeConstOvertones::new(48000.0,
+                                                     ratios.len() - 1,
+                                                     &vec![1.0; ratios.len()])
+            .unwrap();
+        Timbre::new_with_ratios(48000.0, 48, Rc::new(amplitude), ratios).unwrap()
+    }
+
+    /// Returns the peak of the next buffer.
+    fn get_peak(timbre: &Timbre, frequency: SampleCalc) -> SampleCalc {
+        let mut result = [0.0; 48];
+        timbre.get(&[frequency; 48], &mut result).unwrap();
+        result.iter().fold(0.0, |peak, item| item.abs().max(peak))
+    }
+
+    #[test]
+    fn overtone_culling() {
+        let harmonics: Vec<SampleCalc> = (1..11).map(|harmonic| harmonic as SampleCalc).collect();
+        // the fading starts at 21.6 kHz, the limit is 24 kHz
+        let timbre = new_timbre(&harmonics);
+        assert_eq!(timbre.get_active_count(), 10);
+        let _ = get_peak(&timbre, 3000.0);
+        assert_eq!(timbre.get_active_count(), 7);
+        // 2 Hz is below `TONE_FREQUENCY_MIN`
+        let timbre = new_timbre(&harmonics);
+        let _ = get_peak(&timbre, 2.0);
+        assert_eq!(timbre.get_active_count(), 9);
+    }
+
+    #[test]
+    fn overtone_fades() {
+        // the fade time is 240 samples, 5 buffers
+        let timbre = new_timbre(&[1.0]);
+        assert!(get_peak(&timbre, 1000.0) > 0.99);
+        for buffer_index in 0..5 {
+            assert_eq!(timbre.get_active_count(), 1);
+            let peak = get_peak(&timbre, 22000.0);
+            assert!(peak <= 1.0 - 0.2 * buffer_index as SampleCalc);
+            assert!(peak > 0.0);
+        }
+        assert_eq!(timbre.get_active_count(), 0);
+        assert_eq!(get_peak(&timbre, 22000.0), 0.0);
+        // fading in again
+        let peak = get_peak(&timbre, 1000.0);
+        assert!((peak > 0.0) && (peak <= 0.2 + 1e-6));
+        assert_eq!(timbre.get_active_count(), 1);
+    }
+
+    #[test]
+    fn overtones_above_nyquist_frequency_are_muted() {
+        let timbre = new_timbre(&[1.0]);
+        let _ = get_peak(&timbre, 1000.0);
+        assert!(get_peak(&timbre, 22000.0) > 0.0);
+        // the fade out is not finished, but it would alias
+        assert_eq!(get_peak(&timbre, 25000.0), 0.0);
+        assert_eq!(timbre.get_active_count(), 0);
+    }
+
+    #[test]
+    fn stiff_string_ratios() {
+        let inharmonicity = 0.0004;
+        let ratios = get_stiff_string_ratios(20, inharmonicity).unwrap();
+        assert_eq!(ratios.len(), 20);
+        for (index, ratio) in ratios.iter().enumerate() {
+            let n = (index + 1) as f64;
+            let expected = n * (1.0 + inharmonicity as f64 * n * n).sqrt();
+            assert!((*ratio as f64 / expected - 1.0).abs() < 1e-6);
+        }
+        assert!((ratios[19] - 20.0 * 1.16f32.sqrt()).abs() < 1e-4);
+        assert_eq!(get_stiff_string_ratios(3, 0.0).unwrap(), vec![1.0, 2.0, 3.0]);
+        // the partials of the timbre have the same frequencies
+        let amplitude = AmplitudeConstOvertones::new(48000.0, 2, &[1.0; 3]).unwrap();
+        let timbre = Timbre::new_stiff_string(48000.0, 48, Rc::new(amplitude), 3, inharmonicity)
+            .unwrap();
+        let mut result = [0.0; 48];
+        timbre.get(&[440.0; 48], &mut result).unwrap();
+        for (index, item) in result.iter().enumerate() {
+            let time = (index + 1) as f64 / 48000.0;
+            let expected: f64 = ratios.iter()
+                .take(3)
+                .map(|ratio| {
+                    (*ratio as f64 * 440.0 * time * ::std::f64::consts::PI * 2.0).sin() / 3.0
+                })
+                .sum();
+            assert!((*item as f64 - expected).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn invalid_inharmonicity() {
+        for &inharmonicity in &[-0.0001, -1.0, ::std::f32::NAN] {
+            match get_stiff_string_ratios(10, inharmonicity) {
+                Err(Error::InharmonicityInvalid) => {}
+                _ => panic!("InharmonicityInvalid expected"),
+            }
+            let amplitude = AmplitudeConstOvertones::new(48000.0, 9, &[1.0; 10]).unwrap();
+            match Timbre::new_stiff_string(48000.0, 48, Rc::new(amplitude), 10, inharmonicity) {
+                Err(Error::InharmonicityInvalid) => {}
+                _ => panic!("InharmonicityInvalid expected"),
+            }
+        }
+    }
+
+    #[test]
+    fn pan_laws() {
+        let half_power = 0.5f32.sqrt();
+        let half = (half_power, half_power);
+        let expected = [(PanLaw::Linear, [(1.0, 0.0), (0.5, 0.5), (0.0, 1.0)]),
+                        (PanLaw::ConstantPower, [(1.0, 0.0), half, (0.0, 1.0)])];
+        for &(pan_law, gains) in &expected {
+            for (pan, &(left, right)) in [-1.0, 0.0, 1.0].iter().zip(&gains) {
+                let (gain_left, gain_right) = pan_law.get_gains(*pan).unwrap();
+                assert!((gain_left - left).abs() < 1e-6);
+                assert!((gain_right - right).abs() < 1e-6);
+            }
+            for &pan in &[-1.01, 1.01, ::std::f32::NAN] {
+                match pan_law.get_gains(pan) {
+                    Err(Error::PanInvalid) => {}
+                    _ => panic!("PanInvalid expected"),
+                }
+            }
+        }
+        for &pan in &[-0.7, -0.2, 0.3, 0.9] {
+            let (left, right) = PanLaw::ConstantPower.get_gains(pan).unwrap();
+            assert!((left * left + right * right - 1.0).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn multichannel_routing() {
+        let new_timbre = || {
+            let amplitude = AmplitudeConstOvertones::new(48000.0, 0, &[1.0]).unwrap();
+            Timbre::new(48000.0, 48, Rc::new(amplitude), 1).unwrap()
+        };
+        let mixer = Mixer::new(48000.0, 48).unwrap();
+        let _ = mixer.add(INTERVAL_UNISON, Rc::new(new_timbre()), 0.5).unwrap();
+        let _ = mixer.add(Interval::new(3, 2).unwrap(), Rc::new(new_timbre()), 0.5).unwrap();
+        let (reference_first, reference_second) = (new_timbre(), new_timbre());
+        let mut first = vec![0.0; 48];
+        let mut second = vec![0.0; 48];
+        let mut result = vec![vec![0.0; 48]; 3];
+        // every output channel gets every channel by default
+        mixer.get_channels(&[440.0; 48], &mut result).unwrap();
+        reference_first.get(&[440.0; 48], &mut first).unwrap();
+        reference_second.get(&[660.0; 48], &mut second).unwrap();
+        for output in &result {
+            for ((item, first), second) in output.iter().zip(&first).zip(&second) {
+                assert!((item - 0.5 * (first + second)).abs() < 1e-6);
+            }
+        }
+        mixer.set_pan(0, -1.0, PanLaw::ConstantPower).unwrap();
+        mixer.set_output_gain(1, &[0.0, 0.5, 1.0]).unwrap();
+        mixer.get_channels(&[440.0; 48], &mut result).unwrap();
+        reference_first.get(&[440.0; 48], &mut first).unwrap();
+        reference_second.get(&[660.0; 48], &mut second).unwrap();
+        for index in 0..48 {
+            assert!((result[0][index] - 0.5 * first[index]).abs() < 1e-6);
+            assert!((result[1][index] - 0.25 * second[index]).abs() < 1e-6);
+            assert!((result[2][index] - 0.5 * second[index]).abs() < 1e-6);
+        }
+        mixer.set_pan(1, 1.0, PanLaw::ConstantPower).unwrap();
+        match mixer.set_pan(0, 1.5, PanLaw::Linear) {
+            Err(Error::PanInvalid) => {}
+            _ => panic!("PanInvalid expected"),
+        }
+        match mixer.set_output_gain(2, &[1.0]) {
+            Err(Error::ChannelInvalid) => {}
+            _ => panic!("ChannelInvalid expected"),
+        }
+        assert!(mixer.set_output_gain(0, &[-1.0]).is_err());
+    }
+}
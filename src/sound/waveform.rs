@@ -0,0 +1,462 @@
+use sound::*;
+use std::cell::Cell;
+use std::f64::consts::PI;
+use std::rc::Rc;
+
+/// The number of samples in one period of a `BandLimitedTable` level.
+const TABLE_SIZE: usize = 4096;
+
+/// The number of mip-map levels. Level `k` contains the harmonics `1 ... 2^k`.
+const LEVEL_COUNT: usize = 11;
+
+/// The number of harmonics of the highest level. It is the quarter of the table size, so the
+/// interpolation errors stay low.
+const HARMONIC_COUNT_MAX: usize = 1 << (LEVEL_COUNT - 1);
+
+/// Mip-mapped single-cycle tables of a periodic waveform, for alias-free playback. Each level
+/// contains twice as many harmonics as the previous one (1, 2, 4, ... 1024). For each sample
+/// the level with the most harmonics is selected, whose harmonics are all below the frequency
+/// limit (`TONE_FREQUENCY_MAX` or the half of the sample rate). So there is no aliasing at any
+/// sample rate, only the images of the linear interpolation remain (below -70 dB for the
+/// classic waveforms). The band limiting causes overshoots at the jumps of the waveforms
+/// ([Gibbs phenomenon](https://en.wikipedia.org/wiki/Gibbs_phenomenon)), up to about 1.18.
+/// Only the square wave's highest tones have larger peaks, up to 4/π ≈ 1.27 if only the
+/// fundamental remains.
+///
+/// The tables do not depend on the sample rate, so they can be shared between the sound
+/// structures.
+#[derive(Debug, Clone)]
+pub struct BandLimitedTable {
+    /// `LEVEL_COUNT` levels, each of them has `TABLE_SIZE + 1` samples (the last one is equal
+    /// to the first one).
+    values: Vec<SampleCalc>,
+}
+
+impl BandLimitedTable {
+    /// Sawtooth wave, rising from -1.0 to 1.0.
+    pub fn new_saw() -> BandLimitedTable {
+        BandLimitedTable::from_coefficients(&(1..(HARMONIC_COUNT_MAX + 1))
+            .map(|harmonic| {
+                let sign = if harmonic % 2 == 1 { 1.0 } else { -1.0 };
+                (sign * 2.0 / (PI * harmonic as f64), 0.0)
+            })
+            .collect::<Vec<(f64, f64)>>())
+    }
+
+    /// Square wave, between -1.0 and 1.0.
+    pub fn new_square() -> BandLimitedTable {
+        BandLimitedTable::from_coefficients(&(1..(HARMONIC_COUNT_MAX + 1))
+            .map(|harmonic| if harmonic % 2 == 1 {
+                (4.0 / (PI * harmonic as f64), 0.0)
+            } else {
+                (0.0, 0.0)
+            })
+            .collect::<Vec<(f64, f64)>>())
+    }
+
+    /// Triangle wave, between -1.0 and 1.0.
+    pub fn new_triangle() -> BandLimitedTable {
+        BandLimitedTable::from_coefficients(&(1..(HARMONIC_COUNT_MAX + 1))
+            .map(|harmonic| if harmonic % 2 == 1 {
+                let sign = if harmonic % 4 == 1 { 1.0 } else { -1.0 };
+                (sign * 8.0 / (PI * PI * (harmonic * harmonic) as f64), 0.0)
+            } else {
+                (0.0, 0.0)
+            })
+            .collect::<Vec<(f64, f64)>>())
+    }
+
+    /// Builds the tables from the amplitudes of the harmonics (sine waves with zero phase).
+    /// Index 0 is the fundamental tone. The harmonics above the 1024th are ignored.
+    pub fn from_spectrum(amplitudes: &[SampleCalc]) -> BandLimitedTable {
+        BandLimitedTable::from_coefficients(&amplitudes.iter()
+            .map(|amplitude| (*amplitude as f64, 0.0))
+            .collect::<Vec<(f64, f64)>>())
+    }
+
+    /// Builds the tables from the actual amplitudes of an overtone amplitude function (e.g.
+    /// `AmplitudeConstOvertones`).
+    pub fn from_overtones(amplitude_overtones: &AmplitudeOvertonesJoinable)
+                          -> SoundResult<BandLimitedTable> {
+        let mut amplitudes = vec![0.0; HARMONIC_COUNT_MAX];
+        amplitude_overtones.get_amplitudes(&mut amplitudes)?;
+        Ok(BandLimitedTable::from_spectrum(&amplitudes))
+    }
+
+    /// Builds the tables from one period of a waveform, given by at least 4 `samples`. The
+    /// harmonics are calculated by discrete Fourier transform, the DC offset is removed.
+    pub fn from_samples(samples: &[SampleCalc]) -> SoundResult<BandLimitedTable> {
+        let sample_count = samples.len();
+        if sample_count < 4 {
+            return Err(Error::BufferSize);
+        }
+        // the harmonic at the half of the sample count is ambiguous
+        let harmonic_count = ((sample_count - 1) / 2).min(HARMONIC_COUNT_MAX);
+        let mut coefficients = vec![(0.0, 0.0); harmonic_count];
+        for (index, sample) in samples.iter().enumerate() {
+            let (step_sin, step_cos) = (index as f64 * PI * 2.0 / sample_count as f64).sin_cos();
+            let (mut sine, mut cosine) = (0.0, 1.0);
+            for coefficient in &mut coefficients {
+                let sine_next = sine * step_cos + cosine * step_sin;
+                cosine = cosine * step_cos - sine * step_sin;
+                sine = sine_next;
+                coefficient.0 += *sample as f64 * sine;
+                coefficient.1 += *sample as f64 * cosine;
+            }
+        }
+        let normalization = 2.0 / sample_count as f64;
+        for coefficient in &mut coefficients {
+            coefficient.0 *= normalization;
+            coefficient.1 *= normalization;
+        }
+        Ok(BandLimitedTable::from_coefficients(&coefficients))
+    }
+
+    /// Builds the levels from the (sine, cosine) amplitudes of the harmonics. The harmonics
+    /// above `HARMONIC_COUNT_MAX` are ignored.
+    fn from_coefficients(coefficients: &[(f64, f64)]) -> BandLimitedTable {
+        let harmonic_count = coefficients.len().min(HARMONIC_COUNT_MAX);
+        let level_size = TABLE_SIZE + 1;
+        let mut values = vec![0.0; LEVEL_COUNT * level_size];
+        for index in 0..level_size {
+            let (step_sin, step_cos) = (index as f64 * PI * 2.0 / TABLE_SIZE as f64).sin_cos();
+            // sin(n x) and cos(n x) are calculated by rotation
+            let (mut sine, mut cosine) = (0.0, 1.0);
+            let mut sum = 0.0;
+            let mut level = 0;
+            for (harmonic, &(sine_amplitude, cosine_amplitude)) in coefficients.iter()
+                .take(harmonic_count)
+                .enumerate() {
+                let sine_next = sine * step_cos + cosine * step_sin;
+                cosine = cosine * step_cos - sine * step_sin;
+                sine = sine_next;
+                sum += sine_amplitude * sine + cosine_amplitude * cosine;
+                if harmonic + 1 == 1 << level {
+                    values[level * level_size + index] = sum as SampleCalc;
+                    level += 1;
+                }
+            }
+            // the levels above the given harmonics are the same as the last one
+            for upper_level in level..LEVEL_COUNT {
+                values[upper_level * level_size + index] = sum as SampleCalc;
+            }
+        }
+        BandLimitedTable { values: values }
+    }
+
+    /// Returns the level for the given frequency, or `None` if even the fundamental tone is
+    /// above the `frequency_limit`.
+    pub fn get_level(&self,
+                     frequency: SampleCalc,
+                     frequency_limit: SampleCalc)
+                     -> Option<usize> {
+        let harmonics_allowed = (frequency_limit / frequency.abs())
+            .min(HARMONIC_COUNT_MAX as SampleCalc) as u32;
+        if harmonics_allowed == 0 {
+            None
+        } else {
+            Some(31 - harmonics_allowed.leading_zeros() as usize)
+        }
+    }
+
+    /// Provides the number of samples in one period (the range of the table positions).
+    pub fn get_size(&self) -> usize {
+        TABLE_SIZE
+    }
+
+    /// Moves the `position` into the [0, `get_size()`) range. It is fast if the position is at
+    /// most one period outside of the range.
+    pub fn wrap_position(&self, position: SampleCalc) -> SampleCalc {
+        let size = TABLE_SIZE as SampleCalc;
+        let position = if position >= size {
+            position - size
+        } else if position < 0.0 {
+            position + size
+        } else {
+            position
+        };
+        if (position >= 0.0) && (position < size) {
+            return position;
+        }
+        // the frequency can be arbitrary high when the wave is silent, and tiny negative
+        // positions are rounded to `size`
+        let position = position % size;
+        if position < 0.0 {
+            (position + size) % size
+        } else {
+            position
+        }
+    }
+
+    /// Advances the table `position` by `increment`, and moves it into the [0, `get_size()`)
+    /// range.
+    pub fn advance_position(&self, position: SampleCalc, increment: SampleCalc) -> SampleCalc {
+        self.wrap_position(position + increment)
+    }
+
+    /// Returns the interpolated value of the `level` at the table `position`, which must be in
+    /// the [0, `get_size()`) range.
+    pub fn get_at(&self, level: usize, position: SampleCalc) -> SampleCalc {
+        let index = level * (TABLE_SIZE + 1) + position as usize;
+        let fraction = position - (position as usize) as SampleCalc;
+        let value = self.values[index];
+        value + (self.values[index + 1] - value) * fraction
+    }
+}
+
+/// Classic waveforms of subtractive synthesizers.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Waveform {
+    /// Sawtooth wave.
+    Saw,
+    /// Square wave.
+    Square,
+    /// Triangle wave.
+    Triangle,
+    /// Pulse wave with variable width, made from the difference of two sawtooth waves. It has
+    /// no DC offset, so the levels depend on the width.
+    Pulse,
+}
+
+/// A band-limited classic waveform (see `Waveform`), with variable frequency and an amplitude
+/// function. It uses the mip-mapped tables of `BandLimitedTable`, so it does not alias.
+pub struct BandLimitedWave {
+    sample_time: SampleCalc,
+    frequency_limit: SampleCalc,
+    waveform: Waveform,
+    table: Rc<BandLimitedTable>,
+    amplitude: Rc<AmplitudeJoinable>,
+    /// The position in the table, in the [0, `TABLE_SIZE`) range.
+    position: Cell<SampleCalc>,
+    /// The ratio of the high part of the pulse wave's period.
+    pulse_width: Cell<SampleCalc>,
+}
+
+impl BandLimitedWave {
+    /// custom constructor
+    pub fn new(sample_rate: SampleCalc,
+               waveform: Waveform,
+               amplitude: Rc<AmplitudeJoinable>)
+               -> SoundResult<BandLimitedWave> {
+        let table = match waveform {
+            Waveform::Saw | Waveform::Pulse => BandLimitedTable::new_saw(),
+            Waveform::Square => BandLimitedTable::new_square(),
+            Waveform::Triangle => BandLimitedTable::new_triangle(),
+        };
+        BandLimitedWave::new_with_table(sample_rate, waveform, Rc::new(table), amplitude)
+    }
+
+    /// custom constructor, with a shared table. It must be the saw table for
+    /// `Waveform::Pulse`.
+    pub fn new_with_table(sample_rate: SampleCalc,
+                          waveform: Waveform,
+                          table: Rc<BandLimitedTable>,
+                          amplitude: Rc<AmplitudeJoinable>)
+                          -> SoundResult<BandLimitedWave> {
+        let sample_time = get_sample_time(sample_rate)?;
+        Ok(BandLimitedWave {
+            sample_time: sample_time,
+            frequency_limit: TONE_FREQUENCY_MAX.min(sample_rate * 0.5),
+            waveform: waveform,
+            table: table,
+            amplitude: amplitude,
+            position: Cell::new(0.0),
+            pulse_width: Cell::new(0.5),
+        })
+    }
+
+    /// Sets the width of the pulse wave (the ratio of the high part of the period), it must be
+    /// in the (0.0, 1.0) range. The default is 0.5 (square wave).
+    pub fn set_pulse_width(&self, pulse_width: SampleCalc) -> SoundResult<()> {
+        if !(pulse_width > 0.0 && pulse_width < 1.0) {
+            return Err(Error::PulseWidthInvalid);
+        }
+        self.pulse_width.set(pulse_width);
+        Ok(())
+    }
+
+    /// Provides the width of the pulse wave.
+    pub fn get_pulse_width(&self) -> SampleCalc {
+        self.pulse_width.get()
+    }
+
+    /// Provides the waveform.
+    pub fn get_waveform(&self) -> Waveform {
+        self.waveform
+    }
+}
+
+impl HasTimer for BandLimitedWave {
+    fn set_timing(&self, timing: TimingOption) -> SoundResult<()> {
+        self.amplitude.set_timing(timing)
+    }
+
+    fn get_timing(&self) -> TimingOption {
+        self.amplitude.get_timing()
+    }
+
+    fn restart(&self) {
+        self.amplitude.restart();
+    }
+
+    fn apply_parent_timing(&self, parent_timing: TimingOption) -> SoundResult<()> {
+        self.amplitude.apply_parent_timing(parent_timing)
+    }
+}
+
+impl SoundStructure for BandLimitedWave {
+    fn get(&self, base_frequency: &[SampleCalc], result: &mut [SampleCalc]) -> SoundResult<()> {
+        if base_frequency.len() != result.len() {
+            return Err(Error::BufferSize);
+        }
+        let table = &*self.table;
+        let scale = self.sample_time * table.get_size() as SampleCalc;
+        let pulse_offset = self.pulse_width.get() * table.get_size() as SampleCalc;
+        let mut position = self.position.get();
+        let mut frequency_last = 0.0;
+        let mut level = None;
+        for (item, frequency) in result.iter_mut().zip(base_frequency) {
+            if *frequency != frequency_last {
+                level = table.get_level(*frequency, self.frequency_limit);
+                frequency_last = *frequency;
+            }
+            position = table.advance_position(position, frequency * scale);
+            *item = match level {
+                None => 0.0,
+                Some(level) => {
+                    if self.waveform == Waveform::Pulse {
+                        table.get_at(level, position) -
+                        table.get_at(level, table.wrap_position(position + pulse_offset))
+                    } else {
+                        table.get_at(level, position)
+                    }
+                }
+            };
+        }
+        self.position.set(position);
+        self.amplitude.apply(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sound::*;
+    use std::rc::Rc;
+
+    fn render(waveform: Waveform,
+              frequency: SampleCalc,
+              sample_rate: SampleCalc,
+              sample_count: usize)
+              -> Vec<SampleCalc> {
+        let amplitude = AmplitudeConst::new(sample_rate).unwrap();
+        let wave = BandLimitedWave::new(sample_rate, waveform, Rc::new(amplitude)).unwrap();
+        let mut result = vec![0.0; sample_count];
+        wave.get(&vec![frequency; sample_count], &mut result).unwrap();
+        result
+    }
+
+    /// The magnitudes of the discrete Fourier transform, up to the half of the sample count.
+    fn get_spectrum(samples: &[SampleCalc]) -> Vec<f64> {
+        let sample_count = samples.len();
+        (0..(sample_count / 2))
+            .map(|bin| {
+                let (mut real, mut imaginary) = (0.0, 0.0);
+                for (index, sample) in samples.iter().enumerate() {
+                    let angle = ((bin * index) % sample_count) as f64 * 2.0 *
+                                ::std::f64::consts::PI /
+                                sample_count as f64;
+                    real += *sample as f64 * angle.cos();
+                    imaginary += *sample as f64 * angle.sin();
+                }
+                (real * real + imaginary * imaginary).sqrt()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn level_selection() {
+        let table = BandLimitedTable::new_saw();
+        for &sample_rate in &[8000.0, 44100.0, 192000.0] {
+            let limit = TONE_FREQUENCY_MAX.min(sample_rate * 0.5);
+            for &frequency in &[20.0, 27.5, 110.0, 440.0, 1000.0, 3010.0, 7000.0] {
+                let level = match table.get_level(frequency, limit) {
+                    Some(level) => level,
+                    None => {
+                        assert!(frequency > limit);
+                        continue;
+                    }
+                };
+                // all harmonics of the level are below the limit, the next level has some above
+                assert!((1 << level) as SampleCalc * frequency <= limit);
+                assert!(level == 10 || (2 << level) as SampleCalc * frequency > limit);
+            }
+        }
+        assert_eq!(table.get_level(20.0, 24000.0), Some(10));
+        assert_eq!(table.get_level(13000.0, 24000.0), Some(0));
+        assert_eq!(table.get_level(30000.0, 24000.0), None);
+    }
+
+    #[test]
+    fn no_aliasing() {
+        // 4800 samples are whole periods of both tones, the harmonics fall exactly on the bins
+        // of 10 Hz, so every other bin contains only aliases and interpolation images
+        for &frequency in &[110.0, 3010.0] {
+            for &waveform in &[Waveform::Saw, Waveform::Square, Waveform::Triangle] {
+                let spectrum = get_spectrum(&render(waveform, frequency, 48000.0, 4800));
+                let fundamental_bin = (frequency / 10.0) as usize;
+                let image_max = spectrum.iter()
+                    .enumerate()
+                    .filter(|&(bin, _)| bin % fundamental_bin != 0)
+                    .map(|(_, magnitude)| *magnitude)
+                    .fold(0.0, f64::max);
+                // -70 dB
+                assert!(image_max < spectrum[fundamental_bin] * 3.16e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn peak_amplitudes() {
+        let wave = render(Waveform::Square, 110.0, 48000.0, 4800);
+        let peak = wave.iter().fold(0.0, |peak: SampleCalc, item| peak.max(item.abs()));
+        assert!(peak > 1.1 && peak < 1.18);
+        let wave = render(Waveform::Saw, 110.0, 48000.0, 4800);
+        let peak = wave.iter().fold(0.0, |peak: SampleCalc, item| peak.max(item.abs()));
+        assert!(peak > 1.0 && peak < 1.18);
+        let wave = render(Waveform::Triangle, 110.0, 48000.0, 4800);
+        let peak = wave.iter().fold(0.0, |peak: SampleCalc, item| peak.max(item.abs()));
+        assert!(peak > 0.99 && peak < 1.001);
+        // only the fundamental of the square wave remains
+        let wave = render(Waveform::Square, 7000.0, 48000.0, 4800);
+        let peak = wave.iter().fold(0.0, |peak: SampleCalc, item| peak.max(item.abs()));
+        assert!(peak > 1.18 && peak < 1.274);
+    }
+
+    #[test]
+    fn pulse_width() {
+        let amplitude = AmplitudeConst::new(48000.0).unwrap();
+        let wave = BandLimitedWave::new(48000.0, Waveform::Pulse, Rc::new(amplitude)).unwrap();
+        for &pulse_width in &[0.0, 1.0, -0.5, ::std::f32::NAN] {
+            match wave.set_pulse_width(pulse_width) {
+                Err(Error::PulseWidthInvalid) => {}
+                _ => panic!("PulseWidthInvalid expected"),
+            }
+        }
+        assert_eq!(wave.get_pulse_width(), 0.5);
+        wave.set_pulse_width(0.25).unwrap();
+        assert_eq!(wave.get_pulse_width(), 0.25);
+        let mut result = vec![0.0; 4800];
+        wave.get(&vec![110.0; 4800], &mut result).unwrap();
+        // no DC offset
+        let mean = result.iter().sum::<SampleCalc>() / result.len() as SampleCalc;
+        assert!(mean.abs() < 1e-3);
+    }
+
+    #[test]
+    fn tiny_negative_position() {
+        let table = BandLimitedTable::new_saw();
+        let position = table.wrap_position(-1e-8);
+        assert!((position >= 0.0) && (position < table.get_size() as SampleCalc));
+        let _ = table.get_at(10, position);
+    }
+}
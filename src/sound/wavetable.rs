@@ -0,0 +1,261 @@
+use sound::*;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+/// Wavetable oscillator: plays single-cycle waveforms (see `BandLimitedTable`), with variable
+/// frequency and an amplitude function. The table position selects the waveform: integer
+/// positions play the tables, fractional positions mix the two neighbouring tables. The
+/// position can be morphed over time (or tempo) with a `ProgressOption`.
+///
+/// It is much cheaper than a `Timbre` with many overtones, because the calculation does not
+/// depend on the number of harmonics.
+pub struct Wavetable {
+    sample_time: SampleCalc,
+    frequency_limit: SampleCalc,
+    tables: Vec<Rc<BandLimitedTable>>,
+    amplitude: Rc<AmplitudeJoinable>,
+    /// It is used for tempo based morphing.
+    tempo: Cell<Tempo>,
+    /// Progress of the table position, from `position_start` to `position_end`.
+    morph: RefCell<Option<ProgressOption>>,
+    position_start: Cell<SampleCalc>,
+    position_end: Cell<SampleCalc>,
+    /// The actual table position.
+    table_position: Cell<SampleCalc>,
+    /// The position in the tables (inside one period).
+    phase_position: Cell<SampleCalc>,
+}
+
+impl Wavetable {
+    /// custom constructor, the table position is 0.0 (the first table).
+    pub fn new(sample_rate: SampleCalc,
+               tables: Vec<Rc<BandLimitedTable>>,
+               amplitude: Rc<AmplitudeJoinable>)
+               -> SoundResult<Wavetable> {
+        let sample_time = get_sample_time(sample_rate)?;
+        if tables.is_empty() {
+            return Err(Error::SequenceEmpty);
+        }
+        Ok(Wavetable {
+            sample_time: sample_time,
+            frequency_limit: TONE_FREQUENCY_MAX.min(sample_rate * 0.5),
+            tables: tables,
+            amplitude: amplitude,
+            tempo: Cell::new(Tempo::default()),
+            morph: RefCell::new(None),
+            position_start: Cell::new(0.0),
+            position_end: Cell::new(0.0),
+            table_position: Cell::new(0.0),
+            phase_position: Cell::new(0.0),
+        })
+    }
+
+    /// Sets a fixed table position, in the [0.0, table count - 1] range. It stops the
+    /// morphing.
+    pub fn set_position(&self, position: SampleCalc) -> SoundResult<()> {
+        self.check_position(position)?;
+        *self.morph.borrow_mut() = None;
+        self.table_position.set(position);
+        Ok(())
+    }
+
+    /// Morphs the table position from `position_start` to `position_end` during the duration
+    /// of the `progress`. After that the position remains `position_end`.
+    pub fn set_morph(&self,
+                     progress: ProgressOption,
+                     position_start: SampleCalc,
+                     position_end: SampleCalc)
+                     -> SoundResult<()> {
+        self.check_position(position_start)?;
+        self.check_position(position_end)?;
+        progress.set_period_unit(position_end - position_start);
+        progress.set_phase_init(position_start);
+        *self.morph.borrow_mut() = Some(progress);
+        self.position_start.set(position_start);
+        self.position_end.set(position_end);
+        self.table_position.set(position_start);
+        Ok(())
+    }
+
+    /// Sets the tempo, which is used by the tempo based morphing.
+    pub fn set_tempo(&self, tempo: Tempo) {
+        self.tempo.set(tempo);
+    }
+
+    /// Provides the actual table position.
+    pub fn get_position(&self) -> SampleCalc {
+        self.table_position.get()
+    }
+
+    /// Provides the number of tables.
+    pub fn get_table_count(&self) -> usize {
+        self.tables.len()
+    }
+
+    fn check_position(&self, position: SampleCalc) -> SoundResult<()> {
+        if !(position >= 0.0 && position <= (self.tables.len() - 1) as SampleCalc) {
+            return Err(Error::PositionInvalid);
+        }
+        Ok(())
+    }
+
+    /// Provides the next table position of the morphing.
+    fn next_position(&self,
+                     progress: &ProgressOption,
+                     beats_per_second: SampleCalc)
+                     -> SoundResult<SampleCalc> {
+        let next = match *progress {
+            ProgressOption::Time(ref p) => p.next_by_time(),
+            ProgressOption::Tempo(ref p) => p.next_by_tempo(beats_per_second),
+        };
+        match next {
+            Ok(position) => Ok(position),
+            Err(Error::ProgressCompleted) => Ok(self.position_end.get()),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl HasTimer for Wavetable {
+    fn set_timing(&self, timing: TimingOption) -> SoundResult<()> {
+        self.amplitude.set_timing(timing)
+    }
+
+    fn get_timing(&self) -> TimingOption {
+        self.amplitude.get_timing()
+    }
+
+    fn restart(&self) {
+        self.amplitude.restart();
+        if let Some(ref progress) = *self.morph.borrow() {
+            progress.restart();
+            self.table_position.set(self.position_start.get());
+        }
+    }
+
+    fn apply_parent_timing(&self, parent_timing: TimingOption) -> SoundResult<()> {
+        self.amplitude.apply_parent_timing(parent_timing)
+    }
+}
+
+impl SoundStructure for Wavetable {
+    fn get(&self, base_frequency: &[SampleCalc], result: &mut [SampleCalc]) -> SoundResult<()> {
+        if base_frequency.len() != result.len() {
+            return Err(Error::BufferSize);
+        }
+        let first_table = &*self.tables[0];
+        let scale = self.sample_time * first_table.get_size() as SampleCalc;
+        let position_max = (self.tables.len() - 1) as SampleCalc;
+        let beats_per_second = self.tempo.get().get_bpm() / 60.0;
+        let mut table_position = self.table_position.get();
+        let mut phase_position = self.phase_position.get();
+        let mut frequency_last = 0.0;
+        let mut level = None;
+        let morph = self.morph.borrow();
+        for (item, frequency) in result.iter_mut().zip(base_frequency) {
+            if let Some(ref progress) = *morph {
+                table_position = self.next_position(progress, beats_per_second)?
+                    .max(0.0)
+                    .min(position_max);
+            }
+            if *frequency != frequency_last {
+                level = first_table.get_level(*frequency, self.frequency_limit);
+                frequency_last = *frequency;
+            }
+            phase_position = first_table.advance_position(phase_position, frequency * scale);
+            *item = match level {
+                None => 0.0,
+                Some(level) => {
+                    let index = table_position as usize;
+                    let fraction = table_position - index as SampleCalc;
+                    let value = self.tables[index].get_at(level, phase_position);
+                    if fraction > 0.0 {
+                        value +
+                        (self.tables[index + 1].get_at(level, phase_position) - value) * fraction
+                    } else {
+                        value
+                    }
+                }
+            };
+        }
+        self.table_position.set(table_position);
+        self.phase_position.set(phase_position);
+        self.amplitude.apply(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sound::*;
+    use std::rc::Rc;
+
+    /// Two sine tables, with the amplitudes 1.0 and 0.5.
+    fn new_wavetable() -> Wavetable {
+        let tables = vec![Rc::new(BandLimitedTable::from_spectrum(&[1.0])),
+                          Rc::new(BandLimitedTable::from_spectrum(&[0.5]))];
+        let amplitude = AmplitudeConst::new(48000.0).unwrap();
+        Wavetable::new(48000.0, tables, Rc::new(amplitude)).unwrap()
+    }
+
+    fn render(wavetable: &Wavetable, sample_count: usize) -> Vec<SampleCalc> {
+        let mut result = vec![0.0; sample_count];
+        wavetable.get(&vec![480.0; sample_count], &mut result).unwrap();
+        result
+    }
+
+    #[test]
+    fn position_interpolation() {
+        let wavetable = new_wavetable();
+        assert_eq!(wavetable.get_table_count(), 2);
+        let first = render(&wavetable, 100);
+        for &(position, gain) in &[(1.0, 0.5), (0.5, 0.75), (0.25, 0.875)] {
+            let wavetable = new_wavetable();
+            wavetable.set_position(position).unwrap();
+            assert_eq!(wavetable.get_position(), position);
+            for (item, first_item) in render(&wavetable, 100).iter().zip(&first) {
+                assert!((item - first_item * gain).abs() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn morph() {
+        let wavetable = new_wavetable();
+        let first = render(&new_wavetable(), 200);
+        // from the second table to the first one in 100 samples
+        let progress = ProgressTime::new(48000.0, 100.0 / 48000.0).unwrap();
+        wavetable.set_morph(ProgressOption::from(progress), 1.0, 0.0).unwrap();
+        assert_eq!(wavetable.get_position(), 1.0);
+        let result = render(&wavetable, 200);
+        assert_eq!(wavetable.get_position(), 0.0);
+        for (index, (item, first_item)) in result.iter().zip(&first).enumerate() {
+            let position = (1.0 - (index + 1) as SampleCalc / 100.0).max(0.0);
+            assert!((item - first_item * (1.0 - 0.5 * position)).abs() < 1e-4);
+        }
+        // restart goes back to the start of the morph, a fixed position stops it
+        wavetable.restart();
+        assert_eq!(wavetable.get_position(), 1.0);
+        wavetable.set_position(1.0).unwrap();
+        let _ = render(&wavetable, 200);
+        assert_eq!(wavetable.get_position(), 1.0);
+    }
+
+    #[test]
+    fn invalid_positions() {
+        let wavetable = new_wavetable();
+        for &position in &[-0.1, 1.1, ::std::f32::NAN] {
+            match wavetable.set_position(position) {
+                Err(Error::PositionInvalid) => {}
+                _ => panic!("PositionInvalid expected"),
+            }
+            let progress = ProgressTime::new(48000.0, 1.0).unwrap();
+            match wavetable.set_morph(ProgressOption::from(progress), 0.0, position) {
+                Err(Error::PositionInvalid) => {}
+                _ => panic!("PositionInvalid expected"),
+            }
+        }
+        assert_eq!(wavetable.get_position(), 0.0);
+        let amplitude = AmplitudeConst::new(48000.0).unwrap();
+        assert!(Wavetable::new(48000.0, Vec::new(), Rc::new(amplitude)).is_err());
+    }
+}
@@ -0,0 +1,194 @@
+use num::*;
+use sound::*;
+use std::fmt;
+use std::ops::{Div, Mul};
+
+/// Harmonic musical interval with `u64` terms, for the intervals which can not be represented
+/// by `Interval` (e.g. commas like 531441:524288, and long chains of intervals). It is a
+/// separate type behind the `interval-wide` feature, so enabling the feature does not change
+/// `Interval` (the feature is additive).
+///
+/// The `*` and `/` operators panic on overflow of the terms, use `checked_mul()` and
+/// `checked_div()` to handle it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct WideInterval {
+    numerator: u64,
+    denominator: u64,
+}
+
+impl Default for WideInterval {
+    fn default() -> WideInterval {
+        WideInterval {
+            numerator: 1,
+            denominator: 1,
+        }
+    }
+}
+
+impl WideInterval {
+    /// custom constructor, the terms are reduced to lowest terms.
+    pub fn new(numerator: u64, denominator: u64) -> SoundResult<WideInterval> {
+        if numerator == 0 {
+            return Err(Error::NumeratorInvalid);
+        };
+        if denominator == 0 {
+            return Err(Error::DenominatorInvalid);
+        };
+        let d = numerator.gcd(&denominator);
+        Ok(WideInterval {
+            numerator: numerator / d,
+            denominator: denominator / d,
+        })
+    }
+
+    /// Stacks the intervals (multiplies their ratios). Returns `Error::Overflow` if the result
+    /// can not be represented by `u64` terms.
+    pub fn add_interval(&self, interval: WideInterval) -> SoundResult<WideInterval> {
+        self.checked_mul(&interval).ok_or(Error::Overflow)
+    }
+
+    /// Provides the difference of the intervals (divides their ratios). Returns
+    /// `Error::Overflow` if the result can not be represented by `u64` terms.
+    pub fn sub_interval(&self, interval: WideInterval) -> SoundResult<WideInterval> {
+        self.checked_div(&interval).ok_or(Error::Overflow)
+    }
+
+    /// Provides the numerator (in lowest terms).
+    pub fn get_numerator(&self) -> u64 {
+        self.numerator
+    }
+
+    /// Provides the denominator (in lowest terms).
+    pub fn get_denominator(&self) -> u64 {
+        self.denominator
+    }
+
+    /// Returns the ratio of the frequency interval.
+    pub fn get_ratio(&self) -> f64 {
+        self.numerator as f64 / self.denominator as f64
+    }
+
+    /// Provides the size of the interval in cents (1200 cents is an octave).
+    pub fn get_cents(&self) -> SampleCalc {
+        (1200.0 * ((self.numerator as f64).log2() - (self.denominator as f64).log2())) as
+        SampleCalc
+    }
+
+    /// Provides the prime factorization of the interval. It uses trial division, so it can be
+    /// slow for terms with large prime factors.
+    pub fn get_monzo(&self) -> Monzo {
+        Monzo::from(*self)
+    }
+
+    /// Converts to `Interval`. Returns `Error::Overflow` if the terms can not be represented
+    /// by `IntervalTerm`.
+    pub fn get_interval(&self) -> SoundResult<Interval> {
+        let numerator: IntervalTerm = NumCast::from(self.numerator).ok_or(Error::Overflow)?;
+        let denominator: IntervalTerm = NumCast::from(self.denominator).ok_or(Error::Overflow)?;
+        Interval::new(numerator, denominator)
+    }
+
+    /// True, if the interval is `1:1`, aka. unison.
+    pub fn is_unison(&self) -> bool {
+        self.numerator == self.denominator
+    }
+
+    /// Change a frequency according to the interval.
+    pub fn change_frequency(&self, frequency: SampleCalc) -> SoundResult<SampleCalc> {
+        let new_frequency = (frequency as f64 * self.get_ratio()) as SampleCalc;
+        if new_frequency < TONE_FREQUENCY_MIN {
+            return Err(Error::FrequencyTooLow);
+        };
+        if new_frequency > TONE_FREQUENCY_MAX {
+            return Err(Error::FrequencyTooHigh);
+        };
+        Ok(new_frequency)
+    }
+}
+
+impl From<Interval> for WideInterval {
+    fn from(interval: Interval) -> Self {
+        WideInterval {
+            numerator: interval.get_numerator().into(),
+            denominator: interval.get_denominator().into(),
+        }
+    }
+}
+
+impl Mul for WideInterval {
+    type Output = WideInterval;
+
+    fn mul(self, rhs: WideInterval) -> WideInterval {
+        self.checked_mul(&rhs).expect("interval multiplication overflow")
+    }
+}
+
+impl CheckedMul for WideInterval {
+    fn checked_mul(&self, v: &Self) -> Option<Self> {
+        let (numerator, denominator) =
+            try_opt!(multiply_terms(self.numerator, self.denominator, v.numerator, v.denominator));
+        Some(WideInterval {
+            numerator: numerator,
+            denominator: denominator,
+        })
+    }
+}
+
+impl Div for WideInterval {
+    type Output = WideInterval;
+
+    fn div(self, rhs: WideInterval) -> WideInterval {
+        self.checked_div(&rhs).expect("interval division overflow")
+    }
+}
+
+impl CheckedDiv for WideInterval {
+    fn checked_div(&self, v: &Self) -> Option<Self> {
+        let (numerator, denominator) =
+            try_opt!(multiply_terms(self.numerator, self.denominator, v.denominator, v.numerator));
+        Some(WideInterval {
+            numerator: numerator,
+            denominator: denominator,
+        })
+    }
+}
+
+impl fmt::Display for WideInterval {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.numerator, self.denominator)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use num::*;
+    use sound::*;
+
+    #[test]
+    fn pythagorean_comma() {
+        let fifth = WideInterval::new(3, 2).unwrap();
+        let octave = WideInterval::from(Interval::new(2, 1).unwrap());
+        let mut comma = WideInterval::default();
+        for _ in 0..12 {
+            comma = comma.add_interval(fifth).unwrap();
+        }
+        for _ in 0..7 {
+            comma = comma.sub_interval(octave).unwrap();
+        }
+        assert_eq!(comma, WideInterval::new(531441, 524288).unwrap());
+        assert!((comma.get_cents() - 23.46).abs() < 0.01);
+        assert!(comma.get_interval().is_err());
+        assert_eq!(comma.get_monzo(), Monzo::from_exponents(&[-19, 12]));
+        assert_eq!(fifth * octave / octave, fifth);
+    }
+
+    #[test]
+    fn overflow() {
+        let large = WideInterval::new(u64::max_value(), 1).unwrap();
+        assert!(large.checked_mul(&WideInterval::new(2, 1).unwrap()).is_none());
+        assert!(large.add_interval(WideInterval::new(1, 3).unwrap()).is_ok());
+        assert!(WideInterval::new(0, 1).is_err());
+        assert_eq!(WideInterval::new(6, 4).unwrap().get_interval().unwrap().get_numerator(),
+                   3);
+    }
+}